@@ -0,0 +1,796 @@
+use clap::{Arg, ArgAction};
+use stam::{
+    Annotation, AnnotationBuilder, AnnotationDataBuilder, AnnotationHandle, AnnotationStore,
+    AnnotationStoreBuilder, Configurable, DataOperator, Item, Offset, SelectorBuilder, Storable,
+    Text, TextResourceHandle, WrappedItem,
+};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process::exit;
+
+use crate::compression::{load_annotationset, load_resource, merge_store};
+use crate::concordance::ContextUnit;
+use crate::filter::{DataFilter, FilterOperator};
+use crate::rewrite::{References, StoreJson};
+use crate::tsv::JoinData;
+
+pub fn annotate_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("annotationsets")
+            .long("annotationset")
+            .short('s')
+            .help("STAM JSON file containing an annotation data set. Set value to - for standard input.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("resources")
+            .long("resource")
+            .short('r')
+            .help("Plain text or STAM JSON file containing a text resource. Set value to - for standard input.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("stores")
+            .long("store")
+            .short('i')
+            .help(
+                "STAM JSON or STAM CSV file containing an annotation store, will be merged into the new store. Set value to - for standard input.",
+            )
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("annotations")
+            .long("annotations")
+            .short('a')
+            .help("STAM JSON file containing an array of annotations, will be merged into the new store. Files with extension .jsonl or .ndjson are read as JSON Lines (one annotation per line) in a streaming fashion. Set value to - for standard input, which may be either.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("id")
+            .long("id")
+            .help("Sets the identifier for the annotation store")
+            .takes_value(true),
+    ]
+}
+
+/// Arguments for building composite annotations, only used by `stam annotate`
+pub fn compose_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("compose")
+            .long("compose")
+            .help("Build new annotations that combine existing annotations matching these filters (one annotation per filter, so specify this option multiple times), the syntax is that of --filter in other subcommands (e.g. my_set/type=person). Requires --within to determine which annotations belong together.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("within")
+            .long("within")
+            .help("Combine the annotations selected by --compose only if their text is within the same annotation matching this filter (e.g. my_set/type=sentence). All combinations within such an annotation are made.")
+            .takes_value(true),
+        Arg::with_name("pairs")
+            .long("pairs")
+            .help("TSV file listing the IDs of the annotations to combine into a new annotation, one new annotation per line (lines starting with # are ignored). This is an alternative to --compose and --within.")
+            .takes_value(true),
+        Arg::with_name("data")
+            .long("data")
+            .help("Data to assign to the composite annotations built by --compose or --pairs, the syntax is set/key=value. This option may be specified multiple times.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("multi")
+            .long("multi")
+            .help("Target the combined annotations using a MultiSelector (the annotation applies to each of them individually), rather than a CompositeSelector (the annotation applies to them as a whole)"),
+        Arg::with_name("setdelimiter")
+            .long("setdelimiter")
+            .env("STAM_SETDELIMITER")
+            .help("The delimiter between the annotation set and the key in --compose, --within, --data, --delete, --retain, --window-data, --augment and --with-data")
+            .takes_value(true)
+            .default_value("/"),
+    ]
+}
+
+pub fn delete_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("delete")
+            .long("delete")
+            .help("Delete all annotations matching this filter (set/key, set/key=value, etc), along with all annotations that point to them. This option may be specified multiple times.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("retain")
+            .long("retain")
+            .help("Delete all annotations that do not match this filter (set/key, set/key=value, etc), along with all annotations that point to them. This option may be specified multiple times, annotations matching any of the filters are retained.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("keep-orphan-data")
+            .long("keep-orphan-data")
+            .help("After --delete or --retain, keep annotation data (and keys) that is no longer used by any annotation"),
+        Arg::with_name("keep-orphan-sets")
+            .long("keep-orphan-sets")
+            .help("After --delete or --retain, keep annotation sets that are no longer used by any annotation"),
+        Arg::with_name("keep-orphan-resources")
+            .long("keep-orphan-resources")
+            .help("After --delete or --retain, keep text resources that are no longer referenced by any annotation"),
+    ]
+}
+
+pub fn augment_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("augment")
+            .long("augment")
+            .help("Add data to the existing annotations matching this filter (set/key, set/key=value, etc), rather than creating new annotations. The data is specified with --with-data. This option may be specified multiple times, annotations matching any of the filters are augmented.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("with-data")
+            .long("with-data")
+            .help("Data to add to the annotations selected by --augment, the syntax is set/key=value. This option may be specified multiple times.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("with-data-file")
+            .long("with-data-file")
+            .help("TSV file with data to add to existing annotations, matched by annotation ID. The syntax is file.tsv:column, where column is the name of the header column holding the annotation IDs (default: Id). The file name ends at the first colon, write a colon in it as \\:. All other columns must be custom columns (set/key). If --augment is given, only the selected annotations are augmented. This option may be specified multiple times.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+    ]
+}
+
+pub fn window_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("windows")
+            .long("windows")
+            .help("Add annotations on fixed-size windows over the text of all resources (for instance as chunks for retrieval), each this many units (see --window-unit) long. The last window of a resource may be shorter.")
+            .takes_value(true),
+        Arg::with_name("window-unit")
+            .long("window-unit")
+            .help("The unit of --windows and --window-overlap: characters, or tokens (whitespace separated parts of the text)")
+            .takes_value(true)
+            .possible_values(["chars", "tokens"])
+            .default_value("tokens"),
+        Arg::with_name("window-overlap")
+            .long("window-overlap")
+            .help("The number of units that consecutive windows overlap, must be smaller than the window size")
+            .takes_value(true)
+            .default_value("0"),
+        Arg::with_name("window-data")
+            .long("window-data")
+            .help("The set and key (set/key) for the sequence number of each window (1-indexed, per resource)")
+            .takes_value(true)
+            .default_value("window/n"),
+    ]
+}
+
+/// Adds annotations on fixed-size windows of `size` units over the text of each resource, consecutive windows overlap by
+/// `overlap` units. Each annotation gets the sequence number of the window within its resource (1-indexed) as the value
+/// of `set`/`key`, and an ID derived from the resource ID. Returns the number of annotations added.
+pub fn windows(
+    store: &mut AnnotationStore,
+    size: usize,
+    overlap: usize,
+    unit: ContextUnit,
+    set: &str,
+    key: &str,
+) -> Result<usize, String> {
+    if size == 0 || overlap >= size {
+        return Err(format!(
+            "Invalid window size {} with overlap {}, the size must be larger than the overlap",
+            size, overlap
+        ));
+    }
+    let step = size - overlap;
+    let mut windows: Vec<(TextResourceHandle, String, usize, usize)> = Vec::new();
+    for resource in store.resources() {
+        let resource_id = resource.id().unwrap_or("(none)").to_string();
+        let handle = resource.handle().expect("resource must have a handle");
+        //(begin, end) of each unit, in unicode points
+        let units: Vec<(usize, usize)> = match unit {
+            ContextUnit::Chars => (0..resource.textlen()).map(|i| (i, i + 1)).collect(),
+            ContextUnit::Tokens => {
+                let mut tokens = Vec::new();
+                let mut begin: Option<usize> = None;
+                for (i, c) in resource.text().chars().enumerate() {
+                    match (c.is_whitespace(), begin) {
+                        (true, Some(b)) => {
+                            tokens.push((b, i));
+                            begin = None;
+                        }
+                        (false, None) => begin = Some(i),
+                        _ => {}
+                    }
+                }
+                if let Some(b) = begin {
+                    tokens.push((b, resource.textlen()));
+                }
+                tokens
+            }
+        };
+        let mut first = 0;
+        while first < units.len() {
+            let last = (first + size).min(units.len()) - 1;
+            windows.push((handle, resource_id.clone(), units[first].0, units[last].1));
+            if last == units.len() - 1 {
+                break;
+            }
+            first += step;
+        }
+    }
+    let mut n = 0;
+    let mut prev_resource: Option<String> = None;
+    for (handle, resource_id, begin, end) in windows.iter() {
+        if prev_resource.as_ref() != Some(resource_id) {
+            n = 0;
+            prev_resource = Some(resource_id.clone());
+        }
+        n += 1;
+        store
+            .annotate(
+                AnnotationBuilder::new()
+                    .with_id(format!("{}.window{}", resource_id, n))
+                    .with_target(SelectorBuilder::TextSelector(
+                        Item::Handle(*handle),
+                        Offset::simple(*begin, *end),
+                    ))
+                    .with_data_builder(
+                        AnnotationDataBuilder::new()
+                            .with_annotationset(Item::IdRef(set))
+                            .with_key(Item::IdRef(key))
+                            .with_value(n.into()),
+                    ),
+            )
+            .map_err(|e| format!("Error adding window annotation: {}", e))?;
+    }
+    Ok(windows.len())
+}
+
+/// What was removed by [`delete()`] or [`gc()`]
+#[derive(Clone, Debug, Default)]
+pub struct DeleteReport {
+    /// IDs of the deleted annotations
+    pub annotations: Vec<String>,
+    /// Number of orphaned annotation data removed
+    pub data: usize,
+    /// Number of data keys removed because no data uses them anymore
+    pub keys: usize,
+    /// IDs of orphaned annotation sets removed
+    pub sets: Vec<String>,
+    /// IDs of orphaned resources removed
+    pub resources: Vec<String>,
+    /// Changed sets that keep their original standoff file, as (ID, filename); these files must be rewritten
+    pub setfiles: Vec<(String, String)>,
+}
+
+impl DeleteReport {
+    /// Returns true if nothing was removed
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty()
+            && self.data == 0
+            && self.keys == 0
+            && self.sets.is_empty()
+            && self.resources.is_empty()
+    }
+}
+
+/// Deletes the annotations matching any of the `delete` filters, and, if `retain` filters are passed, all annotations
+/// not matching any of those. Annotations pointing to deleted annotations are deleted as well. Afterwards, data, sets and
+/// resources that were used by annotations before, but no longer are, are removed unless they are to be kept.
+///
+/// Returns the new store and a report.
+pub fn delete(
+    store: &AnnotationStore,
+    delete: &[DataFilter],
+    retain: &[DataFilter],
+    keep_orphan_data: bool,
+    keep_orphan_sets: bool,
+    keep_orphan_resources: bool,
+) -> Result<(AnnotationStore, DeleteReport), String> {
+    let mut ids = Vec::new();
+    for annotation in store.annotations() {
+        if delete.iter().any(|filter| filter.test(&annotation))
+            || (!retain.is_empty() && !retain.iter().any(|filter| filter.test(&annotation)))
+        {
+            match annotation.id() {
+                Some(id) => ids.push(id.to_string()),
+                None => eprintln!("Warning: can not delete an annotation without an ID"),
+            }
+        }
+    }
+    let mut storejson = StoreJson::from_store(store)?;
+    let before = storejson.references();
+    let mut report = DeleteReport {
+        annotations: storejson.remove_annotations(&ids),
+        ..Default::default()
+    };
+    prune(
+        &mut storejson,
+        Some(&before),
+        keep_orphan_data,
+        keep_orphan_sets,
+        keep_orphan_resources,
+        &mut report,
+    );
+    Ok((storejson.into_store(store)?, report))
+}
+
+/// Removes the annotation data, data keys, annotation sets and resources that no annotation refers to (garbage collection).
+/// Data without a public ID, and keys used by such data, are always kept.
+///
+/// Returns the new store and a report.
+pub fn gc(store: &AnnotationStore) -> Result<(AnnotationStore, DeleteReport), String> {
+    let mut storejson = StoreJson::from_store(store)?;
+    let mut report = DeleteReport::default();
+    prune(&mut storejson, None, false, false, false, &mut report);
+    Ok((storejson.into_store(store)?, report))
+}
+
+/// Removes data, keys, sets and resources that annotations do not refer to. If `before` is passed, only those that
+/// were referred to before are removed, i.e. those orphaned by a deletion.
+fn prune(
+    storejson: &mut StoreJson,
+    before: Option<&References>,
+    keep_orphan_data: bool,
+    keep_orphan_sets: bool,
+    keep_orphan_resources: bool,
+    report: &mut DeleteReport,
+) {
+    let after = storejson.references();
+
+    let mut changedsets = Vec::new();
+    if !keep_orphan_data {
+        for annotationset in storejson.annotationsets.iter_mut() {
+            let set_id = match annotationset.get("@id").and_then(|x| x.as_str()) {
+                Some(set_id) => set_id.to_string(),
+                None => continue,
+            };
+            let orphan = |data: &serde_json::Value| match data.get("@id").and_then(|x| x.as_str()) {
+                Some(id) => {
+                    let key = (set_id.clone(), id.to_string());
+                    before
+                        .map(|before| before.data.contains(&key))
+                        .unwrap_or(true)
+                        && !after.data.contains(&key)
+                }
+                None => false,
+            };
+            let mut removed = 0;
+            if let Some(serde_json::Value::Array(data)) = annotationset.get_mut("data") {
+                let count = data.len();
+                data.retain(|data| !orphan(data));
+                removed = count - data.len();
+            }
+            //remove keys that are no longer used by any data (or, when collecting garbage, that never were)
+            let mut removedkeys = 0;
+            if removed > 0 || before.is_none() {
+                let usedkeys: BTreeSet<String> = match annotationset.get("data") {
+                    Some(serde_json::Value::Array(data)) => data
+                        .iter()
+                        .filter_map(|data| data.get("key").and_then(|x| x.as_str()))
+                        .map(|key| key.to_string())
+                        .collect(),
+                    _ => BTreeSet::new(),
+                };
+                if let Some(serde_json::Value::Array(keys)) = annotationset.get_mut("keys") {
+                    let count = keys.len();
+                    keys.retain(|key| match key.get("@id").and_then(|x| x.as_str()) {
+                        Some(key) => usedkeys.contains(key),
+                        None => true,
+                    });
+                    removedkeys = count - keys.len();
+                }
+            }
+            if removed > 0 || removedkeys > 0 {
+                report.data += removed;
+                report.keys += removedkeys;
+                changedsets.push(set_id);
+            }
+        }
+    }
+    if !keep_orphan_sets {
+        storejson.annotationsets.retain(|annotationset| {
+            match annotationset.get("@id").and_then(|x| x.as_str()) {
+                Some(set_id)
+                    if before
+                        .map(|before| before.sets.contains(set_id))
+                        .unwrap_or(true)
+                        && !after.sets.contains(set_id) =>
+                {
+                    report.sets.push(set_id.to_string());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+    if !keep_orphan_resources {
+        storejson.resources.retain(
+            |resource| match resource.get("@id").and_then(|x| x.as_str()) {
+                Some(resource_id)
+                    if before
+                        .map(|before| before.resources.contains(resource_id))
+                        .unwrap_or(true)
+                        && !after.resources.contains(resource_id) =>
+                {
+                    report.resources.push(resource_id.to_string());
+                    false
+                }
+                _ => true,
+            },
+        );
+    }
+
+    //changed sets keep their original standoff file, which must be rewritten
+    for set_id in changedsets {
+        if report.sets.contains(&set_id) {
+            continue;
+        }
+        if let Some(filename) = storejson
+            .annotationset_filename(&set_id)
+            .map(|x| x.to_string())
+        {
+            storejson.set_annotationset_filename(&set_id, &filename);
+            report.setfiles.push((set_id, filename));
+        }
+    }
+}
+
+pub fn annotate(
+    mut store: AnnotationStore,
+    resourcefiles: &[&str],
+    setfiles: &[&str],
+    storefiles: &[&str],
+    annotationfiles: &[&str],
+) -> AnnotationStore {
+    for filename in storefiles {
+        store = merge_store(store, filename).unwrap_or_else(|err| {
+            eprintln!("Error merging annotation store {}: {}", filename, err);
+            exit(1);
+        });
+    }
+    let mut builder = AnnotationStoreBuilder::default();
+    for filename in setfiles {
+        builder = builder.with_annotationset(
+            load_annotationset(filename, store.config().clone()).unwrap_or_else(|err| {
+                eprintln!("Error loading AnnotationDataSet {}: {}", filename, err);
+                exit(1);
+            }),
+        );
+    }
+    for filename in resourcefiles {
+        builder = builder.with_resource(
+            load_resource(filename, store.config().clone()).unwrap_or_else(|err| {
+                eprintln!("Error loading TextResource {}: {}", filename, err);
+                exit(1);
+            }),
+        );
+    }
+    store.merge_from_builder(builder).unwrap_or_else(|err| {
+        eprintln!("Error annotating: {}", err);
+        exit(1);
+    });
+    for filename in annotationfiles {
+        if *filename == "-" {
+            annotate_from_stdin(&mut store).unwrap_or_else(|err| {
+                eprintln!("Error parsing annotations from standard input: {}", err);
+                exit(1);
+            });
+        } else if filename.ends_with(".jsonl") || filename.ends_with(".ndjson") {
+            let file = File::open(filename).unwrap_or_else(|err| {
+                eprintln!("Error opening {}: {}", filename, err);
+                exit(1);
+            });
+            annotate_from_jsonl(&mut store, BufReader::new(file)).unwrap_or_else(|err| {
+                eprintln!("Error parsing annotations from {}: {}", filename, err);
+                exit(1);
+            });
+        } else {
+            store.annotate_from_file(filename).unwrap_or_else(|err| {
+                eprintln!("Error parsing annotations from {}: {}", filename, err);
+                exit(1);
+            });
+        }
+    }
+    store
+}
+
+/// Reads annotations from standard input, either as a JSON array or, if the input does not start with `[`, as JSON Lines
+fn annotate_from_stdin(store: &mut AnnotationStore) -> Result<usize, String> {
+    let mut reader = io::stdin().lock();
+    let is_array = loop {
+        let buffer = reader.fill_buf().map_err(|err| format!("{}", err))?;
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        if let Some(pos) = buffer.iter().position(|c| !c.is_ascii_whitespace()) {
+            break buffer[pos] == b'[';
+        }
+        let len = buffer.len();
+        reader.consume(len);
+    };
+    if is_array {
+        let annotations: Vec<AnnotationBuilder> =
+            serde_json::from_reader(reader).map_err(|err| format!("{}", err))?;
+        let count = annotations.len();
+        for annotation in annotations {
+            store
+                .annotate(annotation)
+                .map_err(|err| format!("{}", err))?;
+        }
+        Ok(count)
+    } else {
+        annotate_from_jsonl(store, reader)
+    }
+}
+
+/// Reads annotations in JSON Lines format (one annotation in STAM JSON per line) and adds them to the store one by one, so the input need not fit in memory.
+/// Empty lines are skipped. Returns the number of annotations added.
+pub fn annotate_from_jsonl(
+    store: &mut AnnotationStore,
+    reader: impl BufRead,
+) -> Result<usize, String> {
+    let mut count = 0;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| format!("{}", err))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let annotation: AnnotationBuilder = serde_json::from_str(&line)
+            .map_err(|err| format!("Invalid annotation on line {}: {}", i + 1, err))?;
+        store
+            .annotate(annotation)
+            .map_err(|err| format!("Line {}: {}", i + 1, err))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Is the text of the annotation entirely within the text of the scope annotation?
+fn is_within(annotation: &WrappedItem<Annotation>, scope: &WrappedItem<Annotation>) -> bool {
+    let scope: Vec<_> = scope.textselections().collect();
+    let mut found = false;
+    for textselection in annotation.textselections() {
+        found = true;
+        if !scope.iter().any(|scope| {
+            scope.resource().handle() == textselection.resource().handle()
+                && scope.begin() <= textselection.begin()
+                && textselection.end() <= scope.end()
+        }) {
+            return false;
+        }
+    }
+    found
+}
+
+/// Builds new annotations that combine existing ones, targeting them via a CompositeSelector (or a MultiSelector if `multi` is set).
+/// The annotations to combine are either listed explicitly by ID in a TSV file (`pairs`), or for each annotation matching
+/// the `within` filter, all combinations of annotations (one per filter in `compose`) whose text is within it are made.
+/// Each combination is made only once, regardless of the order of its annotations or the number of scope annotations it is
+/// within. Each new annotation gets the specified data (`set/key=value`, `setdelimiter` is only used in error messages).
+/// Returns the number of annotations added.
+pub fn compose(
+    store: &mut AnnotationStore,
+    compose: &[DataFilter],
+    within: Option<&DataFilter>,
+    pairs: Option<&str>,
+    data: &[DataFilter],
+    multi: bool,
+    setdelimiter: &str,
+) -> Result<usize, String> {
+    if data.is_empty() {
+        return Err("Please specify the data for the new annotations (--data)".to_string());
+    }
+    for filter in data {
+        if filter.op != FilterOperator::Equals {
+            return Err(format!(
+                "Invalid data for new annotations: {}{}{}, expected set{}key=value",
+                filter.set, setdelimiter, filter.key, setdelimiter
+            ));
+        }
+    }
+    let mut combinations: Vec<Vec<AnnotationHandle>> = Vec::new();
+    if let Some(filename) = pairs {
+        let f = File::open(filename).map_err(|e| format!("Error opening {}: {}", filename, e))?;
+        for (i, line) in BufReader::new(f).lines().enumerate() {
+            let line = line.map_err(|e| format!("Error reading {}: {}", filename, e))?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let combination = line
+                .split('\t')
+                .filter(|id| !id.is_empty())
+                .map(|id| {
+                    store
+                        .annotation(&Item::IdRef(id))
+                        .and_then(|annotation| annotation.handle())
+                        .ok_or_else(|| {
+                            format!("{} line {}: annotation {} not found", filename, i + 1, id)
+                        })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            combinations.push(combination);
+        }
+    } else if let Some(within) = within {
+        if compose.is_empty() {
+            return Err("Please specify which annotations to combine (--compose)".to_string());
+        }
+        //combinations made so far, with their handles sorted
+        let mut seen: HashSet<Vec<AnnotationHandle>> = HashSet::new();
+        for scope in store
+            .annotations()
+            .filter(|annotation| within.test(annotation))
+        {
+            //the candidates for each position in the combination
+            let candidates: Vec<Vec<AnnotationHandle>> = compose
+                .iter()
+                .map(|filter| {
+                    store
+                        .annotations()
+                        .filter(|annotation| {
+                            filter.test(annotation) && is_within(annotation, &scope)
+                        })
+                        .filter_map(|annotation| annotation.handle())
+                        .collect()
+                })
+                .collect();
+            //cartesian product
+            let mut partial: Vec<Vec<AnnotationHandle>> = vec![Vec::new()];
+            for candidates in candidates.iter() {
+                partial = partial
+                    .into_iter()
+                    .flat_map(|combination| {
+                        candidates
+                            .iter()
+                            .filter(|handle| !combination.contains(handle))
+                            .map(|handle| {
+                                let mut combination = combination.clone();
+                                combination.push(*handle);
+                                combination
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+            }
+            for combination in partial {
+                let mut key = combination.clone();
+                key.sort();
+                if seen.insert(key) {
+                    combinations.push(combination);
+                }
+            }
+        }
+    } else {
+        return Err("Please specify how to combine annotations (--within or --pairs)".to_string());
+    }
+    let count = combinations.len();
+    for combination in combinations {
+        let selectors: Vec<SelectorBuilder> = combination
+            .into_iter()
+            .map(|handle| SelectorBuilder::AnnotationSelector(Item::Handle(handle), None))
+            .collect();
+        let mut builder = AnnotationBuilder::new().with_target(if multi {
+            SelectorBuilder::MultiSelector(selectors)
+        } else {
+            SelectorBuilder::CompositeSelector(selectors)
+        });
+        for filter in data {
+            builder = builder.with_data_builder(
+                AnnotationDataBuilder::new()
+                    .with_annotationset(Item::Id(filter.set.clone()))
+                    .with_key(Item::Id(filter.key.clone()))
+                    .with_value(filter.value.clone().unwrap_or_default().into()),
+            );
+        }
+        store.annotate(builder).map_err(|e| format!("{}", e))?;
+    }
+    Ok(count)
+}
+
+/// What was added by [`augment()`]
+#[derive(Clone, Debug, Default)]
+pub struct AugmentReport {
+    /// Number of annotations that received data
+    pub annotations: usize,
+    /// Number of annotation data added (data that an annotation already had is not counted)
+    pub data: usize,
+    /// IDs in the joined data that did not match any (selected) annotation
+    pub unmatched: Vec<String>,
+    /// Changed sets that keep their original standoff file, as (ID, filename); these files must be rewritten
+    pub setfiles: Vec<(String, String)>,
+}
+
+/// Adds data to existing annotations: the data in `data` (`set/key=value`) to all annotations matching any of the
+/// `filters`, and the joined data to the annotations with the corresponding IDs (restricted to the annotations matching
+/// the filters, if any). Data an annotation already has is not added again.
+///
+/// Returns the new store (`None` if no annotations were affected) and a report. `setdelimiter` is only used in
+/// error messages.
+pub fn augment(
+    store: &AnnotationStore,
+    filters: &[DataFilter],
+    data: &[DataFilter],
+    joins: &mut JoinData,
+    setdelimiter: &str,
+) -> Result<(Option<AnnotationStore>, AugmentReport), String> {
+    for filter in data {
+        if filter.op != FilterOperator::Equals {
+            return Err(format!(
+                "Invalid data to add: {}{}{}, expected set{}key=value",
+                filter.set, setdelimiter, filter.key, setdelimiter
+            ));
+        }
+    }
+    if !data.is_empty() && filters.is_empty() {
+        return Err("Please specify which annotations to add the data to (--augment)".to_string());
+    }
+    //the new data per annotation ID
+    let mut newdata: HashMap<String, Vec<(String, String, String)>> = HashMap::new();
+    for annotation in store.annotations() {
+        let id = match annotation.id() {
+            Some(id) => id,
+            None => continue,
+        };
+        let selected = filters.iter().any(|filter| filter.test(&annotation));
+        let mut candidates: Vec<(String, String, String)> = if selected {
+            data.iter()
+                .map(|filter| {
+                    (
+                        filter.set.clone(),
+                        filter.key.clone(),
+                        filter.value.clone().unwrap_or_default(),
+                    )
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if selected || filters.is_empty() {
+            candidates.extend(joins.take_raw(id));
+        }
+        candidates.retain(|(set, key, value)| {
+            !annotation.test_data(
+                Some(Item::IdRef(set.as_str())),
+                Some(Item::IdRef(key.as_str())),
+                DataOperator::Equals(value.as_str()),
+            )
+        });
+        candidates.dedup();
+        if !candidates.is_empty() {
+            newdata.insert(id.to_string(), candidates);
+        }
+    }
+    let mut report = AugmentReport {
+        unmatched: joins.ids().map(|id| id.to_string()).collect(),
+        ..Default::default()
+    };
+    report.unmatched.sort();
+    if newdata.is_empty() {
+        return Ok((None, report));
+    }
+
+    let mut storejson = StoreJson::from_store(store)?;
+    let mut changedsets = BTreeSet::new();
+    for annotation in storejson.annotations.iter_mut() {
+        let newdata = match annotation
+            .get("@id")
+            .and_then(|x| x.as_str())
+            .and_then(|id| newdata.remove(id))
+        {
+            Some(newdata) => newdata,
+            None => continue,
+        };
+        if let Some(serde_json::Value::Array(data)) = annotation.get_mut("data") {
+            report.annotations += 1;
+            for (set, key, value) in newdata {
+                data.push(serde_json::json!({
+                    "@type": "AnnotationData",
+                    "set": set,
+                    "key": key,
+                    "value": { "@type": "String", "value": value }
+                }));
+                report.data += 1;
+                changedsets.insert(set);
+            }
+        }
+    }
+    //changed sets keep their original standoff file, which must be rewritten
+    for set_id in changedsets {
+        if let Some(filename) = storejson
+            .annotationset_filename(&set_id)
+            .map(|x| x.to_string())
+        {
+            storejson.set_annotationset_filename(&set_id, &filename);
+            report.setfiles.push((set_id, filename));
+        }
+    }
+    Ok((Some(storejson.into_store(store)?), report))
+}
@@ -0,0 +1,131 @@
+use stam::{AnnotationStore, Storable, Text};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::filter::DataFilter;
+use crate::rewrite::{
+    add_suffix, apply_edits, remap_relative_selector, remap_selector, StoreJson, TextEdit,
+};
+
+/// A sensitive span in a text, along with the data that marked it as sensitive
+struct Span {
+    begin: usize,
+    end: usize,
+    set: String,
+    key: String,
+    value: String,
+}
+
+/// Replaces all text spans covered by annotations matching any of the filters with a placeholder
+/// derived from `template`, in which `{value}`, `{key}`, `{set}` and `{n}` are substituted. `{n}`
+/// is a number that is consistent for the same text and value (1-indexed), so it can be used for pseudonymisation.
+///
+/// Each changed resource gets a new ID and filename by adding `suffix`. All annotations are carried over to the new resources, with their offsets
+/// adapted to the new text; the sensitive annotations themselves will cover the placeholders.
+/// Returns the new store and a list of new resource IDs with the filenames they still need to be written to.
+pub fn anonymize(
+    store: &AnnotationStore,
+    filters: &[DataFilter],
+    template: &str,
+    suffix: &str,
+    verbose: bool,
+) -> Result<(AnnotationStore, Vec<(String, String)>), String> {
+    //collect sensitive spans per resource
+    let mut spans: BTreeMap<String, Vec<Span>> = BTreeMap::new();
+    for annotation in store.annotations() {
+        if let Some(filter) = filters.iter().find(|filter| filter.test(&annotation)) {
+            let value = filter.value_of(&annotation).unwrap_or_default();
+            for textselection in annotation.textselections() {
+                let resource_id = textselection
+                    .resource()
+                    .id()
+                    .ok_or("resource must have an ID")?;
+                spans
+                    .entry(resource_id.to_string())
+                    .or_default()
+                    .push(Span {
+                        begin: textselection.begin(),
+                        end: textselection.end(),
+                        set: filter.set.clone(),
+                        key: filter.key.clone(),
+                        value: value.clone(),
+                    });
+            }
+        }
+    }
+
+    let mut storejson = StoreJson::from_store(store)?;
+    let mut pseudonyms: HashMap<(String, String), usize> = HashMap::new();
+    let mut counters: HashMap<String, usize> = HashMap::new();
+    let mut newresources = Vec::new();
+    for (resource_id, mut spans) in spans {
+        let resource = store
+            .resource(&stam::Item::IdRef(resource_id.as_str()))
+            .expect("resource must exist");
+        let chars: Vec<char> = resource.text().chars().collect();
+        //sort and merge overlapping spans, the first span determines the placeholder
+        spans.sort_by_key(|span| (span.begin, std::cmp::Reverse(span.end)));
+        let mut merged: Vec<Span> = Vec::new();
+        for span in spans {
+            match merged.last_mut() {
+                Some(last) if span.begin < last.end => last.end = last.end.max(span.end),
+                _ => merged.push(span),
+            }
+        }
+        let edits: Vec<TextEdit> = merged
+            .iter()
+            .map(|span| {
+                let text: String = chars[span.begin..span.end].iter().collect();
+                let n = *pseudonyms
+                    .entry((span.value.clone(), text.clone()))
+                    .or_insert_with(|| {
+                        let counter = counters.entry(span.value.clone()).or_insert(0);
+                        *counter += 1;
+                        *counter
+                    });
+                if verbose {
+                    eprintln!(
+                        "{}\t{}\t{}\t{}",
+                        resource_id, span.begin, span.end, span.value
+                    );
+                }
+                TextEdit {
+                    begin: span.begin,
+                    end: span.end,
+                    replacement: template
+                        .replace("{value}", &span.value)
+                        .replace("{key}", &span.key)
+                        .replace("{set}", &span.set)
+                        .replace("{n}", &n.to_string()),
+                }
+            })
+            .collect();
+        eprintln!(
+            "Replacing {} span(s) in resource {}",
+            edits.len(),
+            resource_id
+        );
+        let (newtext, map) = apply_edits(resource.text(), &edits);
+        let new_id = add_suffix(&resource_id, suffix);
+        for value in storejson.resources.iter_mut() {
+            if value.get("@id").and_then(|x| x.as_str()) == Some(resource_id.as_str()) {
+                value["@id"] = new_id.as_str().into();
+                value["text"] = newtext.as_str().into();
+            }
+        }
+        for annotation in storejson.annotations.iter_mut() {
+            if let Some(target) = annotation.get_mut("target") {
+                remap_relative_selector(target, store, &resource_id, &map);
+                remap_selector(target, &resource_id, &new_id, &map);
+            }
+        }
+        let filename = storejson
+            .resource_filename(&resource_id)
+            .map(|filename| add_suffix(filename, suffix));
+        if let Some(filename) = filename {
+            storejson.set_resource_filename(&new_id, &filename);
+            newresources.push((new_id, filename));
+        }
+    }
+    let newstore = storejson.into_store(store)?;
+    Ok((newstore, newresources))
+}
@@ -1,58 +1,78 @@
 use clap::{App, Arg, ArgAction, ArgMatches, SubCommand};
-use stam::{AnnotationStore, AssociatedFile, Config, Configurable};
+use stam::{AnnotationStore, AssociatedFile, Config, Configurable, Item, Regex, Text};
+use stamtools::annotate::*;
+use stamtools::anonymize::*;
+use stamtools::brat::*;
+use stamtools::compression;
+use stamtools::compression::save_store;
+use stamtools::concordance::*;
+use stamtools::coverage::*;
+use stamtools::defaults::{load_config_defaults, unused_config_vars};
+use stamtools::diff::*;
+use stamtools::embed::*;
+use stamtools::filter::*;
+use stamtools::fixtext::*;
+use stamtools::info::*;
+use stamtools::lexicon::*;
+use stamtools::ngram::*;
+use stamtools::normalize::Normalizer;
+use stamtools::pipeline::*;
+use stamtools::rename::*;
+use stamtools::resolve::*;
+use stamtools::rewrite::{write_annotationset, write_resource};
+use stamtools::sort::*;
+use stamtools::tag::*;
+use stamtools::timealigned::*;
+use stamtools::to_text::*;
+use stamtools::tsv::*;
+use stamtools::validate::*;
+use std::fs::File;
 use std::path::Path;
 use std::process::exit;
 
-mod annotate;
-mod info;
-mod tag;
-mod to_text;
-mod tsv;
-mod validate;
-
-use crate::annotate::*;
-use crate::info::*;
-use crate::tag::*;
-use crate::to_text::*;
-use crate::tsv::*;
-use crate::validate::*;
-
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 fn common_arguments<'a>() -> Vec<clap::Arg<'a>> {
-    let mut args: Vec<Arg> = Vec::new();
-    args.push(
+    vec![
         Arg::with_name("verbose")
             .long("verbose")
             .short('V')
             .help("Produce verbose output")
             .required(false),
-    );
-    args.push(
         Arg::with_name("dry-run")
             .long("dry-run")
             .help("Dry run, do not write changes to file")
             .required(false),
-    );
-    args
+        Arg::with_name("progress")
+            .long("progress")
+            .env("STAM_PROGRESS")
+            .help("Show a progress indicator with an estimated time of arrival on standard error for long operations (currently import and tag)")
+            .required(false),
+        Arg::with_name("utf8")
+            .long("utf8")
+            .env("STAM_UTF8")
+            .help("Offsets are in UTF-8 bytes rather than unicode points, both those read (print --offsets-file and the offset columns of import) and those written (the offset columns of export)")
+            .required(false),
+        Arg::with_name("codepoints")
+            .long("codepoints")
+            .help("Offsets are in unicode points (the default). Overrides --utf8 when that is set via STAM_UTF8 or the configuration file")
+            .required(false),
+    ]
 }
 
 fn store_argument<'a>() -> Vec<clap::Arg<'a>> {
-    let mut args: Vec<Arg> = Vec::new();
-    args.push(
+    vec![
         Arg::with_name("annotationstore")
             .help(
                 "Input and output file for the annotation store (will be overwritten if it already exists!). Set to - for standard input/output. Note that for 'stam init', this is only used as output.",
             )
             .takes_value(true)
             .required(true),
-    );
-    args
+    ]
 }
 
 fn multi_store_arguments<'a>(required: bool) -> Vec<clap::Arg<'a>> {
-    let mut args: Vec<Arg> = Vec::new();
-    args.push(
+    vec![
         Arg::with_name("annotationstore")
             .help(
                 "Input file containing an annotation store in STAM JSON or STAM CSV. Set value to - for standard input. Multiple are allowed.",
@@ -60,27 +80,70 @@ fn multi_store_arguments<'a>(required: bool) -> Vec<clap::Arg<'a>> {
             .takes_value(true)
             .required(required)
             .action(ArgAction::Append),
-    );
-    args
+    ]
+}
+
+fn filter_arguments<'a>(required: bool) -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("filter")
+            .long("filter")
+            .short('f')
+            .help(
+                "Select annotations by their data. The syntax is set/key, set/key=value, set/key!=value, or set/key followed by >, >=, < or <= and a number. Multiple are allowed, an annotation is selected if it matches any of them.",
+            )
+            .takes_value(true)
+            .required(required)
+            .action(ArgAction::Append),
+        Arg::with_name("setdelimiter")
+            .long("setdelimiter")
+            .env("STAM_SETDELIMITER")
+            .help("The delimiter between the annotation set and the key in filters")
+            .takes_value(true)
+            .default_value("/"),
+    ]
+}
+
+fn filters_from_args(args: &ArgMatches) -> Vec<DataFilter> {
+    parse_filters(
+        args.values_of("filter").unwrap_or_default(),
+        args.value_of("setdelimiter").unwrap_or("/"),
+    )
+    .unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(1);
+    })
+}
+
+/// Are offsets in UTF-8 bytes rather than unicode points?
+fn utf8_from_args(args: &ArgMatches) -> bool {
+    args.is_present("utf8") && !args.is_present("codepoints")
+}
+
+fn dialect_from_args(args: &ArgMatches) -> Dialect {
+    Dialect::new(
+        args.value_of("delimiter").unwrap(),
+        args.is_present("quoted"),
+    )
+    .unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(1);
+    })
 }
 
 fn config_arguments<'a>() -> Vec<clap::Arg<'a>> {
-    let mut args: Vec<Arg> = Vec::new();
-    args.push(
+    vec![
         Arg::with_name("debug")
             .long("debug")
             .short('d')
             .help("Set debug mode for the underlying library")
             .required(false),
-    );
-    args.push(
         Arg::with_name("no-include")
             .long("no-include")
+            .env("STAM_NO_INCLUDE")
             .short('I')
             .help("Serialize as one file, do not output @include directives nor standoff-files")
             .required(false),
-    );
-    args
+    ]
 }
 
 /// Translate command line arguments to stam library's configuration structure
@@ -94,37 +157,44 @@ fn load_store(args: &ArgMatches) -> AnnotationStore {
     let filename = args
         .value_of("annotationstore")
         .expect("an annotation store must be provided");
-    AnnotationStore::from_file(filename, config_from_args(args)).unwrap_or_else(|err| {
+    compression::load_store(filename, config_from_args(args)).unwrap_or_else(|err| {
         eprintln!("Error loading annotation store: {}", err);
         exit(1);
     })
 }
 
 fn main() {
-    let rootargs = App::new("STAM Tools")
+    //defaults from the configuration file are passed via environment variables, which must be set before the arguments are defined
+    let configvars = load_config_defaults().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        exit(1);
+    });
+    let app = App::new("STAM Tools")
         .version(VERSION)
         .author("Maarten van Gompel (proycon) <proycon@anaproy.nl>")
         .about("CLI tool to work with standoff text annotation (STAM)")
         .subcommand(
             SubCommand::with_name("info")
-                .about("Return information regarding a STAM model. Set --verbose for extra details.")
-                .args(&common_arguments())
-                .args(&multi_store_arguments(true))
-                .args(&config_arguments()),
+                .about("Return information regarding a STAM model. Set --verbose for extra details and statistics, use --filter to restrict these to certain annotations.")
+                .args(common_arguments())
+                .args(multi_store_arguments(true))
+                .args(config_arguments())
+                .args(filter_arguments(false)),
         )
         .subcommand(
             SubCommand::with_name("validate")
-                .about("Validate a STAM model. Set --verbose to have it output the STAM JSON or STAM CSV to standard output.")
-                .args(&common_arguments())
-                .args(&multi_store_arguments(true))
-                .args(&config_arguments()),
+                .about("Validate a STAM model. Set --verbose to have it output the STAM JSON or STAM CSV to standard output. Use --schema to also check the annotation data against constraints, --disallow-overlap or --allow-nesting to detect overlapping annotations, and --make-manifest or --verify-manifest to record or check the checksums of all files.")
+                .args(common_arguments())
+                .args(multi_store_arguments(true))
+                .args(config_arguments())
+                .args(validate_arguments()),
         )
         .subcommand(
             SubCommand::with_name("save")
                 .about("Save the annotation store and all underlying files to the the specified location and data format (detected by extension).")
-                .args(&common_arguments())
-                .args(&store_argument())
-                .args(&config_arguments())
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments())
                 .arg(
                     Arg::with_name("outputfile")
                         .long("outputfile")
@@ -141,25 +211,72 @@ fn main() {
         .subcommand(
             SubCommand::with_name("export")
                 .about("Export annotations (or other data structures) as tabular data to a TSV format. If --verbose is set, a tree-like structure is expressed in which the order of rows matters.")
-                .args(&common_arguments())
-                .args(&multi_store_arguments(true))
-                .args(&config_arguments())
-                .args(&tsv_arguments_out()),
+                .args(common_arguments())
+                .args(multi_store_arguments(true))
+                .args(config_arguments())
+                .args(tsv_arguments_out())
+                .args(brat_arguments())
+                .args(subtitle_arguments()),
+        )
+        .subcommand(
+            SubCommand::with_name("concordance")
+                .about("Output a keyword-in-context (KWIC) listing for all matches of a regular expression (--regex), or for all annotations selected by --filter.")
+                .args(common_arguments())
+                .args(multi_store_arguments(true))
+                .args(config_arguments())
+                .args(filter_arguments(false))
+                .args(concordance_arguments()),
+        )
+        .subcommand(
+            SubCommand::with_name("ngram")
+                .about("Output an n-gram frequency list (TSV) of the text, or of the tokens selected by --filter.")
+                .args(common_arguments())
+                .args(multi_store_arguments(true))
+                .args(config_arguments())
+                .args(filter_arguments(false))
+                .args(ngram_arguments()),
+        )
+        .subcommand(
+            SubCommand::with_name("lexicon")
+                .about("Output a lexicon (TSV or JSON) built from annotations: for each label (the value of --key, e.g. a lemma), the attested surface forms with their frequencies and example contexts. With --reverse, the labels for each surface form.")
+                .args(common_arguments())
+                .args(multi_store_arguments(true))
+                .args(config_arguments())
+                .args(filter_arguments(false))
+                .args(lexicon_arguments()),
+        )
+        .subcommand(
+            SubCommand::with_name("coverage")
+                .about("Report how much of the text of each resource is covered by the annotations selected by --filter (e.g. tokens), along with the number of gaps and overlaps (TSV). Use --list to list the gaps or overlaps themselves, and --annotate-gaps to add annotations for the gaps.")
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments())
+                .args(filter_arguments(true))
+                .args(coverage_arguments()),
+        )
+        .subcommand(
+            SubCommand::with_name("embed")
+                .about("Export text chunks as JSON Lines for embedding and vector database pipelines: one record with the text, offsets and metadata for each annotation selected by --filter, or for each resource if there is no filter.")
+                .args(common_arguments())
+                .args(multi_store_arguments(true))
+                .args(config_arguments())
+                .args(filter_arguments(false))
+                .args(embed_arguments()),
         )
         .subcommand(
             SubCommand::with_name("import")
                 .about("Import annotations from a TSV format.")
-                .args(&common_arguments())
-                .args(&store_argument())
-                .args(&config_arguments())
-                .args(&tsv_arguments_in()),
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments())
+                .args(tsv_arguments_in()),
         )
         .subcommand(
             SubCommand::with_name("print")
-                .about("Output the plain text of one or more resource(s). Requires --resource")
-                .args(&common_arguments())
-                .args(&multi_store_arguments(true))
-                .args(&config_arguments())
+                .about("Output the plain text of one or more resource(s), or parts thereof. Requires --resource or --offsets-file")
+                .args(common_arguments())
+                .args(multi_store_arguments(true))
+                .args(config_arguments())
                 .arg(
                     Arg::with_name("resource")
                         .long("resource")
@@ -168,39 +285,61 @@ fn main() {
                             "The resource ID (not necessarily the filename!) of the text to output",
                         )
                         .takes_value(true)
-                        .required(true)
+                        .required_unless_present("offsets-file")
                         .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::with_name("offsets-file")
+                        .long("offsets-file")
+                        .help("TSV file with the spans to output, one per line: resource ID, begin offset, end offset and optionally an identifier. Negative offsets count from the end of the text. Use - to read from stdin.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("separator")
+                        .long("separator")
+                        .help("The separator to output after each span from --offsets-file (\\n and \\t are interpreted)")
+                        .takes_value(true)
+                        .default_value("\\n"),
+                )
+                .arg(
+                    Arg::with_name("with-id")
+                        .long("with-id")
+                        .help("Precede each span from --offsets-file by its identifier (resource#begin-end if none was given) and a tab"),
                 ),
         )
         .subcommand(
             SubCommand::with_name("init")
                 .about("Initialize a new stam annotationstore")
-                .args(&common_arguments())
-                .args(&store_argument())
-                .args(&annotate_arguments())
-                .args(&config_arguments()),
+                .args(common_arguments())
+                .args(store_argument())
+                .args(annotate_arguments())
+                .args(config_arguments()),
         )
         .subcommand(
             SubCommand::with_name("annotate")
-                .about("Add annotations (or datasets, resources) to an existing annotationstore")
-                .args(&annotate_arguments())
-                .args(&store_argument())
-                .args(&common_arguments())
-                .args(&config_arguments()),
+                .about("Add annotations (or datasets, resources) to an existing annotationstore. Use --compose or --pairs to add annotations that combine existing annotations, --delete or --retain to remove annotations.")
+                .args(annotate_arguments())
+                .args(compose_arguments())
+                .args(delete_arguments())
+                .args(augment_arguments())
+                .args(window_arguments())
+                .args(store_argument())
+                .args(common_arguments())
+                .args(config_arguments()),
         )
         .subcommand(
             SubCommand::with_name("tag")
                 .about("Regular-expression based tagger on plain text")
-                .args(&common_arguments())
-                .args(&store_argument())
-                .args(&config_arguments())
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments())
                 .arg(
                     Arg::with_name("rules")
                         .long("rules")
                         .help(
-                            "A TSV file containing regular expression rules for the tagger.",
+                            "A TSV file containing regular expression rules for the tagger. Use - to read the rules from standard input.",
                         )
-                        .long_help("A TSV file containing regular expression rules for the tagger.
+                        .long_help("A TSV file containing regular expression rules for the tagger. Use - to read the rules from standard input.
 The file contains the following columns:
 
 1. The regular expressions follow the following syntax: https://docs.rs/regex/latest/regex/#syntax
@@ -208,7 +347,10 @@ The file contains the following columns:
    tagged, in that case anything else is considered context and will not be tagged.
 2. The ID of annotation data set
 3. The ID of the data key
-4. The value to set. If this follows the syntax $1,$2,etc.. it will assign the value of that capture group (1-indexed).")
+4. The value to set. If this follows the syntax $1,$2,etc.. it will assign the value of that capture group (1-indexed).
+5. (optional) A scope: a filter like set/key=value (same syntax as --filter). The rule only applies to text covered
+   by annotations matching the filter. These may be annotations produced by other rules, which are then applied
+   first (in an earlier pass).")
                         .takes_value(true)
                         .required(true),
                 )
@@ -218,8 +360,177 @@ The file contains the following columns:
                         .short('O')
                         .help("Allow regular expression matches to overlap")
                         .required(false),
+                )
+                .args(filter_arguments(false))
+                .arg(
+                    Arg::with_name("link-scope")
+                        .long("link-scope")
+                        .help("Link the new annotations to the scope annotations selected by --filter (via an AnnotationSelector with a relative offset), rather than to the text directly")
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("normalize")
+                        .long("normalize")
+                        .help("Match the rules against a normalized version of the text, the annotations still refer to the original text. Comma separated list of: case (lowercase the text, so write the expressions in lowercase), whitespace (collapse runs of whitespace into a single space)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("equivalences")
+                        .long("equivalences")
+                        .help("TSV file with character equivalences for normalizing the text (see --normalize): a character, and the string to replace it with (e.g. ſ and s). An empty second column removes the character.")
+                        .takes_value(true),
                 ))
-        .get_matches();
+        .subcommand(
+            SubCommand::with_name("anonymize")
+                .about("Replace sensitive text spans, selected by their annotations, with placeholders or consistent pseudonyms. The changed resources are written to new files and all annotations are carried over.")
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments())
+                .args(filter_arguments(true))
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .short('t')
+                        .help("Template for the replacement text. The variables {value}, {key} and {set} refer to the matching data, {n} is a number that is the same for the same text and value. [default: [{value}], or {value}{n} with --pseudonymize]")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("pseudonymize")
+                        .long("pseudonymize")
+                        .short('p')
+                        .help("Replace with consistent pseudonyms rather than plain placeholders (shortcut for --template {value}{n})"),
+                )
+                .arg(
+                    Arg::with_name("suffix")
+                        .long("suffix")
+                        .help("Suffix to add to the ID and filename of changed resources")
+                        .takes_value(true)
+                        .default_value("anonymized"),
+                )
+                .arg(
+                    Arg::with_name("outputfile")
+                        .long("outputfile")
+                        .short('o')
+                        .help("Output filename for the annotation store, if not set the input store is overwritten")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sort")
+                .alias("reindex")
+                .about("Rewrite the annotation store with annotations in a deterministic order (by resource, offset and ID), and optionally with new sequential IDs. This produces stable output that can be compared between runs.")
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments())
+                .arg(
+                    Arg::with_name("id-template")
+                        .long("id-template")
+                        .help("Assign new IDs to all annotations, in the sorted order. {n} is replaced by a sequence number (1-indexed), for example: A{n}")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("data-id-template")
+                        .long("data-id-template")
+                        .help("Sort the data in each annotation set by key and value and assign new IDs. {n} is replaced by a sequence number (1-indexed, per set), {set} by the ID of the annotation set. Example: D{n}")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("outputfile")
+                        .long("outputfile")
+                        .short('o')
+                        .help("Output filename for the annotation store, if not set the input store is overwritten")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rename")
+                .about("Rename annotation sets, data keys (merging them into existing keys if present) and annotations (by regular expression), updating all references to them, and report what was renamed.")
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments())
+                .args(rename_arguments()),
+        )
+        .subcommand(
+            SubCommand::with_name("resolve")
+                .about("Link entities: send the text of annotations to a reconciliation service (like the one for Wikidata) and add the identifiers it returns as data to the annotations.")
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments())
+                .args(resolve_arguments()),
+        )
+        .subcommand(
+            SubCommand::with_name("gc")
+                .about("Remove annotation data, data keys, annotation sets and resources that no annotation refers to, and report what was removed. Use --dry-run to only get the report.")
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments()),
+        )
+        .subcommand(
+            SubCommand::with_name("fixtext")
+                .about("Apply corrections to the text of a resource, given as a TSV file (offset, old text, new text) or as a unified diff. The corrected text is written to a new resource, all annotations are carried over to it, and each edit is recorded as an annotation linking the old and the new text.")
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments())
+                .arg(
+                    Arg::with_name("resource")
+                        .long("resource")
+                        .short('r')
+                        .help("The ID of the resource to correct")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("edits")
+                        .long("edits")
+                        .short('e')
+                        .help("TSV file with the edits, with three columns: begin offset (in unicode points), old text and new text. Tabs and newlines may be escaped as \\t and \\n.")
+                        .takes_value(true)
+                        .required_unless_present("diff")
+                        .conflicts_with("diff"),
+                )
+                .arg(
+                    Arg::with_name("diff")
+                        .long("diff")
+                        .help("Unified diff (diff -u) with the edits, relative to the text of the resource")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("suffix")
+                        .long("suffix")
+                        .help("Suffix to add to the ID and filename of the new resource")
+                        .takes_value(true)
+                        .default_value("fixed"),
+                )
+                .arg(
+                    Arg::with_name("annotationset")
+                        .long("annotationset")
+                        .help("The ID of the annotation set for the annotations that record the edits (with keys 'old' and 'new')")
+                        .takes_value(true)
+                        .default_value("fixtext"),
+                )
+                .arg(
+                    Arg::with_name("outputfile")
+                        .long("outputfile")
+                        .short('o')
+                        .help("Output filename for the annotation store, if not set the input store is overwritten")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("annotate-from-diff")
+                .about("Compare two versions of a text (two resources, or plain text files that are added as resources) and record the differences as annotations: insertions, deletions and substitutions, each linking the old and the new text, along with the unchanged segments that link both versions.")
+                .args(common_arguments())
+                .args(store_argument())
+                .args(config_arguments())
+                .args(diff_arguments()),
+        );
+    for name in unused_config_vars(&configvars, &app) {
+        eprintln!(
+            "Warning: configuration sets {}, but no option takes its default from it",
+            name
+        );
+    }
+    let rootargs = app.get_matches();
 
     let args = if let Some(args) = rootargs.subcommand_matches("info") {
         args
@@ -227,6 +538,16 @@ The file contains the following columns:
         args
     } else if let Some(args) = rootargs.subcommand_matches("export") {
         args
+    } else if let Some(args) = rootargs.subcommand_matches("concordance") {
+        args
+    } else if let Some(args) = rootargs.subcommand_matches("ngram") {
+        args
+    } else if let Some(args) = rootargs.subcommand_matches("coverage") {
+        args
+    } else if let Some(args) = rootargs.subcommand_matches("embed") {
+        args
+    } else if let Some(args) = rootargs.subcommand_matches("lexicon") {
+        args
     } else if let Some(args) = rootargs.subcommand_matches("import") {
         args
     } else if let Some(args) = rootargs.subcommand_matches("print") {
@@ -239,6 +560,20 @@ The file contains the following columns:
         args
     } else if let Some(args) = rootargs.subcommand_matches("tag") {
         args
+    } else if let Some(args) = rootargs.subcommand_matches("anonymize") {
+        args
+    } else if let Some(args) = rootargs.subcommand_matches("sort") {
+        args
+    } else if let Some(args) = rootargs.subcommand_matches("rename") {
+        args
+    } else if let Some(args) = rootargs.subcommand_matches("resolve") {
+        args
+    } else if let Some(args) = rootargs.subcommand_matches("gc") {
+        args
+    } else if let Some(args) = rootargs.subcommand_matches("fixtext") {
+        args
+    } else if let Some(args) = rootargs.subcommand_matches("annotate-from-diff") {
+        args
     } else {
         eprintln!("No command specified, please see stam --help");
         exit(2);
@@ -246,41 +581,47 @@ The file contains the following columns:
 
     let mut store = AnnotationStore::new().with_config(config_from_args(args));
 
-    if rootargs.subcommand_matches("info").is_some()
+    if (rootargs.subcommand_matches("info").is_some()
         || rootargs.subcommand_matches("export").is_some()
+        || rootargs.subcommand_matches("concordance").is_some()
+        || rootargs.subcommand_matches("ngram").is_some()
+        || rootargs.subcommand_matches("embed").is_some()
+        || rootargs.subcommand_matches("lexicon").is_some()
         || rootargs.subcommand_matches("print").is_some()
-        || rootargs.subcommand_matches("validate").is_some()
+        || rootargs.subcommand_matches("validate").is_some())
+        && args.is_present("annotationstore")
+        && !(rootargs.subcommand_matches("validate").is_some()
+            && args.is_present("verify-manifest"))
     {
-        if args.is_present("annotationstore") {
-            let storefiles = args
-                .values_of("annotationstore")
-                .unwrap()
-                .collect::<Vec<&str>>();
-            for (i, filename) in storefiles.iter().enumerate() {
-                eprintln!("Loading annotation store {}", filename);
-                if i == 0 {
-                    store = AnnotationStore::from_file(filename, config_from_args(args))
-                        .unwrap_or_else(|err| {
-                            eprintln!("Error loading annotation store: {}", err);
-                            exit(1);
-                        });
-                } else {
-                    store = store.with_file(filename).unwrap_or_else(|err| {
+        let storefiles = args
+            .values_of("annotationstore")
+            .unwrap()
+            .collect::<Vec<&str>>();
+        for (i, filename) in storefiles.iter().enumerate() {
+            eprintln!("Loading annotation store {}", filename);
+            if i == 0 {
+                store = compression::load_store(filename, config_from_args(args)).unwrap_or_else(
+                    |err| {
                         eprintln!("Error loading annotation store: {}", err);
                         exit(1);
-                    });
-                }
+                    },
+                );
+            } else {
+                store = compression::merge_store(store, filename).unwrap_or_else(|err| {
+                    eprintln!("Error loading annotation store: {}", err);
+                    exit(1);
+                });
             }
         }
     }
 
     if rootargs.subcommand_matches("info").is_some() {
-        info(&store, args.is_present("verbose"));
+        info(&store, args.is_present("verbose"), &filters_from_args(args));
     } else if rootargs.subcommand_matches("save").is_some() {
         store = load_store(args);
         store.set_filename(args.value_of("outputfile").unwrap());
         if !args.is_present("dry-run") {
-            store.save().unwrap_or_else(|err| {
+            save_store(&store).unwrap_or_else(|err| {
                 eprintln!(
                     "Failed to write annotation store {:?}: {}",
                     store.filename(),
@@ -291,19 +632,194 @@ The file contains the following columns:
         }
     } else if rootargs.subcommand_matches("export").is_some() {
         let columns: Vec<&str> = args.value_of("columns").unwrap().split(",").collect();
-        to_tsv(
-            &store,
-            &columns,
-            Type::try_from(args.value_of("type").unwrap()).unwrap_or_else(|err| {
-                eprintln!("Invalid type specified: {}", err);
+        let tp = Type::try_from(args.value_of("type").unwrap()).unwrap_or_else(|err| {
+            eprintln!("Invalid type specified: {}", err);
+            exit(1);
+        });
+        let sort_by: Vec<&str> = args
+            .value_of("sort-by")
+            .map(|columns| columns.split(",").collect())
+            .unwrap_or_default();
+        let mut job = ExportJob::new(&columns)
+            .with_type(tp)
+            .with_flatten(!args.is_present("verbose"))
+            .with_delimiter(args.value_of("subdelimiter").unwrap())
+            .with_null(args.value_of("null").unwrap())
+            .with_header(!args.is_present("no-header"))
+            .with_setdelimiter(args.value_of("setdelimiter").unwrap())
+            .with_sort_by(&sort_by)
+            .with_utf8_offsets(utf8_from_args(args))
+            .with_dialect(dialect_from_args(args));
+        if args.is_present("distinct") {
+            let distinct: Vec<&str> = args
+                .value_of("distinct")
+                .map(|columns| columns.split(",").filter(|x| !x.is_empty()).collect())
+                .unwrap_or_default();
+            job = job.with_distinct(&distinct);
+        }
+        if let Some(group_by) = args.value_of("group-by") {
+            job = job.with_group_by(group_by);
+        }
+        if let Some(relative_to) = args.value_of("relative-to") {
+            job = job.with_relative_to(
+                DataFilter::parse(relative_to, args.value_of("setdelimiter").unwrap())
+                    .unwrap_or_else(|err| {
+                        eprintln!("{}", err);
+                        exit(1);
+                    }),
+            );
+        }
+        let selection = Selection {
+            data: parse_filters(
+                args.values_of("data").unwrap_or_default(),
+                args.value_of("setdelimiter").unwrap(),
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
                 exit(1);
             }),
-            !args.is_present("verbose"),
-            args.value_of("subdelimiter").unwrap(),
-            args.value_of("null").unwrap(),
-            !args.is_present("no-header"),
-            args.value_of("setdelimiter").unwrap(),
-        );
+            missing: parse_filters(
+                args.values_of("missing").unwrap_or_default(),
+                args.value_of("setdelimiter").unwrap(),
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            }),
+            text: args
+                .values_of("text-matches")
+                .unwrap_or_default()
+                .map(|expression| {
+                    Regex::new(expression).unwrap_or_else(|err| {
+                        eprintln!("Invalid regular expression for --text-matches: {}", err);
+                        exit(1);
+                    })
+                })
+                .collect(),
+        };
+        job = job.with_selection(selection.clone());
+        if !selection.missing.is_empty() {
+            let count = store
+                .annotations()
+                .filter(|annotation| selection.test(annotation))
+                .count();
+            eprintln!(
+                "{} annotation(s) lack {}",
+                count,
+                args.values_of("missing")
+                    .unwrap_or_default()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        match args.value_of("format").unwrap() {
+            "tsv" => match args.value_of("outputfile") {
+                Some(template)
+                    if template.contains("{resource}")
+                        || (args.is_present("group-by") && template.contains("{group}")) =>
+                {
+                    job.to_files(&store, template).map(|filenames| {
+                        if args.is_present("verbose") {
+                            for filename in filenames {
+                                eprintln!("Wrote {}", filename);
+                            }
+                        }
+                    })
+                }
+                _ => job.to_stdout(&store),
+            },
+            "xlsx" => {
+                let outputfile = args.value_of("outputfile").unwrap_or_else(|| {
+                    eprintln!("--format xlsx requires --outputfile");
+                    exit(1);
+                });
+                job.to_xlsx(&store, outputfile, args.is_present("sheet-per-resource"))
+            }
+            "brat" => {
+                let mapping = match args.value_of("brat-map") {
+                    Some(filename) => {
+                        BratMapping::from_file(filename, args.value_of("setdelimiter").unwrap())
+                            .unwrap_or_else(|err| {
+                                eprintln!("{}", err);
+                                exit(1);
+                            })
+                    }
+                    None => BratMapping::default(),
+                };
+                to_brat(&store, &mapping, args.value_of("outputfile").unwrap_or(".")).map(
+                    |filenames| {
+                        if args.is_present("verbose") {
+                            for filename in filenames {
+                                eprintln!("Wrote {}", filename);
+                            }
+                        }
+                    },
+                )
+            }
+            "json-nested" => match args.value_of("outputfile") {
+                Some(filename) => File::create(filename)
+                    .map_err(|e| format!("Error creating {}: {}", filename, e))
+                    .and_then(|mut file| job.to_json_nested(&mut file, &store)),
+                None => job.to_json_nested(&mut std::io::stdout().lock(), &store),
+            },
+            format @ ("srt" | "vtt") => {
+                let parse = |name: &str| {
+                    DataFilter::parse(
+                        args.value_of(name).unwrap(),
+                        args.value_of("setdelimiter").unwrap(),
+                    )
+                    .unwrap_or_else(|err| {
+                        eprintln!("{}", err);
+                        exit(1);
+                    })
+                };
+                let number = |name: &str| {
+                    args.value_of(name)
+                        .unwrap()
+                        .parse::<usize>()
+                        .unwrap_or_else(|_| {
+                            eprintln!("--{} must be a number", name);
+                            exit(1);
+                        })
+                };
+                let cues = subtitle_cues(
+                    &store,
+                    &parse("begin-time-key"),
+                    &parse("end-time-key"),
+                    &selection,
+                );
+                let format = if format == "srt" {
+                    SubtitleFormat::Srt
+                } else {
+                    SubtitleFormat::WebVtt
+                };
+                let (max_line_length, max_lines) = (number("max-line-length"), number("max-lines"));
+                match args.value_of("outputfile") {
+                    Some(filename) => File::create(filename)
+                        .map_err(|e| format!("Error creating {}: {}", filename, e))
+                        .and_then(|mut file| {
+                            to_subtitles(&mut file, &cues, format, max_line_length, max_lines)
+                        }),
+                    None => to_subtitles(
+                        &mut std::io::stdout().lock(),
+                        &cues,
+                        format,
+                        max_line_length,
+                        max_lines,
+                    ),
+                }
+                .map(|count| {
+                    if args.is_present("verbose") {
+                        eprintln!("Wrote {} subtitle(s)", count);
+                    }
+                })
+            }
+            format => Err(format!("Unknown output format: {}", format)),
+        }
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
     } else if rootargs.subcommand_matches("import").is_some() {
         let storefilename = args
             .value_of("annotationstore")
@@ -316,50 +832,127 @@ The file contains the following columns:
             eprintln!("New annotation store created");
             store.set_filename(storefilename);
         }
-        let columns: Option<Vec<&str>> = if args.is_present("columns") {
-            Some(args.value_of("columns").unwrap().split(",").collect())
-        } else {
-            None
-        };
-        let existing_resource: Option<&str> = if args.is_present("resource") {
-            Some(args.value_of("resource").unwrap())
-        } else {
-            None
-        };
-        let new_resource: Option<&str> = if args.is_present("new-resource") {
-            Some(args.value_of("new-resource").unwrap())
+        let validation = ValidationMode::try_from(args.value_of("validate").unwrap())
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+        if args.value_of("format") == Some("brat") {
+            for filename in inputfiles.iter() {
+                let count = from_brat(
+                    &mut store,
+                    filename,
+                    args.value_of("resource"),
+                    args.value_of("annotationset"),
+                    validation,
+                )
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+                eprintln!("Imported {} annotations from {}", count, filename);
+            }
+        } else if args.value_of("format") == Some("elan")
+            || args.value_of("format") == Some("textgrid")
+        {
+            for filename in inputfiles.iter() {
+                if args.value_of("format") == Some("elan") {
+                    from_eaf(
+                        &mut store,
+                        filename,
+                        args.value_of("new-resource"),
+                        args.value_of("annotationset"),
+                    )
+                } else {
+                    from_textgrid(
+                        &mut store,
+                        filename,
+                        args.value_of("new-resource"),
+                        args.value_of("annotationset"),
+                    )
+                }
+                .map(|count| eprintln!("Imported {} annotations from {}", count, filename))
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+            }
         } else {
-            None
-        };
-        for inputfile in inputfiles {
-            from_tsv(
-                &mut store,
-                &inputfile,
-                columns.as_ref(),
-                existing_resource,
-                new_resource,
-                args.value_of("annotationset"),
-                !args.is_present("no-comments"),
-                !args.is_present("no-seq"),
-                !args.is_present("no-case"),
-                !args.is_present("no-escape"),
-                args.value_of("null").unwrap(),
-                args.value_of("subdelimiter").unwrap(),
-                args.value_of("setdelimiter").unwrap(),
-                args.value_of("outputdelimiter").unwrap(),
-                args.value_of("outputdelimiter2").unwrap(),
-                Some(!args.is_present("no-header")),
-                ValidationMode::try_from(args.value_of("validate").unwrap()).unwrap_or_else(
-                    |err| {
+            let columns: Option<Vec<&str>> = args
+                .value_of("columns")
+                .map(|columns| columns.split(",").collect());
+            let mut job = ImportJob::new()
+                .with_comments(!args.is_present("no-comments"))
+                .with_sequential(!args.is_present("no-seq"))
+                .with_case_sensitive(!args.is_present("no-case"))
+                .with_align_window(
+                    args.value_of("align-window")
+                        .unwrap()
+                        .parse()
+                        .unwrap_or_else(|err| {
+                            eprintln!("Invalid value for --align-window: {}", err);
+                            exit(1);
+                        }),
+                )
+                .with_escape(!args.is_present("no-escape"))
+                .with_null(args.value_of("null").unwrap())
+                .with_subdelimiter(args.value_of("subdelimiter").unwrap())
+                .with_setdelimiter(args.value_of("setdelimiter").unwrap())
+                .with_outputdelimiters(
+                    args.value_of("outputdelimiter").unwrap(),
+                    args.value_of("outputdelimiter2").unwrap(),
+                )
+                .with_header(Some(!args.is_present("no-header")))
+                .with_validation(validation)
+                .with_utf8_offsets(utf8_from_args(args))
+                .with_dialect(dialect_from_args(args))
+                .with_verbose(args.is_present("verbose"))
+                .with_progress(args.is_present("progress"));
+            if let Some(columns) = columns.as_ref() {
+                job = job.with_columns(columns);
+            }
+            if let Some(resource) = args.value_of("resource") {
+                job = job.with_resource(resource);
+            }
+            if let Some(resource) = args.value_of("new-resource") {
+                job = job.with_new_resource(resource);
+            }
+            if let Some(set) = args.value_of("annotationset") {
+                job = job.with_annotationset(set);
+            }
+            for spec in args.values_of("join").unwrap_or_default() {
+                job = job.with_join(spec);
+            }
+            for column in args.values_of("group").unwrap_or_default() {
+                job = job.with_group(column);
+            }
+            let report = job.run(&mut store, &inputfiles).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            if report.annotations > 0 {
+                eprintln!(
+                    "Joined data into {} existing annotation(s)",
+                    report.annotations
+                );
+            }
+            if !report.unmatched.is_empty() {
+                eprintln!(
+                    "Warning: joined data could not be matched with any annotation for the following ID(s): {}",
+                    report.unmatched.join(", ")
+                );
+            }
+            if !args.is_present("dry-run") {
+                for (id, filename) in report.setfiles.iter() {
+                    write_annotationset(&store, id, filename).unwrap_or_else(|err| {
                         eprintln!("{}", err);
                         exit(1);
-                    },
-                ),
-                args.is_present("verbose"),
-            );
+                    });
+                }
+            }
         }
         if !args.is_present("dry-run") {
-            store.save().unwrap_or_else(|err| {
+            save_store(&store).unwrap_or_else(|err| {
                 eprintln!(
                     "Failed to write annotation store {:?}: {}",
                     store.filename(),
@@ -368,11 +961,314 @@ The file contains the following columns:
                 exit(1);
             });
         }
+    } else if rootargs.subcommand_matches("concordance").is_some() {
+        let expression = args.value_of("regex").map(|expression| {
+            Regex::new(expression).unwrap_or_else(|err| {
+                eprintln!("Invalid regular expression: {}", err);
+                exit(1);
+            })
+        });
+        let width: usize = args
+            .value_of("width")
+            .unwrap()
+            .parse()
+            .unwrap_or_else(|err| {
+                eprintln!("Invalid width: {}", err);
+                exit(1);
+            });
+        let lines = concordance(
+            &store,
+            expression.as_ref(),
+            &filters_from_args(args),
+            width,
+            ContextUnit::try_from(args.value_of("unit").unwrap()).unwrap(),
+            SortOrder::try_from(args.value_of("sort").unwrap()).unwrap(),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        let mut stdout = std::io::stdout().lock();
+        if args.value_of("format") == Some("tsv") {
+            print_concordance_tsv(&mut stdout, &lines)
+        } else {
+            print_concordance(&mut stdout, &lines)
+        }
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+    } else if rootargs.subcommand_matches("ngram").is_some() {
+        let size = parse_ngram_size(args.value_of("size").unwrap()).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        let min_freq: usize = args
+            .value_of("min-freq")
+            .unwrap()
+            .parse()
+            .unwrap_or_else(|err| {
+                eprintln!("Invalid value for --min-freq: {}", err);
+                exit(1);
+            });
+        let frequencies = ngrams(
+            &store,
+            &filters_from_args(args),
+            size,
+            ContextUnit::try_from(args.value_of("unit").unwrap()).unwrap(),
+            args.is_present("per-resource"),
+            args.is_present("lowercase"),
+        );
+        print_ngrams(
+            &mut std::io::stdout().lock(),
+            &frequencies,
+            min_freq,
+            args.is_present("per-resource"),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+    } else if rootargs.subcommand_matches("lexicon").is_some() {
+        let parse = |name: &str| {
+            args.value_of(name)
+                .unwrap()
+                .parse::<usize>()
+                .unwrap_or_else(|_| {
+                    eprintln!("--{} must be a number", name);
+                    exit(1);
+                })
+        };
+        let key = DataFilter::parse(
+            args.value_of("key").unwrap(),
+            args.value_of("setdelimiter").unwrap(),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        let reverse = args.is_present("reverse");
+        let lexicon = lexicon(
+            &store,
+            &key,
+            &filters_from_args(args),
+            reverse,
+            parse("examples"),
+            parse("width"),
+            args.is_present("lowercase"),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        let mut stdout = std::io::stdout().lock();
+        if args.value_of("format") == Some("json") {
+            print_lexicon_json(&mut stdout, &lexicon, reverse)
+        } else {
+            print_lexicon_tsv(&mut stdout, &lexicon, reverse)
+        }
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+    } else if rootargs.subcommand_matches("coverage").is_some() {
+        store = load_store(args);
+        let min_gap: usize = args
+            .value_of("min-gap")
+            .unwrap()
+            .parse()
+            .unwrap_or_else(|err| {
+                eprintln!("Invalid value for --min-gap: {}", err);
+                exit(1);
+            });
+        let coverages = coverage(
+            &store,
+            &filters_from_args(args),
+            min_gap,
+            args.is_present("count-whitespace"),
+        );
+        let mut stdout = std::io::stdout().lock();
+        match args.value_of("list") {
+            Some(list) => print_coverage_spans(&mut stdout, &store, &coverages, list == "gaps"),
+            None => print_coverage(&mut stdout, &coverages),
+        }
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        if let Some(set_id) = args.value_of("annotate-gaps") {
+            let count = annotate_gaps(&mut store, &coverages, set_id).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            eprintln!("Added {} gap annotation(s)", count);
+            if !args.is_present("dry-run") {
+                save_store(&store).unwrap_or_else(|err| {
+                    eprintln!(
+                        "Failed to write annotation store {:?}: {}",
+                        store.filename(),
+                        err
+                    );
+                    exit(1);
+                });
+            }
+        }
+    } else if rootargs.subcommand_matches("embed").is_some() {
+        let max_tokens: Option<usize> = args.value_of("max-tokens").map(|value| {
+            value.parse().unwrap_or_else(|err| {
+                eprintln!("Invalid value for --max-tokens: {}", err);
+                exit(1);
+            })
+        });
+        let metadata = parse_filters(
+            args.values_of("metadata").unwrap_or_default(),
+            args.value_of("setdelimiter").unwrap(),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        let count = embed(
+            &mut std::io::stdout().lock(),
+            &store,
+            &filters_from_args(args),
+            &metadata,
+            max_tokens,
+            args.is_present("with-resource-text"),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        if args.is_present("verbose") {
+            eprintln!("Exported {} record(s)", count);
+        }
     } else if rootargs.subcommand_matches("print").is_some() {
-        let resource_ids = args.values_of("resource").unwrap().collect::<Vec<&str>>();
-        to_text(&store, resource_ids);
+        if let Some(filename) = args.value_of("offsets-file") {
+            let spans = load_spans(filename).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            let separator = args
+                .value_of("separator")
+                .unwrap()
+                .replace("\\n", "\n")
+                .replace("\\t", "\t");
+            print_spans(
+                &mut std::io::stdout().lock(),
+                &store,
+                &spans,
+                &separator,
+                args.is_present("with-id"),
+                utf8_from_args(args),
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("Error: {}", err);
+                exit(1);
+            });
+        }
+        if let Some(resource_ids) = args.values_of("resource") {
+            to_text(&store, resource_ids.collect());
+        }
+    } else if rootargs.subcommand_matches("validate").is_some()
+        && args.is_present("verify-manifest")
+    {
+        let manifest = args.value_of("verify-manifest").unwrap();
+        let basedir = manifest_basedir(args.value_of("annotationstore").unwrap());
+        let (count, problems) = verify_manifest(manifest, &basedir).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        for (filename, problem) in problems.iter() {
+            eprintln!("{}: {}", filename, problem.as_str());
+        }
+        if !problems.is_empty() {
+            eprintln!(
+                "{} of {} file(s) in the manifest are missing or modified",
+                problems.len(),
+                count
+            );
+            exit(1);
+        }
+        eprintln!(
+            "All {} file(s) in the manifest are present and unmodified",
+            count
+        );
     } else if rootargs.subcommand_matches("validate").is_some() {
         validate(&store, args.is_present("verbose"));
+        if let Some(manifest) = args.value_of("make-manifest") {
+            let storefiles: Vec<&str> = args.values_of("annotationstore").unwrap().collect();
+            let basedir = manifest_basedir(storefiles[0]);
+            let result = if manifest == "-" {
+                make_manifest(&mut std::io::stdout(), &store, &storefiles, &basedir)
+            } else {
+                File::create(manifest)
+                    .map_err(|e| format!("Error creating manifest {}: {}", manifest, e))
+                    .and_then(|mut f| make_manifest(&mut f, &store, &storefiles, &basedir))
+            };
+            match result {
+                Ok(count) => eprintln!("Wrote checksums of {} file(s)", count),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1);
+                }
+            }
+        }
+        if let Some(filename) = args.value_of("schema") {
+            let schema = Schema::from_file(filename).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            let invalid = validate_schema(&store, &schema);
+            if invalid > 0 {
+                eprintln!("{} annotation(s) do not conform to the schema", invalid);
+                exit(1);
+            }
+        }
+        let setdelimiter = args.value_of("setdelimiter").unwrap();
+        let mut policies = Vec::new();
+        for (name, policy) in [
+            ("disallow-overlap", OverlapPolicy::DisallowOverlap),
+            ("allow-nesting", OverlapPolicy::AllowNesting),
+        ] {
+            for expression in args.values_of(name).unwrap_or_default() {
+                let filter = DataFilter::parse(expression, setdelimiter).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+                policies.push((filter, policy));
+            }
+        }
+        if !policies.is_empty() {
+            let priority = OverlapPriority::try_from(args.value_of("overlap-priority").unwrap())
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+            let conflicts = find_overlap(&store, &policies, priority);
+            report_overlap(&store, &conflicts);
+            if args.is_present("delete-overlap") {
+                let (newstore, deleted) =
+                    delete_overlap(&store, &conflicts).unwrap_or_else(|err| {
+                        eprintln!("{}", err);
+                        exit(1);
+                    });
+                eprintln!("Deleted {} annotation(s)", deleted.len());
+                if !args.is_present("dry-run") {
+                    save_store(&newstore).unwrap_or_else(|err| {
+                        eprintln!(
+                            "Failed to write annotation store {:?}: {}",
+                            newstore.filename(),
+                            err
+                        );
+                        exit(1);
+                    });
+                }
+            } else if !conflicts.is_empty() {
+                eprintln!("{} overlap conflict(s) found", conflicts.len());
+                exit(1);
+            }
+        }
     } else if rootargs.subcommand_matches("init").is_some()
         || rootargs.subcommand_matches("annotate").is_some()
     {
@@ -421,8 +1317,158 @@ The file contains the following columns:
             &storefiles,
             &annotationfiles,
         );
+        if rootargs.subcommand_matches("annotate").is_some()
+            && (args.is_present("compose") || args.is_present("pairs"))
+        {
+            let setdelimiter = args.value_of("setdelimiter").unwrap();
+            let parse = |values: Option<clap::Values>| {
+                parse_filters(values.unwrap_or_default(), setdelimiter).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                })
+            };
+            let within = parse(args.values_of("within"));
+            let count = compose(
+                &mut store,
+                &parse(args.values_of("compose")),
+                within.first(),
+                args.value_of("pairs"),
+                &parse(args.values_of("data")),
+                args.is_present("multi"),
+                setdelimiter,
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("Error composing annotations: {}", err);
+                exit(1);
+            });
+            eprintln!("Added {} composite annotation(s)", count);
+        }
+        if rootargs.subcommand_matches("annotate").is_some()
+            && (args.is_present("delete") || args.is_present("retain"))
+        {
+            let setdelimiter = args.value_of("setdelimiter").unwrap();
+            let parse = |values: Option<clap::Values>| {
+                parse_filters(values.unwrap_or_default(), setdelimiter).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                })
+            };
+            let (newstore, report) = delete(
+                &store,
+                &parse(args.values_of("delete")),
+                &parse(args.values_of("retain")),
+                args.is_present("keep-orphan-data"),
+                args.is_present("keep-orphan-sets"),
+                args.is_present("keep-orphan-resources"),
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("Error deleting annotations: {}", err);
+                exit(1);
+            });
+            eprintln!(
+                "Deleted {} annotation(s), {} orphaned annotation data, {} orphaned annotationset(s), {} orphaned resource(s)",
+                report.annotations.len(),
+                report.data,
+                report.sets.len(),
+                report.resources.len()
+            );
+            if !args.is_present("dry-run") {
+                for (id, filename) in report.setfiles.iter() {
+                    write_annotationset(&newstore, id, filename).unwrap_or_else(|err| {
+                        eprintln!("{}", err);
+                        exit(1);
+                    });
+                }
+            }
+            store = newstore;
+        }
+        if rootargs.subcommand_matches("annotate").is_some()
+            && (args.is_present("augment")
+                || args.is_present("with-data")
+                || args.is_present("with-data-file"))
+        {
+            let setdelimiter = args.value_of("setdelimiter").unwrap();
+            let parse = |values: Option<clap::Values>| {
+                parse_filters(values.unwrap_or_default(), setdelimiter).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                })
+            };
+            let mut joins = JoinData::default();
+            let job = ImportJob::new().with_setdelimiter(setdelimiter);
+            for spec in args.values_of("with-data-file").unwrap_or_default() {
+                joins.load(spec, &job).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+            }
+            let (newstore, report) = augment(
+                &store,
+                &parse(args.values_of("augment")),
+                &parse(args.values_of("with-data")),
+                &mut joins,
+                setdelimiter,
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("Error augmenting annotations: {}", err);
+                exit(1);
+            });
+            if !report.unmatched.is_empty() {
+                eprintln!(
+                    "Warning: data could not be matched with any (selected) annotation for the following ID(s): {}",
+                    report.unmatched.join(", ")
+                );
+            }
+            eprintln!(
+                "Added {} annotation data to {} existing annotation(s)",
+                report.data, report.annotations
+            );
+            if let Some(newstore) = newstore {
+                if !args.is_present("dry-run") {
+                    for (id, filename) in report.setfiles.iter() {
+                        write_annotationset(&newstore, id, filename).unwrap_or_else(|err| {
+                            eprintln!("{}", err);
+                            exit(1);
+                        });
+                    }
+                }
+                store = newstore;
+            }
+        }
+        if rootargs.subcommand_matches("annotate").is_some() && args.is_present("windows") {
+            let parse = |name: &str| {
+                args.value_of(name)
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap_or_else(|_| {
+                        eprintln!("--{} must be a number", name);
+                        exit(1);
+                    })
+            };
+            let unit = ContextUnit::try_from(args.value_of("window-unit").unwrap()).unwrap();
+            let windowdata = args.value_of("window-data").unwrap();
+            let (set, key) = windowdata
+                .rsplit_once(args.value_of("setdelimiter").unwrap())
+                .unwrap_or_else(|| {
+                    eprintln!("--window-data must be of the form set/key");
+                    exit(1);
+                });
+            let count = windows(
+                &mut store,
+                parse("windows"),
+                parse("window-overlap"),
+                unit,
+                set,
+                key,
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            eprintln!("Added {} window annotation(s)", count);
+        }
         if !args.is_present("dry-run") {
-            store.save().unwrap_or_else(|err| {
+            save_store(&store).unwrap_or_else(|err| {
                 eprintln!(
                     "Failed to write annotation store {:?}: {}",
                     store.filename(),
@@ -434,13 +1480,362 @@ The file contains the following columns:
     } else if rootargs.subcommand_matches("tag").is_some() {
         //load the store
         store = load_store(args);
+        let mut normalizer = Normalizer::new()
+            .with_options(args.value_of("normalize").unwrap_or(""))
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+        if let Some(filename) = args.value_of("equivalences") {
+            normalizer = normalizer
+                .with_equivalences_file(filename)
+                .unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+        }
+        let scope = filters_from_args(args);
         tag(
             &mut store,
             args.value_of("rules").expect("--rules must be provided"),
-            args.is_present("allow-overlap"),
+            &TagOptions::new()
+                .with_allow_overlap(args.is_present("allow-overlap"))
+                .with_scope(&scope)
+                .with_link_scope(args.is_present("link-scope"))
+                .with_setdelimiter(args.value_of("setdelimiter").unwrap())
+                .with_normalizer(Some(&normalizer).filter(|normalizer| !normalizer.is_identity()))
+                .with_progress(args.is_present("progress")),
+        );
+        if !args.is_present("dry-run") {
+            save_store(&store).unwrap_or_else(|err| {
+                eprintln!(
+                    "Failed to write annotation store {:?}: {}",
+                    store.filename(),
+                    err
+                );
+                exit(1);
+            });
+        }
+    } else if rootargs.subcommand_matches("anonymize").is_some() {
+        store = load_store(args);
+        let filters = filters_from_args(args);
+        let template = args
+            .value_of("template")
+            .unwrap_or(if args.is_present("pseudonymize") {
+                "{value}{n}"
+            } else {
+                "[{value}]"
+            });
+        let (mut newstore, newresources) = anonymize(
+            &store,
+            &filters,
+            template,
+            args.value_of("suffix").unwrap(),
+            args.is_present("verbose"),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        if let Some(outputfile) = args.value_of("outputfile") {
+            newstore.set_filename(outputfile);
+        }
+        if !args.is_present("dry-run") {
+            for (id, filename) in newresources.iter() {
+                write_resource(&newstore, id, filename).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+            }
+            save_store(&newstore).unwrap_or_else(|err| {
+                eprintln!(
+                    "Failed to write annotation store {:?}: {}",
+                    newstore.filename(),
+                    err
+                );
+                exit(1);
+            });
+        }
+    } else if rootargs.subcommand_matches("sort").is_some() {
+        store = load_store(args);
+        let (mut newstore, setfiles) = sort(
+            &store,
+            args.value_of("id-template"),
+            args.value_of("data-id-template"),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        if let Some(outputfile) = args.value_of("outputfile") {
+            newstore.set_filename(outputfile);
+        }
+        if !args.is_present("dry-run") {
+            for (id, filename) in setfiles.iter() {
+                write_annotationset(&newstore, id, filename).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+            }
+            save_store(&newstore).unwrap_or_else(|err| {
+                eprintln!(
+                    "Failed to write annotation store {:?}: {}",
+                    newstore.filename(),
+                    err
+                );
+                exit(1);
+            });
+        }
+    } else if rootargs.subcommand_matches("rename").is_some() {
+        store = load_store(args);
+        let renames = Renames::parse(
+            args.values_of("set").unwrap_or_default(),
+            args.values_of("key").unwrap_or_default(),
+            args.values_of("annotation-id").unwrap_or_default(),
+            args.value_of("setdelimiter").unwrap(),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        if renames.is_empty() {
+            eprintln!("Nothing to rename, specify --set, --key and/or --annotation-id");
+            exit(1);
+        }
+        let (mut newstore, report) = rename(&store, &renames).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        eprintln!(
+            "Renamed {} annotationset(s), {} data key(s) ({} merged into existing keys, {} duplicate annotation data merged), {} annotation(s); updated {} reference(s)",
+            report.sets,
+            report.keys,
+            report.merged_keys,
+            report.merged_data,
+            report.annotations,
+            report.references
+        );
+        if let Some(outputfile) = args.value_of("outputfile") {
+            newstore.set_filename(outputfile);
+        }
+        if !args.is_present("dry-run") && !report.is_empty() {
+            for (id, filename) in report.setfiles.iter() {
+                write_annotationset(&newstore, id, filename).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+            }
+            save_store(&newstore).unwrap_or_else(|err| {
+                eprintln!(
+                    "Failed to write annotation store {:?}: {}",
+                    newstore.filename(),
+                    err
+                );
+                exit(1);
+            });
+        }
+    } else if rootargs.subcommand_matches("resolve").is_some() {
+        store = load_store(args);
+        let setdelimiter = args.value_of("setdelimiter").unwrap();
+        let filter =
+            DataFilter::parse(args.value_of("key").unwrap(), setdelimiter).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+        let (result_set, result_key) = if let Some(result_key) = args.value_of("result-key") {
+            let result = DataFilter::parse(result_key, setdelimiter).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+            (result.set, result.key)
+        } else {
+            (filter.set.clone(), format!("{}_id", filter.key))
+        };
+        let mut resolver =
+            Resolver::new(args.value_of("endpoint").unwrap())
+                .with_batch_size(args.value_of("batch-size").unwrap().parse().unwrap_or_else(
+                    |_| {
+                        eprintln!("--batch-size must be a number");
+                        exit(1);
+                    },
+                ))
+                .with_delay(std::time::Duration::from_millis(
+                    args.value_of("delay").unwrap().parse().unwrap_or_else(|_| {
+                        eprintln!("--delay must be a number (milliseconds)");
+                        exit(1);
+                    }),
+                ));
+        if let Some(entity_type) = args.value_of("type") {
+            resolver = resolver.with_type(entity_type);
+        }
+        if let Some(min_score) = args.value_of("min-score") {
+            resolver = resolver.with_min_score(min_score.parse().unwrap_or_else(|_| {
+                eprintln!("--min-score must be a number");
+                exit(1);
+            }));
+        }
+        if let Some(filename) = args.value_of("cache") {
+            resolver.load_cache(filename).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+        }
+        let result = resolve(&store, &filter, &result_set, &result_key, &mut resolver);
+        //save what was retrieved, even if a later request failed
+        if let Some(filename) = args.value_of("cache") {
+            resolver.save_cache(filename).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            });
+        }
+        let (mut newstore, report) = result.unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        eprintln!(
+            "Resolved {} annotation(s), {} unresolved, {} skipped because they were resolved already ({} request(s) sent)",
+            report.resolved,
+            report.unresolved,
+            report.skipped,
+            resolver.requests()
+        );
+        if let Some(outputfile) = args.value_of("outputfile") {
+            newstore.set_filename(outputfile);
+        }
+        if !args.is_present("dry-run") && report.resolved > 0 {
+            for (id, filename) in report.setfiles.iter() {
+                write_annotationset(&newstore, id, filename).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+            }
+            save_store(&newstore).unwrap_or_else(|err| {
+                eprintln!(
+                    "Failed to write annotation store {:?}: {}",
+                    newstore.filename(),
+                    err
+                );
+                exit(1);
+            });
+        }
+    } else if rootargs.subcommand_matches("gc").is_some() {
+        store = load_store(args);
+        let (newstore, report) = gc(&store).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        if args.is_present("verbose") {
+            for set_id in report.sets.iter() {
+                eprintln!("Removed annotationset {}", set_id);
+            }
+            for resource_id in report.resources.iter() {
+                eprintln!("Removed resource {}", resource_id);
+            }
+        }
+        eprintln!(
+            "Removed {} unused annotation data, {} unused data key(s), {} unused annotationset(s), {} unused resource(s)",
+            report.data,
+            report.keys,
+            report.sets.len(),
+            report.resources.len()
+        );
+        if !args.is_present("dry-run") && !report.is_empty() {
+            for (id, filename) in report.setfiles.iter() {
+                write_annotationset(&newstore, id, filename).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+            }
+            save_store(&newstore).unwrap_or_else(|err| {
+                eprintln!(
+                    "Failed to write annotation store {:?}: {}",
+                    newstore.filename(),
+                    err
+                );
+                exit(1);
+            });
+        }
+    } else if rootargs.subcommand_matches("fixtext").is_some() {
+        store = load_store(args);
+        let resource_id = args.value_of("resource").unwrap();
+        let text = if let Some(resource) = store.resource(&Item::IdRef(resource_id)) {
+            resource.text().to_string()
+        } else {
+            eprintln!("Resource {} not found", resource_id);
+            exit(1);
+        };
+        let edits = if let Some(filename) = args.value_of("diff") {
+            edits_from_diff(filename, &text)
+        } else {
+            edits_from_tsv(args.value_of("edits").unwrap(), &text)
+        }
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        let (mut newstore, newresource) = fixtext(
+            &store,
+            resource_id,
+            edits,
+            args.value_of("suffix").unwrap(),
+            args.value_of("annotationset").unwrap(),
+            args.is_present("verbose"),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        if let Some(outputfile) = args.value_of("outputfile") {
+            newstore.set_filename(outputfile);
+        }
+        if !args.is_present("dry-run") {
+            if let Some((id, filename)) = newresource.as_ref() {
+                write_resource(&newstore, id, filename).unwrap_or_else(|err| {
+                    eprintln!("{}", err);
+                    exit(1);
+                });
+            }
+            save_store(&newstore).unwrap_or_else(|err| {
+                eprintln!(
+                    "Failed to write annotation store {:?}: {}",
+                    newstore.filename(),
+                    err
+                );
+                exit(1);
+            });
+        }
+    } else if rootargs.subcommand_matches("annotate-from-diff").is_some() {
+        store = load_store(args);
+        let unit = DiffUnit::try_from(args.value_of("unit").unwrap()).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        let mut resource = |name: &str| {
+            get_or_add_resource(&mut store, args.value_of(name).unwrap()).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1);
+            })
+        };
+        let (old_id, new_id) = (resource("old"), resource("new"));
+        let (edits, count) = annotate_from_diff(
+            &mut store,
+            &old_id,
+            &new_id,
+            unit,
+            args.value_of("annotationset").unwrap(),
+            !args.is_present("no-pivot"),
+            args.is_present("verbose"),
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1);
+        });
+        eprintln!(
+            "Found {} edit(s) between {} and {}, added {} annotation(s)",
+            edits, old_id, new_id, count
         );
         if !args.is_present("dry-run") {
-            store.save().unwrap_or_else(|err| {
+            save_store(&store).unwrap_or_else(|err| {
                 eprintln!(
                     "Failed to write annotation store {:?}: {}",
                     store.filename(),
@@ -0,0 +1,551 @@
+use clap::Arg;
+use stam::{
+    Annotation, AnnotationBuilder, AnnotationDataBuilder, AnnotationHandle, AnnotationStore,
+    DataValue, Item, Offset, SelectorBuilder, Storable, Text, TextResourceHandle, WrappedItem,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::filter::{DataFilter, FilterOperator};
+use crate::tsv::{get_resource_handle, ValidationMode};
+
+/// The annotation set used for Brat data if none is specified
+pub const BRAT_SET: &str = "brat";
+
+/// A text-bound annotation (T line) or relation (R line) from a Brat `.ann` file
+struct BratAnnotation {
+    id: String,
+    label: String,
+    /// Spans (begin, end) for text-bound annotations, empty for relations
+    spans: Vec<(usize, usize)>,
+    /// Argument IDs for relations, in order
+    arguments: Vec<String>,
+    /// Attributes, normalizations and notes: key and value
+    data: Vec<(String, DataValue)>,
+}
+
+/// Returns the filename of the text that belongs with a Brat `.ann` file
+pub fn brat_text_filename(filename: &str) -> String {
+    Path::new(filename)
+        .with_extension("txt")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Imports a Brat standoff file (`.ann`). The text is read from the resource if specified, or else from the companion `.txt` file.
+/// Text-bound annotations (T) become annotations on the text, with their label as value of the key `type`; discontinuous spans
+/// yield a CompositeSelector. Relations (R) become annotations on their arguments (a CompositeSelector of AnnotationSelectors, in order).
+/// Attributes (A/M), normalizations (N) and notes (#) are added as data to the annotation they refer to.
+///
+/// Annotation IDs are prefixed with the resource ID, as Brat IDs are only unique within a document. Returns the number of annotations added.
+pub fn from_brat(
+    store: &mut AnnotationStore,
+    filename: &str,
+    resource: Option<&str>,
+    set: Option<&str>,
+    validation: ValidationMode,
+) -> Result<usize, String> {
+    let set = set.unwrap_or(BRAT_SET);
+    let textfilename = resource
+        .map(|resource| resource.to_string())
+        .unwrap_or_else(|| brat_text_filename(filename));
+    let resource_handle = get_resource_handle(store, &textfilename)?;
+    let f = File::open(filename).map_err(|e| format!("Error opening {}: {}", filename, e))?;
+    let mut annotations: Vec<BratAnnotation> = Vec::new();
+    //index of the annotation for each Brat ID
+    let mut index: HashMap<String, usize> = HashMap::new();
+    //data for annotations that may not have been read yet
+    let mut data: Vec<(usize, String, String, DataValue)> = Vec::new();
+    for (i, line) in BufReader::new(f).lines().enumerate() {
+        let line = line.map_err(|e| format!("Error reading {}: {}", filename, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.splitn(3, '\t').collect();
+        let id = fields[0];
+        let annotation = fields.get(1).copied().unwrap_or("");
+        let mut parts = annotation.split(' ');
+        let label = parts.next().unwrap_or("");
+        match id.chars().next() {
+            Some('T') => {
+                let mut spans = Vec::new();
+                for span in parts.collect::<Vec<_>>().join(" ").split(';') {
+                    spans.push(match span.split_once(' ') {
+                        Some((begin, end)) => match (begin.parse(), end.parse()) {
+                            (Ok(begin), Ok(end)) => (begin, end),
+                            _ => {
+                                return Err(format!(
+                                    "Error in {} line {}: invalid span '{}'",
+                                    filename,
+                                    i + 1,
+                                    span
+                                ))
+                            }
+                        },
+                        None => {
+                            return Err(format!(
+                                "Error in {} line {}: invalid span '{}'",
+                                filename,
+                                i + 1,
+                                span
+                            ))
+                        }
+                    });
+                }
+                if let Some(text) = fields.get(2) {
+                    validate_text(store, resource_handle, &spans, text, validation)
+                        .map_err(|e| format!("Error in {} line {}: {}", filename, i + 1, e))?;
+                }
+                index.insert(id.to_string(), annotations.len());
+                annotations.push(BratAnnotation {
+                    id: id.to_string(),
+                    label: label.to_string(),
+                    spans,
+                    arguments: Vec::new(),
+                    data: Vec::new(),
+                });
+            }
+            Some('R') => {
+                let arguments: Vec<String> = parts
+                    .filter_map(|argument| argument.split_once(':'))
+                    .map(|(_, target)| target.to_string())
+                    .collect();
+                if arguments.is_empty() {
+                    return Err(format!(
+                        "Error in {} line {}: relation has no arguments",
+                        filename,
+                        i + 1
+                    ));
+                }
+                index.insert(id.to_string(), annotations.len());
+                annotations.push(BratAnnotation {
+                    id: id.to_string(),
+                    label: label.to_string(),
+                    spans: Vec::new(),
+                    arguments,
+                    data: Vec::new(),
+                });
+            }
+            Some('A') | Some('M') => {
+                //binary attributes have no value
+                let target = parts.next().unwrap_or("");
+                let value: DataValue = match parts.next() {
+                    Some(value) => value.into(),
+                    None => true.into(),
+                };
+                data.push((i, target.to_string(), label.to_string(), value));
+            }
+            Some('N') => {
+                //N1	Reference T1 Wikipedia:534366	Barack Obama
+                let target = parts.next().unwrap_or("");
+                let reference = parts.next().unwrap_or("");
+                data.push((
+                    i,
+                    target.to_string(),
+                    "reference".to_string(),
+                    reference.into(),
+                ));
+            }
+            Some('#') => {
+                let target = parts.next().unwrap_or("");
+                let note = fields.get(2).copied().unwrap_or("");
+                data.push((i, target.to_string(), "note".to_string(), note.into()));
+            }
+            _ => {
+                eprintln!(
+                    "Warning: {} line {}: unsupported annotation type, skipping: {}",
+                    filename,
+                    i + 1,
+                    id
+                );
+            }
+        }
+    }
+    for (i, target, key, value) in data {
+        match index.get(&target) {
+            Some(n) => annotations[*n].data.push((key, value)),
+            None => {
+                eprintln!(
+                    "Warning: {} line {}: unknown or unsupported target {}, skipping",
+                    filename,
+                    i + 1,
+                    target
+                );
+            }
+        }
+    }
+
+    let resource_id = store
+        .resource(&Item::from(resource_handle))
+        .and_then(|resource| resource.id().map(|id| id.to_string()))
+        .ok_or("resource must have an ID")?;
+    let to_id = |id: &str| format!("{}#{}", resource_id, id);
+    //text-bound annotations first, so relations can refer to them
+    annotations.sort_by_key(|annotation| annotation.spans.is_empty());
+    let count = annotations.len();
+    for annotation in annotations {
+        let mut selectors: Vec<SelectorBuilder> = if annotation.spans.is_empty() {
+            annotation
+                .arguments
+                .iter()
+                .map(|argument| {
+                    SelectorBuilder::AnnotationSelector(Item::Id(to_id(argument)), None)
+                })
+                .collect()
+        } else {
+            annotation
+                .spans
+                .iter()
+                .map(|(begin, end)| {
+                    SelectorBuilder::TextSelector(
+                        Item::Handle(resource_handle),
+                        Offset::simple(*begin, *end),
+                    )
+                })
+                .collect()
+        };
+        let selector = if selectors.len() == 1 {
+            selectors.pop().expect("there must be a selector")
+        } else {
+            SelectorBuilder::CompositeSelector(selectors)
+        };
+        let mut builder = AnnotationBuilder::new()
+            .with_id(to_id(&annotation.id))
+            .with_target(selector)
+            .with_data_builder(
+                AnnotationDataBuilder::new()
+                    .with_annotationset(Item::Id(set.to_string()))
+                    .with_key(Item::Id("type".to_string()))
+                    .with_value(annotation.label.into()),
+            );
+        for (key, value) in annotation.data {
+            builder = builder.with_data_builder(
+                AnnotationDataBuilder::new()
+                    .with_annotationset(Item::Id(set.to_string()))
+                    .with_key(Item::Id(key))
+                    .with_value(value),
+            );
+        }
+        store
+            .annotate(builder)
+            .map_err(|e| format!("Failed to add annotation {}: {}", annotation.id, e))?;
+    }
+    Ok(count)
+}
+
+/// Checks the text of a text-bound annotation, Brat joins the fragments of discontinuous spans with a space
+fn validate_text(
+    store: &AnnotationStore,
+    resource_handle: TextResourceHandle,
+    spans: &[(usize, usize)],
+    expected: &str,
+    validation: ValidationMode,
+) -> Result<(), String> {
+    if validation == ValidationMode::No {
+        return Ok(());
+    }
+    let resource = store
+        .resource(&Item::from(resource_handle))
+        .ok_or("resource not found")?;
+    let mut fragments = Vec::new();
+    for (begin, end) in spans {
+        fragments.push(
+            resource
+                .text_by_offset(&Offset::simple(*begin, *end))
+                .map_err(|e| format!("{}", e))?,
+        );
+    }
+    let text = fragments.join(" ");
+    if !match validation {
+        ValidationMode::Strict => text == expected,
+        _ => text.to_lowercase() == expected.to_lowercase(),
+    } {
+        return Err(format!(
+            "Text validation failed, Brat expects '{}', data has '{}'",
+            expected, text
+        ));
+    }
+    Ok(())
+}
+
+pub fn brat_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("brat-map")
+            .long("brat-map")
+            .help("For --format brat: TSV file mapping STAM data to Brat labels")
+            .long_help(
+                "For --format brat: TSV file mapping STAM data to Brat labels. Each line has two columns:
+
+1. A filter like set/key or set/key=value (same syntax as --filter)
+2. The Brat label for annotations matching the filter. If empty or *, the value of the data is used as label.
+
+The first matching line determines the label, annotations that match no line are not exported. Other data of an annotation in
+the same set(s) is exported as attributes, notes (key 'note') or normalizations (key 'reference'). If not set, the data
+in set 'brat' with key 'type' is used, as produced by the importer.",
+            )
+            .takes_value(true),
+    ]
+}
+
+/// Determines which annotations are exported to Brat, and with what label
+pub struct BratMapping {
+    rules: Vec<(DataFilter, Option<String>)>,
+}
+
+impl Default for BratMapping {
+    fn default() -> Self {
+        Self {
+            rules: vec![(
+                DataFilter {
+                    set: BRAT_SET.to_string(),
+                    key: "type".to_string(),
+                    op: FilterOperator::Any,
+                    value: None,
+                },
+                None,
+            )],
+        }
+    }
+}
+
+impl BratMapping {
+    pub fn from_file(filename: &str, setdelimiter: &str) -> Result<Self, String> {
+        let f = File::open(filename)
+            .map_err(|e| format!("Error opening Brat mapping {}: {}", filename, e))?;
+        let mut rules = Vec::new();
+        for (i, line) in BufReader::new(f).lines().enumerate() {
+            let line =
+                line.map_err(|e| format!("Error reading Brat mapping {}: {}", filename, e))?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (filter, label) = line.split_once('\t').unwrap_or((line.as_str(), ""));
+            let filter = DataFilter::parse(filter, setdelimiter)
+                .map_err(|e| format!("Error in Brat mapping {} line {}: {}", filename, i + 1, e))?;
+            let label = match label {
+                "" | "*" => None,
+                label => Some(label.to_string()),
+            };
+            rules.push((filter, label));
+        }
+        Ok(Self { rules })
+    }
+
+    /// Returns the label for the annotation, along with the set and key it derives from, or None if it is not to be exported
+    fn label(&self, annotation: &WrappedItem<Annotation>) -> Option<(String, &str, &str)> {
+        self.rules.iter().find_map(|(filter, label)| {
+            if filter.test(annotation) {
+                let label = match label {
+                    Some(label) => label.clone(),
+                    None => filter.value_of(annotation)?,
+                };
+                Some((brat_token(&label), filter.set.as_str(), filter.key.as_str()))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn has_set(&self, set: &str) -> bool {
+        self.rules.iter().any(|(filter, _)| filter.set == set)
+    }
+}
+
+/// Brat labels and attribute values may not contain whitespace
+fn brat_token(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+/// An annotation to be exported to Brat
+struct BratItem {
+    handle: AnnotationHandle,
+    label: String,
+    set: String,
+    key: String,
+    /// Index of the resource, and the spans (begin, end) in it
+    resource: Option<usize>,
+    spans: Vec<(usize, usize)>,
+    /// Annotations this annotation points to
+    targets: Vec<AnnotationHandle>,
+}
+
+/// Exports the annotations selected by the mapping to Brat standoff format, writing a `.txt` and `.ann` file
+/// to `directory` for each resource, named after the resource ID. Annotations on text become entities (T), with a
+/// discontinuous span if they have multiple text selections. Annotations that point to exactly two exported entities
+/// become relations (R). Returns the filenames that were written.
+pub fn to_brat(
+    store: &AnnotationStore,
+    mapping: &BratMapping,
+    directory: &str,
+) -> Result<Vec<String>, String> {
+    let resources: Vec<_> = store.resources().collect();
+    let mut items: Vec<BratItem> = Vec::new();
+    for annotation in store.annotations() {
+        if let Some((label, set, key)) = mapping.label(&annotation) {
+            let mut spans = Vec::new();
+            let mut resource = None;
+            let mut single_resource = true;
+            for textselection in annotation.textselections() {
+                let resource_handle = textselection.resource().handle();
+                let index = resources
+                    .iter()
+                    .position(|resource| resource.handle() == resource_handle);
+                if resource.is_some() && resource != index {
+                    single_resource = false;
+                }
+                resource = index;
+                spans.push((textselection.begin(), textselection.end()));
+            }
+            if !single_resource {
+                eprintln!(
+                    "Warning: annotation {} spans multiple resources, can not be exported to Brat",
+                    annotation.id().unwrap_or("(no id)")
+                );
+                continue;
+            }
+            spans.sort();
+            items.push(BratItem {
+                handle: annotation.handle().expect("annotation must have handle"),
+                label,
+                set: set.to_string(),
+                key: key.to_string(),
+                resource,
+                spans,
+                targets: annotation
+                    .annotations(false, false)
+                    .filter_map(|target| target.handle())
+                    .collect(),
+            });
+        }
+    }
+    let is_relation = |item: &BratItem| item.targets.len() == 2;
+    //relations must point to entities
+    let entities: Vec<AnnotationHandle> = items
+        .iter()
+        .filter(|item| !is_relation(item))
+        .map(|item| item.handle)
+        .collect();
+    let mut relations: Vec<&BratItem> = Vec::new();
+    let mut documents: Vec<Vec<&BratItem>> = vec![Vec::new(); resources.len()];
+    for item in items.iter() {
+        if is_relation(item) && item.targets.iter().all(|target| entities.contains(target)) {
+            relations.push(item);
+        } else if let Some(resource) = item.resource {
+            documents[resource].push(item);
+        }
+    }
+
+    let mut filenames = Vec::new();
+    for (resource, mut entities) in resources.iter().zip(documents) {
+        let resource_id = resource.id().ok_or("resource must have an ID")?;
+        let stem = Path::new(resource_id)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| resource_id.to_string());
+        entities.sort_by_key(|item| item.spans.clone());
+        let mut ann = String::new();
+        let mut ids: HashMap<AnnotationHandle, String> = HashMap::new();
+        let mut texts: HashMap<AnnotationHandle, String> = HashMap::new();
+        let mut counters: HashMap<char, usize> = HashMap::new();
+        let mut next_id = |prefix: char| {
+            let counter = counters.entry(prefix).or_insert(0);
+            *counter += 1;
+            format!("{}{}", prefix, counter)
+        };
+        for item in entities.iter() {
+            let id = next_id('T');
+            let mut fragments = Vec::new();
+            for (begin, end) in item.spans.iter() {
+                fragments.push(
+                    resource
+                        .text_by_offset(&Offset::simple(*begin, *end))
+                        .map_err(|e| format!("{}", e))?
+                        .replace(['\n', '\r', '\t'], " "),
+                );
+            }
+            ann += &format!(
+                "{}\t{} {}\t{}\n",
+                id,
+                item.label,
+                item.spans
+                    .iter()
+                    .map(|(begin, end)| format!("{} {}", begin, end))
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                fragments.join(" ")
+            );
+            ids.insert(item.handle, id);
+            texts.insert(item.handle, fragments.join(" "));
+        }
+        for item in relations.iter() {
+            if let (Some(arg1), Some(arg2)) = (ids.get(&item.targets[0]), ids.get(&item.targets[1]))
+            {
+                let id = next_id('R');
+                ann += &format!("{}\t{} Arg1:{} Arg2:{}\n", id, item.label, arg1, arg2);
+                ids.insert(item.handle, id);
+            }
+        }
+        //other data in the mapped sets
+        for item in entities.iter().chain(relations.iter()) {
+            let id = match ids.get(&item.handle) {
+                Some(id) => id.clone(),
+                None => continue,
+            };
+            let annotation = store
+                .annotation(&Item::Handle(item.handle))
+                .expect("annotation must exist");
+            for data in annotation.data() {
+                let set = data.set().id().unwrap_or("").to_string();
+                let key = data.key().id().unwrap_or("").to_string();
+                if !mapping.has_set(&set) || (set == item.set && key == item.key) {
+                    continue;
+                }
+                match (key.as_str(), data.value()) {
+                    ("note", value) => {
+                        ann += &format!("{}\tAnnotatorNotes {}\t{}\n", next_id('#'), id, value)
+                    }
+                    ("reference", value) if id.starts_with('T') => {
+                        ann += &format!(
+                            "{}\tReference {} {}\t{}\n",
+                            next_id('N'),
+                            id,
+                            brat_token(&value.to_string()),
+                            texts.get(&item.handle).map(|s| s.as_str()).unwrap_or("")
+                        )
+                    }
+                    (_, DataValue::Bool(true)) => {
+                        ann += &format!("{}\t{} {}\n", next_id('A'), brat_token(&key), id)
+                    }
+                    (_, DataValue::Bool(false)) => {}
+                    (_, value) => {
+                        ann += &format!(
+                            "{}\t{} {} {}\n",
+                            next_id('A'),
+                            brat_token(&key),
+                            id,
+                            brat_token(&value.to_string())
+                        )
+                    }
+                }
+            }
+        }
+        if ann.is_empty() {
+            continue;
+        }
+        let base = Path::new(directory).join(&stem);
+        for (extension, content) in [("txt", resource.text()), ("ann", ann.as_str())] {
+            let filename = base
+                .with_extension(extension)
+                .to_string_lossy()
+                .into_owned();
+            let mut f = File::create(&filename)
+                .map_err(|e| format!("Error creating {}: {}", filename, e))?;
+            f.write_all(content.as_bytes())
+                .map_err(|e| format!("Error writing {}: {}", filename, e))?;
+            filenames.push(filename);
+        }
+    }
+    Ok(filenames)
+}
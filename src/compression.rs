@@ -0,0 +1,313 @@
+//! Transparent reading and writing of gzip and zstd compressed files. The compression is detected
+//! from the extension (`.gz` or `.zst`) of the filename.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use stam::{
+    AnnotationDataSetBuilder, AnnotationStore, AnnotationStoreBuilder, AssociatedFile, Config,
+    Configurable, FromJson, TextResourceBuilder, ToJson,
+};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Detects the compression from the extension of the filename, returns `None` for uncompressed files
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        if filename.ends_with(".gz") {
+            Some(Self::Gzip)
+        } else if filename.ends_with(".zst") {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the filename without the compression extension
+pub fn strip_compression_extension(filename: &str) -> &str {
+    filename
+        .strip_suffix(".gz")
+        .or_else(|| filename.strip_suffix(".zst"))
+        .unwrap_or(filename)
+}
+
+/// Opens a file for reading, decompressing it on the fly if it is compressed
+pub fn open_reader(filename: &str) -> Result<Box<dyn Read>, String> {
+    let file = File::open(filename).map_err(|e| format!("Unable to open {}: {}", filename, e))?;
+    let file = BufReader::new(file);
+    Ok(match Compression::from_filename(filename) {
+        Some(Compression::Gzip) => Box::new(GzDecoder::new(file)),
+        Some(Compression::Zstd) => Box::new(
+            zstd::Decoder::with_buffer(file)
+                .map_err(|e| format!("Unable to decompress {}: {}", filename, e))?,
+        ),
+        None => Box::new(file),
+    })
+}
+
+/// A writer that compresses on the fly if needed. Call [`CompressedWriter::finish()`] when done, so
+/// errors in writing the final compressed block are not lost.
+pub enum CompressedWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Write for CompressedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Gzip(writer) => writer.write(buf),
+            Self::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Gzip(writer) => writer.flush(),
+            Self::Zstd(writer) => writer.flush(),
+        }
+    }
+}
+
+impl CompressedWriter {
+    /// Finishes the compression and flushes the underlying file
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Plain(mut writer) => writer.flush(),
+            Self::Gzip(writer) => writer.finish()?.flush(),
+            Self::Zstd(writer) => writer.finish()?.flush(),
+        }
+    }
+}
+
+/// Opens a file for writing, compressing it on the fly if its extension asks for it.
+pub fn open_writer(filename: &str) -> Result<CompressedWriter, String> {
+    let file =
+        File::create(filename).map_err(|e| format!("Unable to create {}: {}", filename, e))?;
+    let file = BufWriter::new(file);
+    Ok(match Compression::from_filename(filename) {
+        Some(Compression::Gzip) => {
+            CompressedWriter::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+        }
+        Some(Compression::Zstd) => CompressedWriter::Zstd(
+            zstd::Encoder::new(file, 0)
+                .map_err(|e| format!("Unable to compress {}: {}", filename, e))?,
+        ),
+        None => CompressedWriter::Plain(file),
+    })
+}
+
+/// Reads and decompresses an entire file into a string
+pub fn read_to_string(filename: &str) -> Result<String, String> {
+    let mut text = String::new();
+    open_reader(filename)?
+        .read_to_string(&mut text)
+        .map_err(|e| format!("Unable to read {}: {}", filename, e))?;
+    Ok(text)
+}
+
+/// Sets the working directory to the directory of the file, as [`AnnotationStore::from_file()`] does,
+/// so `@include` statements are found relative to the store. The configuration offers no setter for this,
+/// but associating the filename with a store updates its configuration accordingly.
+fn with_workdir_of(config: Config, filename: &str) -> Config {
+    if config.workdir().is_some() {
+        return config;
+    }
+    AnnotationStore::new()
+        .with_config(config)
+        .with_filename(filename)
+        .config()
+        .clone()
+}
+
+/// Loads an annotation store from file, like [`AnnotationStore::from_file()`], but also accepts gzip or zstd compressed STAM JSON.
+pub fn load_store(filename: &str, config: Config) -> Result<AnnotationStore, String> {
+    if Compression::from_filename(filename).is_none() {
+        return AnnotationStore::from_file(filename, config).map_err(|e| format!("{}", e));
+    }
+    if strip_compression_extension(filename).ends_with("csv") {
+        return Err(format!(
+            "Unable to load {}: compressed STAM CSV is not supported, only compressed STAM JSON",
+            filename
+        ));
+    }
+    let json = read_to_string(filename)?;
+    let mut store = AnnotationStore::from_str(&json, with_workdir_of(config, filename))
+        .map_err(|e| format!("{}", e))?;
+    store.set_filename(filename);
+    Ok(store)
+}
+
+/// Merges another annotation store from file into this one, like [`AnnotationStore::with_file()`], but also accepts gzip or zstd compressed STAM JSON.
+pub fn merge_store(mut store: AnnotationStore, filename: &str) -> Result<AnnotationStore, String> {
+    if Compression::from_filename(filename).is_none() {
+        return store.with_file(filename).map_err(|e| format!("{}", e));
+    }
+    let json = read_to_string(filename)?;
+    let builder = AnnotationStoreBuilder::from_json_str(&json, store.config().clone())
+        .map_err(|e| format!("{}", e))?;
+    store
+        .merge_from_builder(builder)
+        .map_err(|e| format!("{}", e))?;
+    Ok(store)
+}
+
+/// Saves an annotation store to its associated file, like [`AnnotationStore::save()`], but compresses the STAM JSON if
+/// the filename ends in `.gz` or `.zst`. Standoff files referenced via `@include` are written uncompressed as usual.
+pub fn save_store(store: &AnnotationStore) -> Result<(), String> {
+    match store.filename() {
+        Some(filename) if Compression::from_filename(filename).is_some() => {
+            let compact = match store.config().dataformat() {
+                stam::DataFormat::Json { compact }
+                    if !strip_compression_extension(filename).ends_with("csv") =>
+                {
+                    compact
+                }
+                _ => {
+                    return Err(format!(
+                        "Unable to write {}: compression is only supported for STAM JSON",
+                        filename
+                    ))
+                }
+            };
+            let mut writer = open_writer(filename)?;
+            store
+                .to_json_writer(&mut writer, compact)
+                .map_err(|e| format!("{}", e))?;
+            writer
+                .finish()
+                .map_err(|e| format!("Unable to write {}: {}", filename, e))
+        }
+        _ => store.save().map_err(|e| format!("{}", e)),
+    }
+}
+
+/// Loads a text resource from file, like [`TextResourceBuilder::from_file()`], but also accepts gzip or zstd compressed
+/// plain text or STAM JSON. A compressed plain text is kept in the store itself (the ID is the filename without the
+/// compression extension), as it can not be referenced via `@include`.
+pub fn load_resource(filename: &str, config: Config) -> Result<TextResourceBuilder, String> {
+    if Compression::from_filename(filename).is_none() {
+        return TextResourceBuilder::from_file(filename, config).map_err(|e| format!("{}", e));
+    }
+    let text = read_to_string(filename)?;
+    let id = strip_compression_extension(filename);
+    if id.ends_with(".json") {
+        TextResourceBuilder::from_json_str(&text, config).map_err(|e| format!("{}", e))
+    } else {
+        let id = Path::new(id)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| id.to_string());
+        Ok(TextResourceBuilder::new().with_id(id).with_text(text))
+    }
+}
+
+/// Loads an annotation dataset from file, like [`AnnotationDataSetBuilder::from_file()`], but also accepts gzip or zstd compressed STAM JSON.
+pub fn load_annotationset(
+    filename: &str,
+    config: Config,
+) -> Result<AnnotationDataSetBuilder<'static>, String> {
+    if Compression::from_filename(filename).is_none() {
+        return AnnotationDataSetBuilder::from_file(filename, config).map_err(|e| format!("{}", e));
+    }
+    let json = read_to_string(filename)?;
+    AnnotationDataSetBuilder::from_json_str(&json, config).map_err(|e| format!("{}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stam::{Item, StoreFor, Text, TextResource};
+
+    fn tempfile(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("stamtools-compression-{}", name));
+        path.to_str().expect("valid path").to_string()
+    }
+
+    fn roundtrip(filename: &str) {
+        let text = "The quick brown fox jumps over the lazy dog.\n".repeat(100);
+        let mut writer = open_writer(filename).unwrap();
+        writer.write_all(text.as_bytes()).unwrap();
+        writer.finish().unwrap();
+        assert_eq!(read_to_string(filename).unwrap(), text);
+    }
+
+    #[test]
+    fn detect_compression() {
+        assert_eq!(
+            Compression::from_filename("store.json.gz"),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::from_filename("store.json.zst"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::from_filename("store.json"), None);
+        assert_eq!(Compression::from_filename("store.gzip"), None);
+    }
+
+    #[test]
+    fn strip_extension() {
+        assert_eq!(strip_compression_extension("store.json.gz"), "store.json");
+        assert_eq!(strip_compression_extension("store.json.zst"), "store.json");
+        assert_eq!(strip_compression_extension("store.json"), "store.json");
+    }
+
+    #[test]
+    fn roundtrip_plain() {
+        roundtrip(&tempfile("plain.txt"));
+    }
+
+    #[test]
+    fn roundtrip_gzip() {
+        let filename = tempfile("text.txt.gz");
+        roundtrip(&filename);
+        //the file must really be compressed
+        let bytes = std::fs::read(&filename).unwrap();
+        assert_eq!(&bytes[..2], &[0x1f, 0x8b]);
+        assert!(bytes.len() < 4500);
+    }
+
+    #[test]
+    fn roundtrip_zstd() {
+        let filename = tempfile("text.txt.zst");
+        roundtrip(&filename);
+        let bytes = std::fs::read(&filename).unwrap();
+        assert_eq!(&bytes[..4], &[0x28, 0xb5, 0x2f, 0xfd]);
+        assert!(bytes.len() < 4500);
+    }
+
+    #[test]
+    fn roundtrip_store() {
+        let filename = tempfile("store.json.zst");
+        let mut store = AnnotationStore::new()
+            .with_id("test".to_string())
+            .with_filename(&filename);
+        store
+            .insert(TextResource::from_string(
+                "hello".to_string(),
+                "Hello world".to_string(),
+                Config::default(),
+            ))
+            .unwrap();
+        save_store(&store).unwrap();
+        let store = load_store(&filename, Config::default()).unwrap();
+        let resource = store.resource(&Item::IdRef("hello")).unwrap();
+        assert_eq!(resource.text(), "Hello world");
+    }
+
+    #[test]
+    fn missing_file() {
+        let err = read_to_string(&tempfile("missing.txt.gz")).unwrap_err();
+        assert!(err.contains("missing.txt.gz"), "{}", err);
+    }
+}
@@ -0,0 +1,273 @@
+use clap::Arg;
+use stam::{AnnotationStore, Regex, Storable, Text, TextResource, WrappedItem};
+use std::io::Write;
+
+use crate::filter::DataFilter;
+
+pub fn concordance_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("regex")
+            .long("regex")
+            .short('e')
+            .help("Regular expression to search for in the text. If not set, the text of the annotations selected by --filter is used as keyword instead")
+            .takes_value(true),
+        Arg::with_name("width")
+            .long("width")
+            .env("STAM_CONCORDANCE_WIDTH")
+            .short('w')
+            .help("The size of the left and right context, in characters or tokens (see --unit)")
+            .takes_value(true)
+            .default_value("40"),
+        Arg::with_name("unit")
+            .long("unit")
+            .help("The unit in which the context width is expressed: chars or tokens (whitespace separated)")
+            .takes_value(true)
+            .possible_values(["chars", "tokens"])
+            .default_value("chars"),
+        Arg::with_name("sort")
+            .long("sort")
+            .help("Sort the listing by the left context (the nearest word first), the keyword, or the right context, rather than in text order")
+            .takes_value(true)
+            .possible_values(["text", "left", "keyword", "right"])
+            .default_value("text"),
+        Arg::with_name("format")
+            .long("format")
+            .env("STAM_CONCORDANCE_FORMAT")
+            .short('F')
+            .help("Output format: text (aligned columns) or tsv (with resource and offsets)")
+            .takes_value(true)
+            .possible_values(["text", "tsv"])
+            .default_value("text"),
+    ]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContextUnit {
+    Chars,
+    Tokens,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortOrder {
+    Text,
+    Left,
+    Keyword,
+    Right,
+}
+
+impl TryFrom<&str> for ContextUnit {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "chars" => Ok(Self::Chars),
+            "tokens" => Ok(Self::Tokens),
+            _ => Err(format!("Invalid context unit: {}", value)),
+        }
+    }
+}
+
+impl TryFrom<&str> for SortOrder {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "text" => Ok(Self::Text),
+            "left" => Ok(Self::Left),
+            "keyword" => Ok(Self::Keyword),
+            "right" => Ok(Self::Right),
+            _ => Err(format!("Invalid sort order: {}", value)),
+        }
+    }
+}
+
+/// A single line in a keyword-in-context listing
+pub struct ConcordanceLine {
+    pub resource: String,
+    pub begin: usize,
+    pub end: usize,
+    pub left: String,
+    pub keyword: String,
+    pub right: String,
+    /// Index of the resource in the store, for sorting in text order
+    resource_index: usize,
+}
+
+/// Builds a keyword-in-context listing for all matches of the regular expression or, if there
+/// is none, for all annotations matching any of the filters.
+pub fn concordance(
+    store: &AnnotationStore,
+    expression: Option<&Regex>,
+    filters: &[DataFilter],
+    width: usize,
+    unit: ContextUnit,
+    sort: SortOrder,
+) -> Result<Vec<ConcordanceLine>, String> {
+    let mut lines = Vec::new();
+    let resources: Vec<_> = store.resources().collect();
+    if let Some(expression) = expression {
+        for (resource_index, resource) in resources.iter().enumerate() {
+            let matches = resource
+                .unwrap()
+                .find_text_regex(std::slice::from_ref(expression), None, false)
+                .map_err(|e| format!("{}", e))?;
+            for textmatch in matches {
+                for textselection in textmatch.textselections() {
+                    lines.push(line(
+                        resource,
+                        resource_index,
+                        textselection.begin(),
+                        textselection.end(),
+                        width,
+                        unit,
+                    )?);
+                }
+            }
+        }
+    } else if !filters.is_empty() {
+        for annotation in store
+            .annotations()
+            .filter(|annotation| filters.iter().any(|filter| filter.test(annotation)))
+        {
+            for textselection in annotation.textselections() {
+                let resource_handle = textselection.resource().handle();
+                let resource_index = resources
+                    .iter()
+                    .position(|resource| resource.handle() == resource_handle)
+                    .expect("resource must exist");
+                lines.push(line(
+                    &resources[resource_index],
+                    resource_index,
+                    textselection.begin(),
+                    textselection.end(),
+                    width,
+                    unit,
+                )?);
+            }
+        }
+    } else {
+        return Err("Please specify a regular expression (--regex) or a filter (--filter)".into());
+    }
+    match sort {
+        SortOrder::Text => lines.sort_by_key(|line| (line.resource_index, line.begin, line.end)),
+        SortOrder::Left => lines.sort_by_cached_key(|line| {
+            //nearest word first
+            let words: Vec<String> = line
+                .left
+                .split_whitespace()
+                .rev()
+                .map(|w| w.to_lowercase())
+                .collect();
+            (words, line.keyword.to_lowercase())
+        }),
+        SortOrder::Keyword => lines
+            .sort_by_cached_key(|line| (line.keyword.to_lowercase(), line.right.to_lowercase())),
+        SortOrder::Right => lines
+            .sort_by_cached_key(|line| (line.right.to_lowercase(), line.keyword.to_lowercase())),
+    }
+    Ok(lines)
+}
+
+/// Extracts the keyword and its context from the resource
+pub(crate) fn line(
+    resource: &WrappedItem<TextResource>,
+    resource_index: usize,
+    begin: usize,
+    end: usize,
+    width: usize,
+    unit: ContextUnit,
+) -> Result<ConcordanceLine, String> {
+    let text = resource.text();
+    let bytebegin = resource.utf8byte(begin).map_err(|e| format!("{}", e))?;
+    let byteend = resource.utf8byte(end).map_err(|e| format!("{}", e))?;
+    let (left, right) = match unit {
+        ContextUnit::Chars => {
+            let left: Vec<char> = text[..bytebegin].chars().rev().take(width).collect();
+            (
+                left.into_iter().rev().collect(),
+                text[byteend..].chars().take(width).collect(),
+            )
+        }
+        ContextUnit::Tokens => {
+            let mut left: Vec<&str> = text[..bytebegin]
+                .split_whitespace()
+                .rev()
+                .take(width)
+                .collect();
+            left.reverse();
+            let right: Vec<&str> = text[byteend..].split_whitespace().take(width).collect();
+            //preserve the whitespace (or lack thereof) adjacent to the keyword
+            let mut left = left.join(" ");
+            if text[..bytebegin].ends_with(char::is_whitespace) && !left.is_empty() {
+                left.push(' ');
+            }
+            let mut right = right.join(" ");
+            if text[byteend..].starts_with(char::is_whitespace) && !right.is_empty() {
+                right.insert(0, ' ');
+            }
+            (left, right)
+        }
+    };
+    Ok(ConcordanceLine {
+        resource: resource.id().unwrap_or("(none)").to_string(),
+        begin,
+        end,
+        left: normalize_whitespace(&left),
+        keyword: normalize_whitespace(&text[bytebegin..byteend]),
+        right: normalize_whitespace(&right),
+        resource_index,
+    })
+}
+
+/// Replaces newlines and tabs by spaces so each match fits on one line
+fn normalize_whitespace(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .collect()
+}
+
+/// Writes the listing with the keywords aligned in one column
+pub fn print_concordance(writer: &mut impl Write, lines: &[ConcordanceLine]) -> Result<(), String> {
+    let leftwidth = lines
+        .iter()
+        .map(|line| line.left.chars().count())
+        .max()
+        .unwrap_or(0);
+    let keywordwidth = lines
+        .iter()
+        .map(|line| line.keyword.chars().count())
+        .max()
+        .unwrap_or(0);
+    for line in lines {
+        writeln!(
+            writer,
+            "{:>leftwidth$} | {:<keywordwidth$} | {}",
+            line.left,
+            line.keyword,
+            line.right,
+            leftwidth = leftwidth,
+            keywordwidth = keywordwidth
+        )
+        .map_err(|e| format!("{}", e))?;
+    }
+    Ok(())
+}
+
+/// Writes the listing as TSV, with the resource and offsets of each keyword
+pub fn print_concordance_tsv(
+    writer: &mut impl Write,
+    lines: &[ConcordanceLine],
+) -> Result<(), String> {
+    writeln!(
+        writer,
+        "TextResource\tBeginOffset\tEndOffset\tLeft\tKeyword\tRight"
+    )
+    .map_err(|e| format!("{}", e))?;
+    for line in lines {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            line.resource, line.begin, line.end, line.left, line.keyword, line.right
+        )
+        .map_err(|e| format!("{}", e))?;
+    }
+    Ok(())
+}
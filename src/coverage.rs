@@ -0,0 +1,236 @@
+use clap::Arg;
+use stam::{
+    AnnotationBuilder, AnnotationDataBuilder, AnnotationStore, Item, Offset, SelectorBuilder,
+    Storable, Text,
+};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::filter::DataFilter;
+
+pub fn coverage_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("min-gap")
+            .long("min-gap")
+            .help(
+                "Only report uncovered stretches of text of at least this many characters as gaps",
+            )
+            .takes_value(true)
+            .default_value("1"),
+        Arg::with_name("count-whitespace")
+            .long("count-whitespace")
+            .help("Count whitespace as text that should be covered. By default whitespace is ignored, so the space between tokens is not a gap and does not lower the coverage"),
+        Arg::with_name("list")
+            .long("list")
+            .help("Instead of the coverage per resource, list the gaps or the overlaps (text covered by more than one annotation) with their offsets and text")
+            .takes_value(true)
+            .possible_values(["gaps", "overlaps"]),
+        Arg::with_name("annotate-gaps")
+            .long("annotate-gaps")
+            .help("Add an annotation for each gap, with key 'type' and value 'gap' in this annotation set, and save the annotation store")
+            .takes_value(true),
+    ]
+}
+
+/// How much of the text of a resource is covered by the selected annotations
+#[derive(Clone, Debug)]
+pub struct Coverage {
+    pub resource: String,
+    /// Number of characters that count (excludes whitespace unless it is counted)
+    pub length: usize,
+    /// Number of those characters covered by at least one annotation
+    pub covered: usize,
+    pub annotations: usize,
+    /// Uncovered stretches of text (offsets in unicode points)
+    pub gaps: Vec<(usize, usize)>,
+    /// Stretches of text covered by more than one annotation (offsets in unicode points)
+    pub overlaps: Vec<(usize, usize)>,
+}
+
+impl Coverage {
+    /// The fraction of the characters that is covered
+    pub fn fraction(&self) -> f64 {
+        if self.length == 0 {
+            0.0
+        } else {
+            self.covered as f64 / self.length as f64
+        }
+    }
+}
+
+/// Computes, for each resource, how much of the text is covered by the annotations matching any of the filters.
+/// Gaps are the maximal uncovered stretches of at least `min_gap` characters; unless `count_whitespace` is set,
+/// whitespace is not counted and gaps are trimmed, so a gap consisting only of whitespace is no gap.
+pub fn coverage(
+    store: &AnnotationStore,
+    filters: &[DataFilter],
+    min_gap: usize,
+    count_whitespace: bool,
+) -> Vec<Coverage> {
+    //the number of annotations covering each character, per resource
+    let mut depths: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut annotations: BTreeMap<String, usize> = BTreeMap::new();
+    for resource in store.resources() {
+        let resource_id = resource.id().unwrap_or("(none)").to_string();
+        depths.insert(resource_id.clone(), vec![0; resource.textlen()]);
+        annotations.insert(resource_id, 0);
+    }
+    for annotation in store
+        .annotations()
+        .filter(|annotation| filters.iter().any(|filter| filter.test(annotation)))
+    {
+        let mut counted = false;
+        for textselection in annotation.textselections() {
+            let resource_id = textselection.resource().id().unwrap_or("(none)");
+            if let Some(depth) = depths.get_mut(resource_id) {
+                for d in depth[textselection.begin()..textselection.end()].iter_mut() {
+                    *d += 1;
+                }
+            }
+            if !counted {
+                *annotations
+                    .get_mut(resource_id)
+                    .expect("resource must exist") += 1;
+                counted = true;
+            }
+        }
+    }
+    let mut result = Vec::new();
+    for resource in store.resources() {
+        let resource_id = resource.id().unwrap_or("(none)").to_string();
+        let depth = &depths[&resource_id];
+        let chars: Vec<char> = resource.text().chars().collect();
+        let counts = |i: usize| count_whitespace || !chars[i].is_whitespace();
+        let mut coverage = Coverage {
+            resource: resource_id.clone(),
+            length: (0..chars.len()).filter(|i| counts(*i)).count(),
+            covered: (0..chars.len())
+                .filter(|i| counts(*i) && depth[*i] > 0)
+                .count(),
+            annotations: annotations[&resource_id],
+            gaps: Vec::new(),
+            overlaps: Vec::new(),
+        };
+        //maximal runs of characters for which the predicate holds
+        let runs = |predicate: &dyn Fn(usize) -> bool| {
+            let mut runs = Vec::new();
+            let mut begin: Option<usize> = None;
+            for i in 0..=chars.len() {
+                match (begin, i < chars.len() && predicate(i)) {
+                    (None, true) => begin = Some(i),
+                    (Some(b), false) => {
+                        runs.push((b, i));
+                        begin = None;
+                    }
+                    _ => {}
+                }
+            }
+            runs
+        };
+        for (mut begin, mut end) in runs(&|i| depth[i] == 0) {
+            while begin < end && !counts(begin) {
+                begin += 1;
+            }
+            while end > begin && !counts(end - 1) {
+                end -= 1;
+            }
+            if end > begin && end - begin >= min_gap {
+                coverage.gaps.push((begin, end));
+            }
+        }
+        coverage.overlaps = runs(&|i| depth[i] > 1);
+        result.push(coverage);
+    }
+    result
+}
+
+/// Outputs the coverage per resource as TSV
+pub fn print_coverage(writer: &mut impl Write, coverages: &[Coverage]) -> Result<(), String> {
+    let mut write = |line: String| writeln!(writer, "{}", line).map_err(|e| format!("{}", e));
+    write("TextResource\tLength\tCovered\tCoverage\tAnnotations\tGaps\tOverlaps".to_string())?;
+    for coverage in coverages {
+        write(format!(
+            "{}\t{}\t{}\t{:.4}\t{}\t{}\t{}",
+            coverage.resource,
+            coverage.length,
+            coverage.covered,
+            coverage.fraction(),
+            coverage.annotations,
+            coverage.gaps.len(),
+            coverage.overlaps.len()
+        ))?;
+    }
+    Ok(())
+}
+
+/// Outputs the gaps (or, if `gaps` is false, the overlaps) as TSV, with their offsets and text
+pub fn print_coverage_spans(
+    writer: &mut impl Write,
+    store: &AnnotationStore,
+    coverages: &[Coverage],
+    gaps: bool,
+) -> Result<(), String> {
+    let mut write = |line: String| writeln!(writer, "{}", line).map_err(|e| format!("{}", e));
+    write("TextResource\tBeginOffset\tEndOffset\tText".to_string())?;
+    for coverage in coverages {
+        let resource = store
+            .resource(&Item::IdRef(coverage.resource.as_str()))
+            .ok_or_else(|| format!("Resource {} not found", coverage.resource))?;
+        let spans = if gaps {
+            &coverage.gaps
+        } else {
+            &coverage.overlaps
+        };
+        for (begin, end) in spans {
+            let text = resource
+                .text_by_offset(&Offset::simple(*begin, *end))
+                .map_err(|e| format!("{}", e))?;
+            //whitespace is not allowed inside a TSV cell
+            let text: String = text
+                .chars()
+                .map(|c| if c.is_whitespace() { ' ' } else { c })
+                .collect();
+            write(format!(
+                "{}\t{}\t{}\t{}",
+                coverage.resource, begin, end, text
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/// Adds an annotation for each gap, with key `type` and value `gap` in the set `set_id`. Gaps that were already annotated
+/// (by ID, which is derived from the resource and the offsets) are skipped. Returns the number of annotations added.
+pub fn annotate_gaps(
+    store: &mut AnnotationStore,
+    coverages: &[Coverage],
+    set_id: &str,
+) -> Result<usize, String> {
+    let mut count = 0;
+    for coverage in coverages {
+        for (begin, end) in coverage.gaps.iter() {
+            let id = format!("{}.gap.{}-{}", coverage.resource, begin, end);
+            if store.annotation(&Item::IdRef(id.as_str())).is_some() {
+                continue;
+            }
+            store
+                .annotate(
+                    AnnotationBuilder::new()
+                        .with_id(id)
+                        .with_target(SelectorBuilder::TextSelector(
+                            Item::IdRef(coverage.resource.as_str()),
+                            Offset::simple(*begin, *end),
+                        ))
+                        .with_data_builder(
+                            AnnotationDataBuilder::new()
+                                .with_annotationset(Item::IdRef(set_id))
+                                .with_key(Item::IdRef("type"))
+                                .with_value("gap".into()),
+                        ),
+                )
+                .map_err(|e| format!("Error adding annotation: {}", e))?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,207 @@
+use clap::App;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Returns the location of the configuration file: `$STAM_CONFIG` if set, otherwise `stam-tools.toml`
+/// in `$XDG_CONFIG_HOME` or `~/.config`
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("STAM_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("stam-tools.toml"))
+}
+
+/// Parses a configuration file with default values for command line options, in a small subset of TOML:
+/// `key = value` pairs, where the value is a (double) quoted string, a boolean or a number, optionally in
+/// `[section]` tables named after a subcommand. Returns the corresponding environment variables: `STAM_`
+/// followed by the section (if any) and the key, in uppercase and with hyphens replaced by underscores.
+/// For example, `format` in section `export` becomes `STAM_EXPORT_FORMAT`.
+pub fn parse_config(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut section: Option<String> = None;
+    let mut vars = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            section = Some(line[1..line.len() - 1].trim().to_string());
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected key = value", i + 1))?;
+        let key = key.trim().trim_matches('"');
+        let value = parse_value(value.trim()).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        let name = match section.as_ref() {
+            Some(section) => format!("STAM_{}_{}", section, key),
+            None => format!("STAM_{}", key),
+        };
+        vars.push((name.to_uppercase().replace('-', "_"), value));
+    }
+    Ok(vars)
+}
+
+fn parse_value(value: &str) -> Result<String, String> {
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut result = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    let rest = chars.as_str().trim();
+                    if !rest.is_empty() && !rest.starts_with('#') {
+                        return Err(format!("unexpected text after string: {}", rest));
+                    }
+                    return Ok(result);
+                }
+                '\\' => match chars.next() {
+                    Some('t') => result.push('\t'),
+                    Some('n') => result.push('\n'),
+                    Some('\\') => result.push('\\'),
+                    Some('"') => result.push('"'),
+                    Some(c) => return Err(format!("unsupported escape sequence \\{}", c)),
+                    None => return Err("unterminated string".to_string()),
+                },
+                c => result.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    } else {
+        //booleans, numbers: strip trailing comments
+        let value = value.split('#').next().unwrap_or("").trim();
+        if value.is_empty() {
+            Err("missing value".to_string())
+        } else {
+            Ok(value.to_string())
+        }
+    }
+}
+
+/// Loads the configuration file (if it exists) and sets the environment variables it describes, unless they are
+/// already set. This must be called before the command line arguments are defined, as that is when the defaults
+/// are read from the environment. Explicit command line options still take precedence. Returns the names of all
+/// variables in the configuration, so they can be checked against the arguments with [`unused_config_vars()`].
+pub fn load_config_defaults() -> Result<Vec<String>, String> {
+    let path = match config_path() {
+        Some(path) if path.exists() => path,
+        _ => return Ok(Vec::new()),
+    };
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Error reading configuration {}: {}", path.display(), e))?;
+    let mut names = Vec::new();
+    for (name, value) in parse_config(&content)
+        .map_err(|e| format!("Error in configuration {}: {}", path.display(), e))?
+    {
+        if env::var_os(&name).is_none() {
+            env::set_var(&name, value);
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Returns the configuration variables that no argument of the command (or any of its subcommands) reads its
+/// default from, such as misspelled keys or options that do not take defaults
+pub fn unused_config_vars<'a>(names: &'a [String], app: &App) -> Vec<&'a str> {
+    fn collect(app: &App, used: &mut HashSet<String>) {
+        for arg in app.get_arguments() {
+            if let Some(name) = arg.get_env() {
+                used.insert(name.to_string_lossy().into_owned());
+            }
+        }
+        for subcommand in app.get_subcommands() {
+            collect(subcommand, used);
+        }
+    }
+    let mut used = HashSet::new();
+    collect(app, &mut used);
+    names
+        .iter()
+        .filter(|name| !used.contains(name.as_str()))
+        .map(|name| name.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn sections_and_keys() {
+        let vars = parse_config(
+            "# defaults\nverbose = true\n\n[export]\nformat = \"csv\"\nset-delimiter = \"::\"\n[ tag ]\nmax-passes = 3 # comment\n",
+        )
+        .unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                var("STAM_VERBOSE", "true"),
+                var("STAM_EXPORT_FORMAT", "csv"),
+                var("STAM_EXPORT_SET_DELIMITER", "::"),
+                var("STAM_TAG_MAX_PASSES", "3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_strings() {
+        assert_eq!(parse_value("\"a\\tb\\n\"").unwrap(), "a\tb\n");
+        assert_eq!(parse_value("\"say \\\"hi\\\"\"").unwrap(), "say \"hi\"");
+        assert_eq!(parse_value("\"a # b\" # comment").unwrap(), "a # b");
+        assert_eq!(parse_value("\"\"").unwrap(), "");
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert!(parse_value("\"unterminated").is_err());
+        assert!(parse_value("\"bad \\x escape\"").is_err());
+        assert!(parse_value("\"a\" b").is_err());
+        assert!(parse_value("# nothing").is_err());
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let err = parse_config("[export]\nformat\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+        let err = parse_config("format = \"csv\n").unwrap_err();
+        assert!(err.starts_with("line 1:"), "{}", err);
+    }
+
+    #[test]
+    fn unused_vars() {
+        use clap::{Arg, SubCommand};
+        let app = App::new("test")
+            .arg(
+                Arg::with_name("verbose")
+                    .long("verbose")
+                    .env("STAM_VERBOSE"),
+            )
+            .subcommand(
+                SubCommand::with_name("export").arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .env("STAM_EXPORT_FORMAT"),
+                ),
+            );
+        let vars = parse_config(
+            "verbose = true\n[export]\nformat = \"csv\"\nfromat = \"tsv\"\n[tag]\nrules = \"a\"\n",
+        )
+        .unwrap();
+        let names: Vec<String> = vars.into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            unused_config_vars(&names, &app),
+            vec!["STAM_EXPORT_FROMAT", "STAM_TAG_RULES"]
+        );
+    }
+}
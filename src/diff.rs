@@ -0,0 +1,431 @@
+use clap::Arg;
+use stam::{
+    AnnotationBuilder, AnnotationDataBuilder, AnnotationStore, Item, Offset, SelectorBuilder,
+    Storable, Text,
+};
+use std::path::Path;
+
+pub fn diff_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("old")
+            .long("old")
+            .help("The old version of the text: the ID of a resource in the store, or a plain text file to add as a new resource")
+            .takes_value(true)
+            .required(true),
+        Arg::with_name("new")
+            .long("new")
+            .help("The new version of the text: the ID of a resource in the store, or a plain text file to add as a new resource")
+            .takes_value(true)
+            .required(true),
+        Arg::with_name("unit")
+            .long("unit")
+            .help("The unit to compare: chars, words (runs of letters and digits, runs of whitespace, and any other single characters), or lines")
+            .takes_value(true)
+            .possible_values(["chars", "words", "lines"])
+            .default_value("words"),
+        Arg::with_name("annotationset")
+            .long("annotationset")
+            .help("The ID of the annotation set for the annotations that record the differences (with keys 'type', 'old' and 'new')")
+            .takes_value(true)
+            .default_value("diff"),
+        Arg::with_name("no-pivot").long("no-pivot").help(
+        "Only record the edits, do not record the unchanged segments that link the two versions",
+    ),
+    ]
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DiffUnit {
+    Chars,
+    Words,
+    Lines,
+}
+
+impl TryFrom<&str> for DiffUnit {
+    type Error = String;
+    fn try_from(val: &str) -> Result<Self, Self::Error> {
+        match val.to_lowercase().as_str() {
+            "chars" | "characters" => Ok(Self::Chars),
+            "words" | "tokens" => Ok(Self::Words),
+            "lines" => Ok(Self::Lines),
+            _ => Err(format!(
+                "Unknown diff unit: {}, expected chars, words or lines",
+                val
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SegmentType {
+    /// Unchanged text
+    Equal,
+    Insertion,
+    Deletion,
+    Substitution,
+}
+
+impl SegmentType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Equal => "equal",
+            Self::Insertion => "insertion",
+            Self::Deletion => "deletion",
+            Self::Substitution => "substitution",
+        }
+    }
+}
+
+/// A segment of the old text and the corresponding segment of the new text (offsets in unicode points)
+#[derive(Clone, Debug)]
+pub struct DiffSegment {
+    pub segmenttype: SegmentType,
+    pub old: (usize, usize),
+    pub new: (usize, usize),
+}
+
+/// Splits a text into tokens, returns their begin and end offsets (in unicode points) along with the token itself.
+/// The tokens together cover the entire text.
+fn tokenize(text: &str, unit: DiffUnit) -> Vec<(usize, usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut begin = 0; //in unicode points
+    let mut beginbyte = 0;
+    let mut prev: Option<char> = None;
+    for (charpos, (bytepos, c)) in text.char_indices().enumerate() {
+        let boundary = match (unit, prev) {
+            (_, None) => false,
+            (DiffUnit::Chars, _) => true,
+            (DiffUnit::Lines, Some(prev)) => prev == '\n',
+            (DiffUnit::Words, Some(prev)) => {
+                !((prev.is_alphanumeric() && c.is_alphanumeric())
+                    || (prev.is_whitespace() && c.is_whitespace()))
+            }
+        };
+        if boundary {
+            tokens.push((begin, charpos, &text[beginbyte..bytepos]));
+            begin = charpos;
+            beginbyte = bytepos;
+        }
+        prev = Some(c);
+    }
+    if beginbyte < text.len() {
+        tokens.push((
+            begin,
+            begin + text[beginbyte..].chars().count(),
+            &text[beginbyte..],
+        ));
+    }
+    tokens
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Computes the shortest edit script between two sequences (Myers' algorithm)
+fn edit_script(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    //the relevant part of v (diagonals -d..=d) at the start of each round d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'outer: for d in 0..=max {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d
+                || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize])
+            {
+                v[(offset + k + 1) as usize]
+            } else {
+                v[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+            if x >= n && y >= m {
+                break 'outer;
+            }
+        }
+    }
+    //backtrack
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let get = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 { 0 } else { get(prev_k) };
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                ops.push(Op::Insert);
+            } else {
+                ops.push(Op::Delete);
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.reverse();
+    ops
+}
+
+/// Compares two texts and returns the segments of unchanged and changed text, in order
+pub fn diff_texts(old: &str, new: &str, unit: DiffUnit) -> Vec<DiffSegment> {
+    let oldtokens = tokenize(old, unit);
+    let newtokens = tokenize(new, unit);
+    let a: Vec<&str> = oldtokens.iter().map(|(_, _, token)| *token).collect();
+    let b: Vec<&str> = newtokens.iter().map(|(_, _, token)| *token).collect();
+    let oldlen = oldtokens.last().map(|(_, end, _)| *end).unwrap_or(0);
+    let newlen = newtokens.last().map(|(_, end, _)| *end).unwrap_or(0);
+    //offset (in unicode points) of the token at the given index
+    let oldpos = |i: usize| oldtokens.get(i).map(|x| x.0).unwrap_or(oldlen);
+    let newpos = |j: usize| newtokens.get(j).map(|x| x.0).unwrap_or(newlen);
+
+    let mut segments: Vec<DiffSegment> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let ops = edit_script(&a, &b);
+    let mut ops = ops.iter().peekable();
+    while let Some(op) = ops.next() {
+        let (begin_i, begin_j) = (i, j);
+        let equal = *op == Op::Equal;
+        let mut advance = |op: &Op| match op {
+            Op::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        };
+        advance(op);
+        while let Some(op) = ops.next_if(|next| (**next == Op::Equal) == equal) {
+            advance(op);
+        }
+        let segmenttype = match (equal, begin_i == i, begin_j == j) {
+            (true, _, _) => SegmentType::Equal,
+            (false, true, _) => SegmentType::Insertion,
+            (false, _, true) => SegmentType::Deletion,
+            (false, false, false) => SegmentType::Substitution,
+        };
+        segments.push(DiffSegment {
+            segmenttype,
+            old: (oldpos(begin_i), oldpos(i)),
+            new: (newpos(begin_j), newpos(j)),
+        });
+    }
+    segments
+}
+
+/// Returns the ID of the resource with the given ID, or if there is none, adds the file with that name as a new resource
+pub fn get_or_add_resource(store: &mut AnnotationStore, resource: &str) -> Result<String, String> {
+    if store.resource(&Item::IdRef(resource)).is_some() {
+        Ok(resource.to_string())
+    } else if Path::new(resource).exists() {
+        let handle = store
+            .add_resource_from_file(resource)
+            .map_err(|e| format!("Error loading resource {}: {}", resource, e))?;
+        Ok(store
+            .resource(&Item::Handle(handle))
+            .and_then(|resource| resource.id().map(|id| id.to_string()))
+            .unwrap_or_else(|| resource.to_string()))
+    } else {
+        Err(format!(
+            "Resource {} not found in the store, nor as a file",
+            resource
+        ))
+    }
+}
+
+/// Compares the texts of two resources and records the differences as annotations in the set `set_id`. Each annotation
+/// has a [`stam::Selector::DirectionalSelector`] pointing from a segment of the old text to the corresponding segment of the new text,
+/// the type of the segment (key `type`: insertion, deletion or substitution) and the old and new texts (keys `old` and `new`).
+/// Unless `pivot` is false, the unchanged segments are recorded as well (type `equal`), so that together the annotations
+/// link every part of the old text to the new one.
+///
+/// Returns the number of edits and the number of annotations added.
+pub fn annotate_from_diff(
+    store: &mut AnnotationStore,
+    old_id: &str,
+    new_id: &str,
+    unit: DiffUnit,
+    set_id: &str,
+    pivot: bool,
+    verbose: bool,
+) -> Result<(usize, usize), String> {
+    let text = |resource_id: &str| {
+        store
+            .resource(&Item::IdRef(resource_id))
+            .map(|resource| resource.text().to_string())
+            .ok_or_else(|| format!("Resource {} not found", resource_id))
+    };
+    let (oldtext, newtext) = (text(old_id)?, text(new_id)?);
+    let id = |i: usize| format!("{}.{}.diff{}", old_id, new_id, i + 1);
+    if store.annotation(&Item::Id(id(0))).is_some() {
+        return Err(format!(
+            "The differences between {} and {} were already recorded",
+            old_id, new_id
+        ));
+    }
+    let segments = diff_texts(&oldtext, &newtext, unit);
+    let oldchars: Vec<char> = oldtext.chars().collect();
+    let newchars: Vec<char> = newtext.chars().collect();
+    let mut edits = 0;
+    let mut count = 0;
+    for (i, segment) in segments.iter().enumerate() {
+        if segment.segmenttype != SegmentType::Equal {
+            edits += 1;
+        } else if !pivot {
+            continue;
+        }
+        let old: String = oldchars[segment.old.0..segment.old.1].iter().collect();
+        let new: String = newchars[segment.new.0..segment.new.1].iter().collect();
+        if verbose && segment.segmenttype != SegmentType::Equal {
+            eprintln!(
+                "{}\t{}-{}\t{}-{}\t{:?}\t{:?}",
+                segment.segmenttype.as_str(),
+                segment.old.0,
+                segment.old.1,
+                segment.new.0,
+                segment.new.1,
+                old,
+                new
+            );
+        }
+        let data = |key: &'static str| {
+            AnnotationDataBuilder::new()
+                .with_annotationset(Item::IdRef(set_id))
+                .with_key(Item::IdRef(key))
+        };
+        store
+            .annotate(
+                AnnotationBuilder::new()
+                    .with_id(id(i))
+                    .with_target(SelectorBuilder::DirectionalSelector(vec![
+                        SelectorBuilder::TextSelector(
+                            Item::IdRef(old_id),
+                            Offset::simple(segment.old.0, segment.old.1),
+                        ),
+                        SelectorBuilder::TextSelector(
+                            Item::IdRef(new_id),
+                            Offset::simple(segment.new.0, segment.new.1),
+                        ),
+                    ]))
+                    .with_data_builder(data("type").with_value(segment.segmenttype.as_str().into()))
+                    .with_data_builder(data("old").with_value(old.into()))
+                    .with_data_builder(data("new").with_value(new.into())),
+            )
+            .map_err(|e| format!("Error adding annotation: {}", e))?;
+        count += 1;
+    }
+    Ok((edits, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the type of each segment with the old and new text it covers
+    fn segments(old: &str, new: &str, unit: DiffUnit) -> Vec<(SegmentType, String, String)> {
+        let slice = |text: &str, (begin, end): (usize, usize)| {
+            text.chars()
+                .skip(begin)
+                .take(end - begin)
+                .collect::<String>()
+        };
+        diff_texts(old, new, unit)
+            .into_iter()
+            .map(|segment| {
+                (
+                    segment.segmenttype,
+                    slice(old, segment.old),
+                    slice(new, segment.new),
+                )
+            })
+            .collect()
+    }
+
+    fn segment(segmenttype: SegmentType, old: &str, new: &str) -> (SegmentType, String, String) {
+        (segmenttype, old.to_string(), new.to_string())
+    }
+
+    #[test]
+    fn tokens() {
+        let tokens: Vec<&str> = tokenize("Hi, wörld  ok", DiffUnit::Words)
+            .into_iter()
+            .map(|(_, _, token)| token)
+            .collect();
+        assert_eq!(tokens, vec!["Hi", ",", " ", "wörld", "  ", "ok"]);
+        assert_eq!(
+            tokenize("a\nb\n", DiffUnit::Lines),
+            vec![(0, 2, "a\n"), (2, 4, "b\n")]
+        );
+        assert_eq!(
+            tokenize("äb", DiffUnit::Chars),
+            vec![(0, 1, "ä"), (1, 2, "b")]
+        );
+    }
+
+    #[test]
+    fn script() {
+        let a = vec!["a", "b", "c"];
+        let b = vec!["a", "x", "c", "d"];
+        let ops = edit_script(&a, &b);
+        assert_eq!(ops.iter().filter(|op| **op == Op::Equal).count(), 2);
+        assert_eq!(ops.len(), 5);
+        assert_eq!(edit_script(&[], &["a"]), vec![Op::Insert]);
+        assert_eq!(edit_script(&["a"], &[]), vec![Op::Delete]);
+        assert!(edit_script(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn lines() {
+        use SegmentType::*;
+        assert_eq!(
+            segments("a\nb\nc\n", "a\nc\nd\n", DiffUnit::Lines),
+            vec![
+                segment(Equal, "a\n", "a\n"),
+                segment(Deletion, "b\n", ""),
+                segment(Equal, "c\n", "c\n"),
+                segment(Insertion, "", "d\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(
+            segments("héllo wörld", "héllo world", DiffUnit::Words),
+            vec![
+                segment(SegmentType::Equal, "héllo ", "héllo "),
+                segment(SegmentType::Substitution, "wörld", "world")
+            ]
+        );
+    }
+
+    #[test]
+    fn identical() {
+        assert_eq!(
+            segments("same", "same", DiffUnit::Chars),
+            vec![segment(SegmentType::Equal, "same", "same")]
+        );
+        assert!(diff_texts("", "", DiffUnit::Lines).is_empty());
+    }
+}
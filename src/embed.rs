@@ -0,0 +1,160 @@
+use clap::Arg;
+use serde_json::{json, Map, Value};
+use stam::{AnnotationStore, DataValue, Item, Storable, Text};
+use std::io::Write;
+
+use crate::filter::DataFilter;
+
+pub fn embed_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("metadata")
+            .long("metadata")
+            .short('m')
+            .help("Include the value of this set/key of the annotation in the metadata of each record. Multiple are allowed.")
+            .takes_value(true)
+            .action(clap::ArgAction::Append),
+        Arg::with_name("max-tokens")
+            .long("max-tokens")
+            .help("Truncate the text of each record after this many tokens (whitespace separated), the end offset is adjusted accordingly")
+            .takes_value(true),
+        Arg::with_name("with-resource-text")
+            .long("with-resource-text")
+            .help("Output a record for each text resource as a whole as well, not only for the annotations selected by --filter"),
+    ]
+}
+
+/// Converts a data value to plain JSON, without the type information STAM JSON adds
+fn value_to_json(value: &DataValue) -> Value {
+    match value {
+        DataValue::Null => Value::Null,
+        DataValue::String(s) => s.as_str().into(),
+        DataValue::Bool(b) => (*b).into(),
+        DataValue::Int(n) => (*n).into(),
+        DataValue::Float(f) => (*f).into(),
+        DataValue::List(values) => values.iter().map(value_to_json).collect(),
+    }
+}
+
+/// Truncates the text after `max_tokens` tokens (whitespace separated), returns the truncated text,
+/// its length in unicode points, the number of tokens in the result and whether anything was cut off.
+fn truncate(text: &str, max_tokens: Option<usize>) -> (&str, usize, usize, bool) {
+    let mut tokens = 0;
+    let mut endbyte = text.len();
+    let mut truncated = false;
+    let mut in_token = false;
+    for (bytepos, c) in text.char_indices() {
+        if c.is_whitespace() {
+            in_token = false;
+        } else if !in_token {
+            in_token = true;
+            if Some(tokens) == max_tokens {
+                truncated = true;
+                endbyte = bytepos;
+                break;
+            }
+            tokens += 1;
+        }
+    }
+    let text = if truncated {
+        text[..endbyte].trim_end()
+    } else {
+        text
+    };
+    (text, text.chars().count(), tokens, truncated)
+}
+
+fn record(
+    id: String,
+    text: &str,
+    resource: &str,
+    begin: usize,
+    max_tokens: Option<usize>,
+    metadata: Map<String, Value>,
+) -> Value {
+    let (text, length, tokens, truncated) = truncate(text, max_tokens);
+    json!({
+        "id": id,
+        "text": text,
+        "resource": resource,
+        "begin": begin,
+        "end": begin + length,
+        "tokens": tokens,
+        "truncated": truncated,
+        "metadata": metadata,
+    })
+}
+
+/// Writes one JSON record per line for each text selection of the annotations matching any of the filters, and, if
+/// `resources` is set or there are no filters, for each resource as a whole. Records have a stable ID (the annotation ID,
+/// or resource#begin-end for annotations without ID and for resources), the text, the resource ID, the begin and end
+/// offset (in unicode points), the number of tokens, and the values of the `metadata` keys (by key ID) on the annotation.
+pub fn embed(
+    writer: &mut impl Write,
+    store: &AnnotationStore,
+    filters: &[DataFilter],
+    metadata: &[DataFilter],
+    max_tokens: Option<usize>,
+    resources: bool,
+) -> Result<usize, String> {
+    let mut count = 0;
+    let mut write = |record: Value| {
+        count += 1;
+        writeln!(writer, "{}", record).map_err(|e| format!("{}", e))
+    };
+    if resources || filters.is_empty() {
+        for resource in store.resources() {
+            let resource_id = resource.id().unwrap_or("(none)");
+            write(record(
+                format!("{}#0-{}", resource_id, resource.textlen()),
+                resource.text(),
+                resource_id,
+                0,
+                max_tokens,
+                Map::new(),
+            ))?;
+        }
+    }
+    for annotation in store
+        .annotations()
+        .filter(|annotation| filters.iter().any(|filter| filter.test(annotation)))
+    {
+        let mut annotation_metadata = Map::new();
+        for filter in metadata {
+            if let Some(mut data) = filter.operator().ok().and_then(|operator| {
+                annotation.find_data(
+                    Some(Item::IdRef(filter.set.as_str())),
+                    Some(Item::IdRef(filter.key.as_str())),
+                    operator,
+                )
+            }) {
+                if let Some(data) = data.next() {
+                    annotation_metadata.insert(filter.key.clone(), value_to_json(data.value()));
+                }
+            }
+        }
+        let textselections: Vec<_> = annotation.textselections().collect();
+        for (i, textselection) in textselections.iter().enumerate() {
+            let resource_id = textselection.resource().id().unwrap_or("(none)");
+            let id = match annotation.id() {
+                Some(id) if textselections.len() == 1 => id.to_string(),
+                //non-contiguous annotations result in one record per part
+                Some(id) => format!("{}.{}", id, i + 1),
+                None => format!(
+                    "{}#{}-{}",
+                    resource_id,
+                    textselection.begin(),
+                    textselection.end()
+                ),
+            };
+            write(record(
+                id,
+                textselection.text(),
+                resource_id,
+                textselection.begin(),
+                max_tokens,
+                annotation_metadata.clone(),
+            ))?;
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,246 @@
+use stam::{Annotation, DataOperator, Item, Regex, WrappedItem};
+
+/// A simple filter on annotation data, parsed from an expression like `set/key`, `set/key=value`
+/// or `set/key>value`. Supported operators are `=`, `!=`, `>`, `>=`, `<` and `<=`, the latter four
+/// require a numeric value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataFilter {
+    pub set: String,
+    pub key: String,
+    pub op: FilterOperator,
+    pub value: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterOperator {
+    Any,
+    Equals,
+    NotEquals,
+    GreaterThan,
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+}
+
+impl DataFilter {
+    /// Parse a filter expression. The set and key are separated by `setdelimiter`.
+    pub fn parse(expression: &str, setdelimiter: &str) -> Result<Self, String> {
+        //find the operator (order matters, two-character operators first)
+        let mut found: Option<(usize, &str, FilterOperator)> = None;
+        for (opstr, op) in [
+            ("!=", FilterOperator::NotEquals),
+            (">=", FilterOperator::GreaterThanOrEqual),
+            ("<=", FilterOperator::LessThanOrEqual),
+            ("=", FilterOperator::Equals),
+            (">", FilterOperator::GreaterThan),
+            ("<", FilterOperator::LessThan),
+        ] {
+            if let Some(pos) = expression.find(opstr) {
+                if found.is_none() || pos < found.unwrap().0 {
+                    found = Some((pos, opstr, op));
+                }
+            }
+        }
+        let (setkey, op, value) = if let Some((pos, opstr, op)) = found {
+            (
+                &expression[..pos],
+                op,
+                Some(expression[pos + opstr.len()..].to_string()),
+            )
+        } else {
+            (expression, FilterOperator::Any, None)
+        };
+        let (set, key) = setkey.rsplit_once(setdelimiter).ok_or_else(|| {
+            format!(
+                "Invalid filter '{}', expected set{}key, optionally followed by an operator and a value",
+                expression, setdelimiter
+            )
+        })?;
+        if set.is_empty() || key.is_empty() {
+            return Err(format!(
+                "Invalid filter '{}', set and key may not be empty",
+                expression
+            ));
+        }
+        let filter = Self {
+            set: set.to_string(),
+            key: key.to_string(),
+            op,
+            value,
+        };
+        //check early whether numeric operators have a numeric value
+        filter.operator()?;
+        Ok(filter)
+    }
+
+    /// Returns the [`DataOperator`] corresponding with this filter
+    pub fn operator(&self) -> Result<DataOperator<'_>, String> {
+        let value = self.value.as_deref().unwrap_or("");
+        let numeric_error = || {
+            format!(
+                "Filter on {}/{}: expected a numeric value, got '{}'",
+                self.set, self.key, value
+            )
+        };
+        Ok(match self.op {
+            FilterOperator::Any => DataOperator::Any,
+            FilterOperator::Equals => DataOperator::Equals(value),
+            FilterOperator::NotEquals => DataOperator::Not(Box::new(DataOperator::Equals(value))),
+            FilterOperator::GreaterThan => {
+                if let Ok(n) = value.parse::<isize>() {
+                    DataOperator::GreaterThan(n)
+                } else {
+                    DataOperator::GreaterThanFloat(value.parse().map_err(|_| numeric_error())?)
+                }
+            }
+            FilterOperator::GreaterThanOrEqual => {
+                if let Ok(n) = value.parse::<isize>() {
+                    DataOperator::GreaterThanOrEqual(n)
+                } else {
+                    DataOperator::GreaterThanOrEqualFloat(
+                        value.parse().map_err(|_| numeric_error())?,
+                    )
+                }
+            }
+            FilterOperator::LessThan => {
+                if let Ok(n) = value.parse::<isize>() {
+                    DataOperator::LessThan(n)
+                } else {
+                    DataOperator::LessThanFloat(value.parse().map_err(|_| numeric_error())?)
+                }
+            }
+            FilterOperator::LessThanOrEqual => {
+                if let Ok(n) = value.parse::<isize>() {
+                    DataOperator::LessThanOrEqual(n)
+                } else {
+                    DataOperator::LessThanOrEqualFloat(value.parse().map_err(|_| numeric_error())?)
+                }
+            }
+        })
+    }
+
+    /// Tests whether the annotation has data matching this filter
+    pub fn test(&self, annotation: &WrappedItem<Annotation>) -> bool {
+        if let Ok(operator) = self.operator() {
+            annotation.test_data(
+                Some(Item::IdRef(self.set.as_str())),
+                Some(Item::IdRef(self.key.as_str())),
+                operator,
+            )
+        } else {
+            false
+        }
+    }
+
+    /// Returns the first value of matching data on the annotation, as a string
+    pub fn value_of(&self, annotation: &WrappedItem<Annotation>) -> Option<String> {
+        let operator = self.operator().ok()?;
+        let mut iter = annotation.find_data(
+            Some(Item::IdRef(self.set.as_str())),
+            Some(Item::IdRef(self.key.as_str())),
+            operator,
+        )?;
+        iter.next().map(|data| data.value().to_string())
+    }
+}
+
+/// Parse multiple filter expressions at once
+pub fn parse_filters<'a>(
+    expressions: impl Iterator<Item = &'a str>,
+    setdelimiter: &str,
+) -> Result<Vec<DataFilter>, String> {
+    expressions
+        .map(|expression| DataFilter::parse(expression, setdelimiter))
+        .collect()
+}
+
+/// Constraints on the annotations to export, as set by `--data`, `--missing` and `--text-matches`. An annotation is selected
+/// if it matches all of the data filters, none of the missing filters, and its text matches all of the regular expressions.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    pub data: Vec<DataFilter>,
+    /// Data the annotation must lack
+    pub missing: Vec<DataFilter>,
+    pub text: Vec<Regex>,
+}
+
+impl Selection {
+    /// Returns true if there are no constraints, i.e. everything is selected
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty() && self.missing.is_empty() && self.text.is_empty()
+    }
+
+    /// Tests whether the annotation is selected
+    pub fn test(&self, annotation: &WrappedItem<Annotation>) -> bool {
+        self.data.iter().all(|filter| filter.test(annotation))
+            && !self.missing.iter().any(|filter| filter.test(annotation))
+            && (self.text.is_empty() || {
+                let text: Vec<&str> = annotation.text().collect();
+                let text = text.join(" ");
+                self.text
+                    .iter()
+                    .all(|expression| expression.is_match(&text))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_operators() {
+        let filter = DataFilter::parse("pos/tag", "/").unwrap();
+        assert_eq!(
+            filter,
+            DataFilter {
+                set: "pos".to_string(),
+                key: "tag".to_string(),
+                op: FilterOperator::Any,
+                value: None
+            }
+        );
+        for (expression, op, value) in [
+            ("pos/tag=noun", FilterOperator::Equals, "noun"),
+            ("pos/tag!=noun", FilterOperator::NotEquals, "noun"),
+            ("x/n>=2", FilterOperator::GreaterThanOrEqual, "2"),
+            ("x/n<=2.5", FilterOperator::LessThanOrEqual, "2.5"),
+            ("x/n>2", FilterOperator::GreaterThan, "2"),
+            ("x/n<2", FilterOperator::LessThan, "2"),
+        ] {
+            let filter = DataFilter::parse(expression, "/").unwrap();
+            assert_eq!(filter.op, op, "{}", expression);
+            assert_eq!(filter.value.as_deref(), Some(value), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn parse_value_with_operators() {
+        //the first operator separates the key from the value
+        let filter = DataFilter::parse("url/href=http://x.org/?a=b", "/").unwrap();
+        assert_eq!(filter.set, "url");
+        assert_eq!(filter.key, "href");
+        assert_eq!(filter.value.as_deref(), Some("http://x.org/?a=b"));
+    }
+
+    #[test]
+    fn parse_set_delimiter() {
+        let filter = DataFilter::parse("http://example.org/set#key=v", "#").unwrap();
+        assert_eq!(filter.set, "http://example.org/set");
+        assert_eq!(filter.key, "key");
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(DataFilter::parse("tag", "/").is_err());
+        assert!(DataFilter::parse("/tag", "/").is_err());
+        assert!(DataFilter::parse("x/n>ten", "/").is_err());
+        assert!(parse_filters(["a/b", "c"].into_iter(), "/").is_err());
+        assert_eq!(
+            parse_filters(["a/b", "c/d=e"].into_iter(), "/")
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
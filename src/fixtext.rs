@@ -0,0 +1,400 @@
+use serde_json::json;
+use stam::{AnnotationStore, Item, Text};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use crate::rewrite::{
+    add_suffix, apply_edits, remap_relative_selector, remap_selector, StoreJson, TextEdit,
+};
+use crate::tsv::unescape;
+
+/// Reads edits from a TSV file with three columns: the begin offset (in unicode points), the old text and the new text.
+/// Tabs and newlines in the texts can be escaped as `\t` and `\n`. Empty lines, comments (`#`) and a header line are skipped.
+/// The old text is checked against the text of the resource.
+pub fn edits_from_tsv(filename: &str, text: &str) -> Result<Vec<TextEdit>, String> {
+    let f = File::open(filename).map_err(|e| format!("Error opening {}: {}", filename, e))?;
+    let chars: Vec<char> = text.chars().collect();
+    let mut edits = Vec::new();
+    for (i, line) in BufReader::new(f).lines().enumerate() {
+        let line = line.map_err(|e| format!("Error reading {}: {}", filename, e))?;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let cells: Vec<&str> = line.split('\t').collect();
+        if cells.len() != 3 {
+            return Err(format!(
+                "{} line {}: expected three columns (offset, old text, new text), got {}",
+                filename,
+                i + 1,
+                cells.len()
+            ));
+        }
+        let begin: usize = match cells[0].parse() {
+            Ok(begin) => begin,
+            Err(_) if i == 0 => continue, //header
+            Err(_) => {
+                return Err(format!(
+                    "{} line {}: invalid offset: {}",
+                    filename,
+                    i + 1,
+                    cells[0]
+                ))
+            }
+        };
+        let old = unescape(cells[1]);
+        let end = begin + old.chars().count();
+        if end > chars.len() || chars[begin..end].iter().collect::<String>() != old {
+            return Err(format!(
+                "{} line {}: old text {:?} not found at offset {}",
+                filename,
+                i + 1,
+                old,
+                begin
+            ));
+        }
+        edits.push(TextEdit {
+            begin,
+            end,
+            replacement: unescape(cells[2]),
+        });
+    }
+    Ok(edits)
+}
+
+/// Reads edits from a unified diff (as produced by `diff -u` or `git diff`) against the text of the resource.
+/// Only a diff against a single file is supported. Removed lines are checked against the text.
+pub fn edits_from_diff(filename: &str, text: &str) -> Result<Vec<TextEdit>, String> {
+    let f = File::open(filename).map_err(|e| format!("Error opening {}: {}", filename, e))?;
+    //begin offsets (in unicode points) of all lines in the text, and the lines themselves (including newline)
+    let mut linestarts = vec![0];
+    let mut lines: Vec<&str> = Vec::new();
+    let mut pos = 0;
+    for line in text.split_inclusive('\n') {
+        pos += line.chars().count();
+        linestarts.push(pos);
+        lines.push(line);
+    }
+    let mut edits = Vec::new();
+    let mut oldline: usize = 0; //0-indexed line in the old text
+    let mut removed: Option<(usize, usize)> = None; //line range being removed
+    let mut added = String::new();
+    let mut in_hunk = false;
+    let flush = |removed: &mut Option<(usize, usize)>,
+                 added: &mut String,
+                 oldline: usize,
+                 edits: &mut Vec<TextEdit>| {
+        let (first, last) = removed.take().unwrap_or((oldline, oldline));
+        if first != last || !added.is_empty() {
+            if last >= linestarts.len() {
+                return Err(format!(
+                    "{}: hunk at line {} is out of range",
+                    filename,
+                    first + 1
+                ));
+            }
+            edits.push(TextEdit {
+                begin: linestarts[first],
+                end: linestarts[last],
+                replacement: std::mem::take(added),
+            });
+        }
+        Ok(())
+    };
+    for (i, line) in BufReader::new(f).lines().enumerate() {
+        let line = line.map_err(|e| format!("Error reading {}: {}", filename, e))?;
+        if line.starts_with("@@") {
+            flush(&mut removed, &mut added, oldline, &mut edits)?;
+            //@@ -oldstart,oldcount +newstart,newcount @@
+            let oldstart: usize = line
+                .split(' ')
+                .nth(1)
+                .and_then(|x| x.strip_prefix('-'))
+                .and_then(|x| x.split(',').next())
+                .and_then(|x| x.parse().ok())
+                .ok_or_else(|| format!("{} line {}: invalid hunk header", filename, i + 1))?;
+            //a count of zero means the hunk is inserted after the start line
+            let oldcount_zero = line
+                .split(' ')
+                .nth(1)
+                .map(|x| x.ends_with(",0"))
+                .unwrap_or(false);
+            oldline = if oldcount_zero {
+                oldstart
+            } else {
+                oldstart.saturating_sub(1)
+            };
+            in_hunk = true;
+        } else if !in_hunk || line.starts_with('\\') {
+            //file headers and "\ No newline at end of file" markers
+            continue;
+        } else if let Some(content) = line.strip_prefix('-') {
+            if added.is_empty() {
+                if lines.get(oldline).map(|x| x.trim_end_matches('\n')) != Some(content) {
+                    return Err(format!(
+                        "{} line {}: removed line does not match line {} of the text",
+                        filename,
+                        i + 1,
+                        oldline + 1
+                    ));
+                }
+                removed = Some((removed.map(|x| x.0).unwrap_or(oldline), oldline + 1));
+                oldline += 1;
+            } else {
+                return Err(format!(
+                    "{} line {}: removed line after added lines is not supported",
+                    filename,
+                    i + 1
+                ));
+            }
+        } else if let Some(content) = line.strip_prefix('+') {
+            added.push_str(content);
+            added.push('\n');
+        } else {
+            //context line
+            flush(&mut removed, &mut added, oldline, &mut edits)?;
+            oldline += 1;
+            if oldline > lines.len() {
+                return Err(format!(
+                    "{} line {}: diff extends beyond the end of the text",
+                    filename,
+                    i + 1
+                ));
+            }
+        }
+    }
+    flush(&mut removed, &mut added, oldline, &mut edits)?;
+    Ok(edits)
+}
+
+/// Applies edits to a resource. The edited text becomes a new resource, ID and filename are derived by adding `suffix`.
+/// The original resource is kept as is. All annotations on the original resource are moved over to the new one, with their
+/// offsets adapted to the new text. Each edit is recorded as an annotation in the set `set_id`, with a [`stam::Selector::DirectionalSelector`]
+/// pointing from the old text to the new text, and with the old and new texts as data (keys `old` and `new`).
+///
+/// Returns the new store and the new resource ID with the filename it still needs to be written to (if any).
+pub fn fixtext(
+    store: &AnnotationStore,
+    resource_id: &str,
+    mut edits: Vec<TextEdit>,
+    suffix: &str,
+    set_id: &str,
+    verbose: bool,
+) -> Result<(AnnotationStore, Option<(String, String)>), String> {
+    let resource = store
+        .resource(&Item::IdRef(resource_id))
+        .ok_or_else(|| format!("Resource {} not found", resource_id))?;
+    edits.sort_by_key(|edit| (edit.begin, edit.end));
+    for pair in edits.windows(2) {
+        if pair[1].begin < pair[0].end {
+            return Err(format!(
+                "Edits may not overlap: {}-{} and {}-{}",
+                pair[0].begin, pair[0].end, pair[1].begin, pair[1].end
+            ));
+        }
+    }
+    let chars: Vec<char> = resource.text().chars().collect();
+    let (newtext, map) = apply_edits(resource.text(), &edits);
+    let new_id = add_suffix(resource_id, suffix);
+    eprintln!(
+        "Applying {} edit(s) to resource {}, new resource: {}",
+        edits.len(),
+        resource_id,
+        new_id
+    );
+
+    let mut storejson = StoreJson::from_store(store)?;
+    let mut newresource = storejson
+        .resources
+        .iter()
+        .find(|value| value.get("@id").and_then(|x| x.as_str()) == Some(resource_id))
+        .cloned()
+        .ok_or_else(|| format!("Resource {} not found", resource_id))?;
+    newresource["@id"] = new_id.as_str().into();
+    newresource["text"] = newtext.as_str().into();
+    storejson.resources.push(newresource);
+    for annotation in storejson.annotations.iter_mut() {
+        if let Some(target) = annotation.get_mut("target") {
+            remap_relative_selector(target, store, resource_id, &map);
+            remap_selector(target, resource_id, &new_id, &map);
+        }
+    }
+
+    let textselector = |resource: &str, begin: usize, end: usize| {
+        json!({
+            "@type": "TextSelector",
+            "resource": resource,
+            "offset": {
+                "@type": "Offset",
+                "begin": { "@type": "BeginAlignedCursor", "value": begin },
+                "end": { "@type": "BeginAlignedCursor", "value": end },
+            }
+        })
+    };
+    let databuilder = |key: &str, value: String| {
+        json!({
+            "@type": "AnnotationData",
+            "set": set_id,
+            "key": key,
+            "value": { "@type": "String", "value": value }
+        })
+    };
+    let mut delta: isize = 0;
+    for (i, edit) in edits.iter().enumerate() {
+        let old: String = chars[edit.begin..edit.end].iter().collect();
+        let newbegin = (edit.begin as isize + delta) as usize;
+        let newend = newbegin + edit.replacement.chars().count();
+        delta += newend as isize - edit.end as isize;
+        if verbose {
+            eprintln!(
+                "{}\t{}\t{:?}\t{:?}",
+                edit.begin, edit.end, old, edit.replacement
+            );
+        }
+        storejson.annotations.push(json!({
+            "@type": "Annotation",
+            "@id": format!("{}.edit{}", new_id, i + 1),
+            "target": {
+                "@type": "DirectionalSelector",
+                "selectors": [
+                    textselector(resource_id, edit.begin, edit.end),
+                    textselector(&new_id, newbegin, newend),
+                ]
+            },
+            "data": [
+                databuilder("old", old),
+                databuilder("new", edit.replacement.clone()),
+            ]
+        }));
+    }
+    if storejson
+        .annotationsets
+        .iter()
+        .all(|value| value.get("@id").and_then(|x| x.as_str()) != Some(set_id))
+    {
+        storejson.annotationsets.push(json!({
+            "@type": "AnnotationDataSet",
+            "@id": set_id,
+            "keys": [],
+            "data": [],
+        }));
+    }
+
+    let filename = storejson
+        .resource_filename(resource_id)
+        .map(|filename| add_suffix(filename, suffix));
+    if let Some(filename) = filename.as_ref() {
+        storejson.set_resource_filename(&new_id, filename);
+    }
+    let newstore = storejson.into_store(store)?;
+    Ok((newstore, filename.map(|filename| (new_id, filename))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "The quick brown fox\njumps over\nthe lazy dog.\n";
+
+    /// Writes the content to a file in the temporary directory and returns its name
+    fn tempfile(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("stamtools-fixtext-{}", name));
+        std::fs::write(&path, content).expect("writing temporary file");
+        path.to_str().expect("valid path").to_string()
+    }
+
+    #[test]
+    fn tsv_edits() {
+        let filename = tempfile(
+            "edits.tsv",
+            "Offset\tOld\tNew\n# a comment\n\n4\tquick\tslow\n19\t\\n\t \n",
+        );
+        let edits = edits_from_tsv(&filename, TEXT).unwrap();
+        assert_eq!(
+            edits,
+            vec![
+                TextEdit {
+                    begin: 4,
+                    end: 9,
+                    replacement: "slow".to_string()
+                },
+                TextEdit {
+                    begin: 19,
+                    end: 20,
+                    replacement: " ".to_string()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn tsv_old_text_mismatch() {
+        let filename = tempfile("mismatch.tsv", "4\tslow\tquick\n");
+        let err = edits_from_tsv(&filename, TEXT).unwrap_err();
+        assert!(err.contains("not found at offset 4"), "{}", err);
+    }
+
+    #[test]
+    fn tsv_wrong_columns() {
+        let filename = tempfile("columns.tsv", "4\tquick\n");
+        assert!(edits_from_tsv(&filename, TEXT).is_err());
+    }
+
+    #[test]
+    fn diff_replace_line() {
+        let filename = tempfile(
+            "replace.diff",
+            "--- a/text.txt\n+++ b/text.txt\n@@ -1,3 +1,3 @@\n The quick brown fox\n-jumps over\n+leaps over\n the lazy dog.\n",
+        );
+        let edits = edits_from_diff(&filename, TEXT).unwrap();
+        assert_eq!(
+            edits,
+            vec![TextEdit {
+                begin: 20,
+                end: 31,
+                replacement: "leaps over\n".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn diff_insert_and_delete() {
+        let filename = tempfile(
+            "insdel.diff",
+            "@@ -0,0 +1 @@\n+Title\n@@ -3 +3,0 @@\n-the lazy dog.\n",
+        );
+        let edits = edits_from_diff(&filename, TEXT).unwrap();
+        assert_eq!(
+            edits,
+            vec![
+                TextEdit {
+                    begin: 0,
+                    end: 0,
+                    replacement: "Title\n".to_string()
+                },
+                TextEdit {
+                    begin: 31,
+                    end: 45,
+                    replacement: String::new()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_mismatch() {
+        let filename = tempfile("mismatch.diff", "@@ -2 +2 @@\n-jumps under\n+leaps over\n");
+        let err = edits_from_diff(&filename, TEXT).unwrap_err();
+        assert!(err.contains("does not match line 2"), "{}", err);
+    }
+
+    #[test]
+    fn diff_out_of_range() {
+        //appending after the last line is fine, inserting beyond it is not
+        let filename = tempfile("append.diff", "@@ -3,0 +4 @@\n+The end.\n");
+        let edits = edits_from_diff(&filename, TEXT).unwrap();
+        assert_eq!(edits[0].begin, 45);
+        let filename = tempfile("outofrange.diff", "@@ -10,0 +11 @@\n+The end.\n");
+        let err = edits_from_diff(&filename, TEXT).unwrap_err();
+        assert!(err.contains("hunk at line 11 is out of range"), "{}", err);
+    }
+}
@@ -1,6 +1,14 @@
-use stam::{AnnotationStore, AssociatedFile, Configurable, Handle, Item, Storable, Text};
+use stam::{
+    Annotation, AnnotationStore, AssociatedFile, Configurable, Handle, Item, Storable, Text,
+    WrappedItem,
+};
+use std::collections::{BTreeMap, BTreeSet};
 
-pub fn info(store: &AnnotationStore, verbose: bool) {
+use crate::filter::DataFilter;
+
+/// Output information on the annotation store. If filters are provided, the listing of annotations and the statistics
+/// (verbose mode only) are restricted to annotations matching any of the filters.
+pub fn info(store: &AnnotationStore, verbose: bool, filters: &[DataFilter]) {
     if !verbose {
         eprintln!("(Tip: add --verbose for more detailed info output)");
     }
@@ -87,8 +95,16 @@ pub fn info(store: &AnnotationStore, verbose: bool) {
         }
     }
     println!("Annotations:            {}", store.annotations_len());
+    let selected = || {
+        store.annotations().filter(|annotation| {
+            filters.is_empty() || filters.iter().any(|filter| filter.test(annotation))
+        })
+    };
+    if !filters.is_empty() {
+        println!("Selected annotations:   {}", selected().count());
+    }
     if verbose {
-        for annotation in store.annotations() {
+        for annotation in selected() {
             println!(
                 "    - [{}] Annotation ID: {:?}; target: {:?}; text: {:?}, #data: {}",
                 annotation.handle().unwrap().unwrap(),
@@ -118,5 +134,110 @@ pub fn info(store: &AnnotationStore, verbose: bool) {
                 );
             }
         }
+        statistics(store, selected());
+    }
+}
+
+/// Statistics on (a selection of) annotations, for quality control
+struct Statistics {
+    /// Number of annotations and number of covered characters (may overlap) per resource
+    resources: BTreeMap<String, (usize, usize)>,
+    /// Number of annotations and distinct values per set/key
+    keys: BTreeMap<(String, String), (usize, BTreeSet<String>)>,
+    /// ID and number of covered characters of the smallest and largest annotations
+    smallest: Option<(String, usize)>,
+    largest: Option<(String, usize)>,
+}
+
+fn statistics<'a>(
+    store: &'a AnnotationStore,
+    annotations: impl Iterator<Item = WrappedItem<'a, Annotation>>,
+) {
+    let mut stats = Statistics {
+        resources: BTreeMap::new(),
+        keys: BTreeMap::new(),
+        smallest: None,
+        largest: None,
+    };
+    for annotation in annotations {
+        let mut length = 0;
+        let mut seen_resources: BTreeSet<String> = BTreeSet::new();
+        for textselection in annotation.textselections() {
+            let resource_id = textselection.resource().id().unwrap_or("(none)");
+            let textlength = textselection.end() - textselection.begin();
+            length += textlength;
+            let entry = stats
+                .resources
+                .entry(resource_id.to_string())
+                .or_insert((0, 0));
+            if seen_resources.insert(resource_id.to_string()) {
+                entry.0 += 1;
+            }
+            entry.1 += textlength;
+        }
+        for data in annotation.data() {
+            let set_id = data.set().id().unwrap_or("(none)").to_string();
+            let key_id = data.key().id().unwrap_or("(none)").to_string();
+            let entry = stats
+                .keys
+                .entry((set_id, key_id))
+                .or_insert((0, BTreeSet::new()));
+            entry.0 += 1;
+            entry.1.insert(data.value().to_string());
+        }
+        if !seen_resources.is_empty() {
+            let id = annotation.id().unwrap_or("(none)");
+            if stats
+                .smallest
+                .as_ref()
+                .map(|smallest| length < smallest.1)
+                .unwrap_or(true)
+            {
+                stats.smallest = Some((id.to_string(), length));
+            }
+            if stats
+                .largest
+                .as_ref()
+                .map(|largest| length > largest.1)
+                .unwrap_or(true)
+            {
+                stats.largest = Some((id.to_string(), length));
+            }
+        }
+    }
+    println!("Statistics:");
+    println!("    Per resource:");
+    for (resource_id, (count, covered)) in stats.resources.iter() {
+        let textlen = store
+            .resource(&Item::IdRef(resource_id.as_str()))
+            .map(|resource| resource.textlen())
+            .unwrap_or(0);
+        println!(
+            "        - Resource ID: {:?}; #annotations: {}; annotations per 1000 characters: {:.2}; #covered characters: {} (may overlap)",
+            resource_id,
+            count,
+            if textlen > 0 {
+                *count as f64 * 1000.0 / textlen as f64
+            } else {
+                0.0
+            },
+            covered
+        );
+    }
+    println!("    Per key:");
+    for ((set_id, key_id), (count, values)) in stats.keys.iter() {
+        println!(
+            "        - Set ID: {:?}; Key ID: {:?}; #annotations: {}; #distinct values: {}",
+            set_id,
+            key_id,
+            count,
+            values.len()
+        );
+    }
+    if let Some((id, length)) = stats.smallest.as_ref() {
+        println!("    Smallest annotation: {:?} ({} characters)", id, length);
+    }
+    if let Some((id, length)) = stats.largest.as_ref() {
+        println!("    Largest annotation:  {:?} ({} characters)", id, length);
     }
 }
@@ -0,0 +1,253 @@
+use clap::Arg;
+use serde_json::json;
+use stam::{AnnotationStore, Storable};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use crate::concordance::{line, ContextUnit};
+use crate::filter::DataFilter;
+
+pub fn lexicon_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("key")
+            .long("key")
+            .short('k')
+            .help("The set/key that holds the label, e.g. my_set/lemma. Like a filter, it may be restricted to certain values, e.g. my_set/pos=noun")
+            .takes_value(true)
+            .required(true),
+        Arg::with_name("reverse").long("reverse").help(
+            "Build the reverse lexicon: for each surface form, the labels it is annotated with",
+        ),
+        Arg::with_name("examples")
+            .long("examples")
+            .help("The number of example contexts to output per entry (the first ones in the text)")
+            .takes_value(true)
+            .default_value("1"),
+        Arg::with_name("width")
+            .long("width")
+            .short('w')
+            .help("The size of the left and right context of the examples, in tokens (whitespace separated)")
+            .takes_value(true)
+            .default_value("5"),
+        Arg::with_name("lowercase")
+            .long("lowercase")
+            .help("Lowercase the surface forms, so differences in case are ignored"),
+        Arg::with_name("format")
+            .long("format")
+            .short('F')
+            .help("Output format: tsv (one line per label and form) or json (forms nested under each label)")
+            .takes_value(true)
+            .possible_values(["tsv", "json"])
+            .default_value("tsv"),
+    ]
+}
+
+/// An attested combination of a label and a surface form
+#[derive(Clone, Debug, Default)]
+pub struct LexiconEntry {
+    pub frequency: usize,
+    /// Example contexts, with the form marked by square brackets
+    pub examples: Vec<String>,
+}
+
+/// Lexicon entries by headword and then by the label or form that goes with it. The headword is the label, or the surface form for a reverse lexicon.
+pub type Lexicon = BTreeMap<String, HashMap<String, LexiconEntry>>;
+
+/// Builds a lexicon from the annotations that have data matching `key`: for each value (label), the annotated
+/// surface forms with their frequencies and up to `examples` example contexts of `width` tokens. If `reverse` is set,
+/// the labels are collected per surface form instead. If `filters` are passed, annotations must also match one of them.
+pub fn lexicon(
+    store: &AnnotationStore,
+    key: &DataFilter,
+    filters: &[DataFilter],
+    reverse: bool,
+    examples: usize,
+    width: usize,
+    lowercase: bool,
+) -> Result<Lexicon, String> {
+    let mut lexicon = Lexicon::new();
+    let resources: Vec<_> = store.resources().collect();
+    let mut annotations: Vec<_> = store
+        .annotations()
+        .filter(|annotation| {
+            key.test(annotation)
+                && (filters.is_empty() || filters.iter().any(|filter| filter.test(annotation)))
+        })
+        .collect();
+    //in textual order, so the examples are the first ones in the text
+    annotations.sort_by_cached_key(|annotation| {
+        annotation
+            .textselections()
+            .next()
+            .map(|textselection| {
+                let resource = resources
+                    .iter()
+                    .position(|resource| resource.handle() == textselection.resource().handle())
+                    .expect("resource must exist");
+                (resource, textselection.begin())
+            })
+            .unwrap_or((usize::MAX, 0))
+    });
+    for annotation in annotations {
+        let label = match key.value_of(&annotation) {
+            Some(label) => label,
+            None => continue,
+        };
+        let text: Vec<&str> = annotation.text().collect();
+        let form: String = text
+            .join(" ")
+            .chars()
+            .map(|c| if c.is_whitespace() { ' ' } else { c })
+            .collect();
+        if form.is_empty() {
+            continue;
+        }
+        let form = if lowercase { form.to_lowercase() } else { form };
+        let (head, other) = if reverse {
+            (form, label)
+        } else {
+            (label, form)
+        };
+        let entry = lexicon.entry(head).or_default().entry(other).or_default();
+        entry.frequency += 1;
+        if entry.examples.len() < examples {
+            if let Some(textselection) = annotation.textselections().next() {
+                let resource = resources
+                    .iter()
+                    .find(|resource| resource.handle() == textselection.resource().handle())
+                    .expect("resource must exist");
+                let context = line(
+                    resource,
+                    0,
+                    textselection.begin(),
+                    textselection.end(),
+                    width,
+                    ContextUnit::Tokens,
+                )?;
+                //no tabs or newlines, which would break the TSV output
+                entry.examples.push(
+                    format!("{}[{}]{}", context.left, context.keyword, context.right)
+                        .chars()
+                        .map(|c| if c.is_whitespace() { ' ' } else { c })
+                        .collect(),
+                );
+            }
+        }
+    }
+    Ok(lexicon)
+}
+
+/// Returns the entries of a headword, the most frequent first
+fn sorted_entries(entries: &HashMap<String, LexiconEntry>) -> Vec<(&String, &LexiconEntry)> {
+    let mut entries: Vec<_> = entries.iter().collect();
+    entries
+        .sort_by(|(a, aentry), (b, bentry)| bentry.frequency.cmp(&aentry.frequency).then(a.cmp(b)));
+    entries
+}
+
+/// Writes the lexicon as TSV, one line per headword and label or form, with the examples separated by ` | `
+pub fn print_lexicon_tsv(
+    writer: &mut impl Write,
+    lexicon: &Lexicon,
+    reverse: bool,
+) -> Result<(), String> {
+    if reverse {
+        writeln!(writer, "Form\tLabel\tFrequency\tExamples")
+    } else {
+        writeln!(writer, "Label\tForm\tFrequency\tExamples")
+    }
+    .map_err(|e| format!("{}", e))?;
+    for (head, entries) in lexicon {
+        for (other, entry) in sorted_entries(entries) {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                head,
+                other,
+                entry.frequency,
+                entry.examples.join(" | ")
+            )
+            .map_err(|e| format!("{}", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the lexicon as JSON: a list of headwords, each with its total frequency and its forms (or labels, if `reverse` is set)
+pub fn print_lexicon_json(
+    writer: &mut impl Write,
+    lexicon: &Lexicon,
+    reverse: bool,
+) -> Result<(), String> {
+    let (headkey, otherskey, otherkey) = if reverse {
+        ("form", "labels", "label")
+    } else {
+        ("label", "forms", "form")
+    };
+    let entries: Vec<serde_json::Value> = lexicon
+        .iter()
+        .map(|(head, entries)| {
+            let others: Vec<serde_json::Value> = sorted_entries(entries)
+                .into_iter()
+                .map(|(other, entry)| {
+                    json!({
+                        otherkey: other,
+                        "frequency": entry.frequency,
+                        "examples": entry.examples,
+                    })
+                })
+                .collect();
+            json!({
+                headkey: head,
+                "frequency": entries.values().map(|entry| entry.frequency).sum::<usize>(),
+                otherskey: others,
+            })
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut *writer, &entries).map_err(|e| format!("{}", e))?;
+    writeln!(writer).map_err(|e| format!("{}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stam::{
+        AnnotationBuilder, AnnotationDataBuilder, Config, Item, Offset, SelectorBuilder, StoreFor,
+        TextResource,
+    };
+
+    #[test]
+    fn examples_in_text_order() {
+        let mut store = AnnotationStore::new();
+        store
+            .insert(TextResource::from_string(
+                "doc".to_string(),
+                "a cat\tsat\nand a cat ran".to_string(),
+                Config::default(),
+            ))
+            .unwrap();
+        //the last cat is added first
+        for (begin, end) in [(16, 19), (2, 5)] {
+            store
+                .annotate(
+                    AnnotationBuilder::new()
+                        .with_target(SelectorBuilder::TextSelector(
+                            Item::IdRef("doc"),
+                            Offset::simple(begin, end),
+                        ))
+                        .with_data_builder(
+                            AnnotationDataBuilder::new()
+                                .with_annotationset(Item::IdRef("test"))
+                                .with_key(Item::IdRef("pos"))
+                                .with_value("noun".into()),
+                        ),
+                )
+                .unwrap();
+        }
+        let key = DataFilter::parse("test/pos", "/").unwrap();
+        let lexicon = lexicon(&store, &key, &[], false, 1, 1, false).unwrap();
+        let entry = &lexicon["noun"]["cat"];
+        assert_eq!(entry.frequency, 2);
+        assert_eq!(entry.examples, vec!["a [cat] sat".to_string()]);
+    }
+}
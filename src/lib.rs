@@ -0,0 +1,34 @@
+//! The functionality behind the `stam` command-line tool, for use from other Rust programs.
+//! Each module implements one or more subcommands. See [`pipeline`] for builders for the TSV
+//! export and import procedures.
+//!
+//! Note that some functions still report fatal errors by printing a message and exiting the process,
+//! rather than returning an error.
+
+pub mod annotate;
+pub mod anonymize;
+pub mod brat;
+pub mod compression;
+pub mod concordance;
+pub mod coverage;
+pub mod defaults;
+pub mod diff;
+pub mod embed;
+pub mod filter;
+pub mod fixtext;
+pub mod info;
+pub mod lexicon;
+pub mod ngram;
+pub mod normalize;
+pub mod pipeline;
+pub mod progress;
+pub mod rename;
+pub mod resolve;
+pub mod rewrite;
+pub mod sort;
+pub mod tag;
+pub mod timealigned;
+pub mod to_text;
+pub mod tsv;
+pub mod validate;
+pub mod xlsx;
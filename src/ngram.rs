@@ -0,0 +1,183 @@
+use clap::Arg;
+use stam::{AnnotationStore, Storable, Text};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use crate::concordance::ContextUnit;
+use crate::filter::DataFilter;
+
+pub fn ngram_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("size")
+            .long("size")
+            .short('n')
+            .help(
+                "The size of the n-grams, or a range like 1-3 to extract n-grams of multiple sizes",
+            )
+            .takes_value(true)
+            .default_value("2"),
+        Arg::with_name("unit")
+            .long("unit")
+            .help("Extract n-grams of tokens or of characters. Tokens are the annotations selected by --filter, or, if there is no filter, the whitespace separated parts of the text. Character n-grams are taken from each token if there is a filter, or from the entire text otherwise.")
+            .takes_value(true)
+            .possible_values(["chars", "tokens"])
+            .default_value("tokens"),
+        Arg::with_name("min-freq")
+            .long("min-freq")
+            .help("Only output n-grams that occur at least this many times")
+            .takes_value(true)
+            .default_value("1"),
+        Arg::with_name("per-resource")
+            .long("per-resource")
+            .help("Count n-grams per text resource rather than over all resources, the output then has an extra TextResource column"),
+        Arg::with_name("lowercase")
+            .long("lowercase")
+            .help("Lowercase the text before counting, so differences in case are ignored"),
+    ]
+}
+
+/// Parses an n-gram size like `2`, or a range like `1-3`
+pub fn parse_ngram_size(value: &str) -> Result<(usize, usize), String> {
+    let (min, max) = value.split_once('-').unwrap_or((value, value));
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid n-gram size: {}", value))
+    };
+    let (min, max) = (parse(min)?, parse(max)?);
+    if min == 0 || max < min {
+        return Err(format!("Invalid n-gram size: {}", value));
+    }
+    Ok((min, max))
+}
+
+/// Frequencies of n-grams, per resource ID (or under an empty ID if counted over all resources)
+pub type NgramFrequencies = BTreeMap<String, HashMap<String, usize>>;
+
+/// Counts the n-grams of sizes `min` to `max` (inclusive). If filters are passed, the tokens are the annotations matching
+/// any of the filters, in textual order; otherwise the text is split on whitespace. N-grams do not cross resources.
+pub fn ngrams(
+    store: &AnnotationStore,
+    filters: &[DataFilter],
+    (min, max): (usize, usize),
+    unit: ContextUnit,
+    per_resource: bool,
+    lowercase: bool,
+) -> NgramFrequencies {
+    let mut frequencies = NgramFrequencies::new();
+    let normalize = |text: &str| {
+        //whitespace is not allowed inside a TSV cell, tokens are separated by a space
+        let text: String = text
+            .chars()
+            .map(|c| if c.is_whitespace() { ' ' } else { c })
+            .collect();
+        if lowercase {
+            text.to_lowercase()
+        } else {
+            text
+        }
+    };
+    //the tokens (or, for character n-grams over the raw text, the entire text) per resource
+    let mut sequences: BTreeMap<String, Vec<Vec<String>>> = BTreeMap::new();
+    if filters.is_empty() {
+        for resource in store.resources() {
+            let resource_id = resource.id().unwrap_or("(none)").to_string();
+            let sequence: Vec<String> = match unit {
+                ContextUnit::Tokens => resource.text().split_whitespace().map(normalize).collect(),
+                ContextUnit::Chars => vec![normalize(resource.text())],
+            };
+            sequences.entry(resource_id).or_default().push(sequence);
+        }
+    } else {
+        let mut tokens: BTreeMap<String, Vec<(usize, String)>> = BTreeMap::new();
+        for annotation in store
+            .annotations()
+            .filter(|annotation| filters.iter().any(|filter| filter.test(annotation)))
+        {
+            if let Some(textselection) = annotation.textselections().next() {
+                let resource_id = textselection
+                    .resource()
+                    .id()
+                    .unwrap_or("(none)")
+                    .to_string();
+                let text: Vec<&str> = annotation.text().collect();
+                tokens
+                    .entry(resource_id)
+                    .or_default()
+                    .push((textselection.begin(), normalize(&text.join(" "))));
+            }
+        }
+        for (resource_id, mut tokens) in tokens {
+            tokens.sort_by_key(|(begin, _)| *begin);
+            let tokens = tokens.into_iter().map(|(_, token)| token);
+            let resource_sequences = sequences.entry(resource_id).or_default();
+            match unit {
+                ContextUnit::Tokens => resource_sequences.push(tokens.collect()),
+                //each token on its own
+                ContextUnit::Chars => resource_sequences.extend(tokens.map(|token| vec![token])),
+            }
+        }
+    }
+    for (resource_id, sequences) in sequences {
+        let counts = frequencies
+            .entry(if per_resource {
+                resource_id
+            } else {
+                String::new()
+            })
+            .or_default();
+        for sequence in sequences {
+            match unit {
+                ContextUnit::Tokens => {
+                    for n in min..=max {
+                        for ngram in sequence.windows(n) {
+                            *counts.entry(ngram.join(" ")).or_insert(0) += 1;
+                        }
+                    }
+                }
+                ContextUnit::Chars => {
+                    for text in sequence {
+                        let chars: Vec<char> = text.chars().collect();
+                        for n in min..=max {
+                            for ngram in chars.windows(n) {
+                                *counts.entry(ngram.iter().collect()).or_insert(0) += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    frequencies
+}
+
+/// Writes the frequency list as TSV, the most frequent n-grams first
+pub fn print_ngrams(
+    writer: &mut impl Write,
+    frequencies: &NgramFrequencies,
+    min_freq: usize,
+    per_resource: bool,
+) -> Result<(), String> {
+    if per_resource {
+        writeln!(writer, "TextResource\tNGram\tFrequency")
+    } else {
+        writeln!(writer, "NGram\tFrequency")
+    }
+    .map_err(|e| format!("{}", e))?;
+    for (resource_id, counts) in frequencies {
+        let mut counts: Vec<(&String, &usize)> = counts
+            .iter()
+            .filter(|(_, count)| **count >= min_freq)
+            .collect();
+        counts.sort_by(|(a, acount), (b, bcount)| bcount.cmp(acount).then(a.cmp(b)));
+        for (ngram, count) in counts {
+            if per_resource {
+                writeln!(writer, "{}\t{}\t{}", resource_id, ngram, count)
+            } else {
+                writeln!(writer, "{}\t{}", ngram, count)
+            }
+            .map_err(|e| format!("{}", e))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Produces a normalized view of a text (for searching), along with a map back to the offsets in the original text.
+#[derive(Clone, Debug, Default)]
+pub struct Normalizer {
+    /// Case folding (lowercasing)
+    casefold: bool,
+    /// Collapse runs of whitespace into a single space
+    whitespace: bool,
+    /// Characters to replace by another string (possibly empty)
+    equivalences: HashMap<char, String>,
+}
+
+/// A normalized text
+pub struct NormalizedText {
+    pub text: String,
+    /// For each character in the normalized text, the begin and end offset (unicode points) of the characters in the original text it stems from
+    map: Vec<(usize, usize)>,
+    /// Length of the original text in unicode points
+    originallen: usize,
+}
+
+impl Normalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a comma separated list of normalizations: `case` and/or `whitespace`
+    pub fn with_options(mut self, options: &str) -> Result<Self, String> {
+        for option in options.split(',').map(|option| option.trim()) {
+            match option {
+                "case" => self.casefold = true,
+                "whitespace" => self.whitespace = true,
+                "" => {}
+                _ => {
+                    return Err(format!(
+                        "Unknown normalization '{}', expected case or whitespace",
+                        option
+                    ))
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn with_casefold(mut self, casefold: bool) -> Self {
+        self.casefold = casefold;
+        self
+    }
+
+    pub fn with_whitespace(mut self, whitespace: bool) -> Self {
+        self.whitespace = whitespace;
+        self
+    }
+
+    /// Loads character equivalences from a TSV file with two columns: a single character, and the string to replace it
+    /// with (may be empty to remove the character). Lines starting with # are ignored.
+    pub fn with_equivalences_file(mut self, filename: &str) -> Result<Self, String> {
+        let f = File::open(filename)
+            .map_err(|e| format!("Error opening equivalences {}: {}", filename, e))?;
+        for (i, line) in BufReader::new(f).lines().enumerate() {
+            let line =
+                line.map_err(|e| format!("Error reading equivalences {}: {}", filename, e))?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (from, to) = line.split_once('\t').unwrap_or((line.as_str(), ""));
+            let mut chars = from.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => {
+                    self.equivalences.insert(c, to.to_string());
+                }
+                _ => {
+                    return Err(format!(
+                        "Error in equivalences {} line {}: first column must be a single character, got '{}'",
+                        filename,
+                        i + 1,
+                        from
+                    ))
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Returns true if no normalization is configured
+    pub fn is_identity(&self) -> bool {
+        !self.casefold && !self.whitespace && self.equivalences.is_empty()
+    }
+
+    pub fn normalize(&self, text: &str) -> NormalizedText {
+        let mut normalized = String::with_capacity(text.len());
+        let mut map: Vec<(usize, usize)> = Vec::with_capacity(text.len());
+        let mut previous_whitespace = false;
+        let mut originallen = 0;
+        for (i, c) in text.chars().enumerate() {
+            originallen += 1;
+            let mut replacement = match self.equivalences.get(&c) {
+                Some(replacement) => replacement.clone(),
+                None => c.to_string(),
+            };
+            if self.casefold {
+                replacement = replacement.to_lowercase();
+            }
+            for c in replacement.chars() {
+                if self.whitespace && c.is_whitespace() {
+                    if previous_whitespace {
+                        //extend the space we already output
+                        if let Some(last) = map.last_mut() {
+                            last.1 = i + 1;
+                        }
+                        continue;
+                    }
+                    previous_whitespace = true;
+                    normalized.push(' ');
+                } else {
+                    previous_whitespace = false;
+                    normalized.push(c);
+                }
+                map.push((i, i + 1));
+            }
+        }
+        NormalizedText {
+            text: normalized,
+            map,
+            originallen,
+        }
+    }
+}
+
+impl NormalizedText {
+    /// Maps a begin and end offset (unicode points) in the normalized text to the original text
+    pub fn to_original(&self, begin: usize, end: usize) -> (usize, usize) {
+        let position = |offset: usize| {
+            self.map
+                .get(offset)
+                .map(|(begin, _)| *begin)
+                .unwrap_or(self.originallen)
+        };
+        if end > begin {
+            (position(begin), self.map[end - 1].1)
+        } else {
+            (position(begin), position(begin))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options() {
+        assert!(Normalizer::new().is_identity());
+        let normalizer = Normalizer::new().with_options("case, whitespace").unwrap();
+        assert!(normalizer.casefold && normalizer.whitespace);
+        assert!(Normalizer::new().with_options("").unwrap().is_identity());
+        assert!(Normalizer::new().with_options("accents").is_err());
+    }
+
+    #[test]
+    fn casefold_and_whitespace() {
+        let normalizer = Normalizer::new().with_casefold(true).with_whitespace(true);
+        let normalized = normalizer.normalize("The  Quick\n\tFox");
+        assert_eq!(normalized.text, "the quick fox");
+        //"quick"
+        assert_eq!(normalized.to_original(4, 9), (5, 10));
+        //the collapsed whitespace maps back to all of it
+        assert_eq!(normalized.to_original(9, 10), (10, 12));
+        assert_eq!(normalized.to_original(13, 13), (15, 15));
+    }
+
+    #[test]
+    fn expanding_casefold() {
+        //lowercasing may produce more characters than the original
+        let normalized = Normalizer::new().with_casefold(true).normalize("İx");
+        assert_eq!(normalized.text.chars().count(), 3);
+        assert_eq!(normalized.to_original(0, 2), (0, 1));
+        assert_eq!(normalized.to_original(2, 3), (1, 2));
+    }
+
+    #[test]
+    fn equivalences() {
+        let path = std::env::temp_dir().join("stamtools-normalize-equivalences.tsv");
+        std::fs::write(&path, "# comment\nß\tss\n\u{00AD}\n").unwrap();
+        let normalizer = Normalizer::new()
+            .with_equivalences_file(path.to_str().unwrap())
+            .unwrap();
+        let normalized = normalizer.normalize("Stra\u{00AD}ße");
+        assert_eq!(normalized.text, "Strasse");
+        //"sse" stems from "ße"
+        assert_eq!(normalized.to_original(4, 7), (5, 7));
+    }
+
+    #[test]
+    fn invalid_equivalences() {
+        let path = std::env::temp_dir().join("stamtools-normalize-invalid.tsv");
+        std::fs::write(&path, "ab\tc\n").unwrap();
+        let err = Normalizer::new()
+            .with_equivalences_file(path.to_str().unwrap())
+            .unwrap_err();
+        assert!(err.contains("line 1"), "{}", err);
+    }
+}
@@ -0,0 +1,510 @@
+//! Builders for running the TSV export and import procedures programmatically, with the same defaults as the
+//! command-line tool. Example:
+//!
+//! ```no_run
+//! use stam::{AnnotationStore, Config};
+//! use stamtools::pipeline::{ExportJob, ImportJob};
+//!
+//! let mut store = AnnotationStore::from_file("my.store.stam.json", Config::default()).unwrap();
+//! ImportJob::new()
+//!     .with_resource("hello.txt")
+//!     .with_annotationset("my_set")
+//!     .run(&mut store, &["tokens.tsv"])
+//!     .unwrap();
+//! let (header, rows) = ExportJob::new(&["Id", "Text", "my_set/pos"])
+//!     .to_table(&store)
+//!     .unwrap();
+//! ```
+
+use stam::AnnotationStore;
+use std::fs::File;
+use std::io::Write;
+
+use crate::filter::{DataFilter, Selection};
+use crate::tsv::{
+    distinct_rows, from_tsv, group_rows, join_existing, parse_columns, sort_rows, to_json_nested,
+    to_table, to_tsv, write_table, Columns, Dialect, JoinData, JoinReport, Row, RowGroup, Type,
+    ValidationMode,
+};
+use crate::xlsx::{sheets_by_type_or_resource, to_xlsx, write_xlsx};
+
+/// Exports annotations (or other STAM data) in tabular form, like `stam export`
+#[derive(Clone, Debug)]
+pub struct ExportJob<'a> {
+    pub(crate) columns: Vec<&'a str>,
+    pub(crate) tp: Type,
+    pub(crate) flatten: bool,
+    pub(crate) delimiter: &'a str,
+    pub(crate) null: &'a str,
+    pub(crate) header: bool,
+    pub(crate) setdelimiter: &'a str,
+    pub(crate) sort_by: Vec<&'a str>,
+    pub(crate) group_by: Option<&'a str>,
+    pub(crate) distinct: Option<Vec<&'a str>>,
+    pub(crate) relative_to: Option<DataFilter>,
+    pub(crate) selection: Selection,
+    pub(crate) utf8_offsets: bool,
+    pub(crate) dialect: Dialect,
+}
+
+impl<'a> ExportJob<'a> {
+    /// Create a new export job with the specified columns, see `stam export --help` for the supported columns.
+    pub fn new(columns: &[&'a str]) -> Self {
+        Self {
+            columns: columns.to_vec(),
+            tp: Type::Annotation,
+            flatten: true,
+            delimiter: "|",
+            null: "-",
+            header: true,
+            setdelimiter: "/",
+            sort_by: Vec::new(),
+            group_by: None,
+            distinct: None,
+            relative_to: None,
+            selection: Selection::default(),
+            utf8_offsets: false,
+            dialect: Dialect::default(),
+        }
+    }
+
+    /// Select the data type to export rows for (default: [`Type::Annotation`])
+    pub fn with_type(mut self, tp: Type) -> Self {
+        self.tp = tp;
+        self
+    }
+
+    /// Output one row per item rather than also outputting rows for related items (as `--verbose` does)
+    pub fn with_flatten(mut self, flatten: bool) -> Self {
+        self.flatten = flatten;
+        self
+    }
+
+    /// Delimiter for multiple values in a single cell (default: `|`)
+    pub fn with_delimiter(mut self, delimiter: &'a str) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Text to use for NULL values (default: `-`)
+    pub fn with_null(mut self, null: &'a str) -> Self {
+        self.null = null;
+        self
+    }
+
+    /// Output a header row (default: true), only applies to [`Self::to_stdout()`]
+    pub fn with_header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Delimiter between the set and the key in custom columns (default: `/`)
+    pub fn with_setdelimiter(mut self, setdelimiter: &'a str) -> Self {
+        self.setdelimiter = setdelimiter;
+        self
+    }
+
+    /// Sort the rows by the values in these columns, in order of priority. The columns must be part of the output columns.
+    pub fn with_sort_by(mut self, columns: &[&'a str]) -> Self {
+        self.sort_by = columns.to_vec();
+        self
+    }
+
+    /// Group the rows by the value in this column, which must be part of the output columns
+    pub fn with_group_by(mut self, column: &'a str) -> Self {
+        self.group_by = Some(column);
+        self
+    }
+
+    /// Suppress duplicate rows. If columns are passed, rows are compared by the values in these columns only,
+    /// which must be part of the output columns. The first row is kept.
+    pub fn with_distinct(mut self, columns: &[&'a str]) -> Self {
+        self.distinct = Some(columns.to_vec());
+        self
+    }
+
+    /// Compute the relative offset columns relative to the enclosing annotation matching this filter, rather than to the parent annotation
+    pub fn with_relative_to(mut self, filter: DataFilter) -> Self {
+        self.relative_to = Some(filter);
+        self
+    }
+
+    /// Only export the annotations matching this selection (by default all annotations are exported)
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Output the Offset, BeginOffset and EndOffset columns in UTF-8 bytes rather than unicode points (default: false)
+    pub fn with_utf8_offsets(mut self, utf8_offsets: bool) -> Self {
+        self.utf8_offsets = utf8_offsets;
+        self
+    }
+
+    /// The delimiter and quoting of the TSV output (default: plain TSV), for instance to write CSV
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Checks the column configuration
+    pub fn validate(&self) -> Result<(), String> {
+        parse_columns(&self.columns, self.setdelimiter).map(|_| ())
+    }
+
+    /// Parses the column configuration, with the options that affect the columns applied
+    pub(crate) fn parse_columns(&self) -> Result<Columns, String> {
+        Ok(parse_columns(&self.columns, self.setdelimiter)?
+            .with_relative_to(self.relative_to.as_ref())
+            .with_utf8_offsets(self.utf8_offsets))
+    }
+
+    /// Writes TSV to standard output. If grouping is enabled, each group is preceded by a comment line.
+    pub fn to_stdout(&self, store: &AnnotationStore) -> Result<(), String> {
+        if self.sort_by.is_empty() && self.group_by.is_none() && self.distinct.is_none() {
+            //no need to hold all rows in memory
+            return to_tsv(store, self);
+        }
+        let (header, groups) = self.to_groups(store)?;
+        let mut stdout = std::io::stdout().lock();
+        if self.header {
+            write_table(&mut stdout, Some(&header), &[], &self.dialect)?;
+        }
+        for (value, rows) in groups {
+            if let Some(group_by) = self.group_by {
+                writeln!(stdout, "# {}: {}", group_by, value).map_err(|e| format!("{}", e))?;
+            }
+            write_table(&mut stdout, None, &rows, &self.dialect)?;
+        }
+        Ok(())
+    }
+
+    /// Writes one TSV file per group, `{group}` in the filename template is replaced by the value of the group.
+    /// If the template contains `{resource}`, the rows are split further per resource and it is replaced by the
+    /// resource ID (or `_none_` for rows that pertain to no resource). Characters that are not valid in filenames
+    /// are replaced by underscores. Returns the filenames.
+    pub fn to_files(&self, store: &AnnotationStore, template: &str) -> Result<Vec<String>, String> {
+        let (header, groups) = self.to_groups(store)?;
+        let sanitize = |value: &str| -> String {
+            value
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || "-_.".contains(c) {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect()
+        };
+        let mut filenames = Vec::new();
+        for (value, rows) in groups {
+            let mut parts: Vec<(String, Vec<Row>)> = Vec::new();
+            if template.contains("{resource}") {
+                for row in rows {
+                    let resource = row.resource.as_deref().unwrap_or("(none)");
+                    match parts.iter_mut().find(|(id, _)| id == resource) {
+                        Some((_, rows)) => rows.push(row),
+                        None => parts.push((resource.to_string(), vec![row])),
+                    }
+                }
+            } else {
+                parts.push((String::new(), rows));
+            }
+            for (resource, rows) in parts {
+                let filename = template
+                    .replace("{group}", &sanitize(&value))
+                    .replace("{resource}", &sanitize(&resource));
+                let mut f = File::create(&filename)
+                    .map_err(|e| format!("Failed to write {}: {}", filename, e))?;
+                write_table(
+                    &mut f,
+                    Some(header.as_slice()).filter(|_| self.header),
+                    &rows,
+                    &self.dialect,
+                )?;
+                filenames.push(filename);
+            }
+        }
+        Ok(filenames)
+    }
+
+    /// Returns the header and all rows, sorted and with the rows of each group together
+    pub fn to_table(&self, store: &AnnotationStore) -> Result<(Vec<String>, Vec<Row>), String> {
+        let (header, groups) = self.to_groups(store)?;
+        Ok((
+            header,
+            groups.into_iter().flat_map(|(_, rows)| rows).collect(),
+        ))
+    }
+
+    /// Returns the header and the sorted rows per group. If grouping is not enabled, there is one group with an empty name.
+    pub fn to_groups(
+        &self,
+        store: &AnnotationStore,
+    ) -> Result<(Vec<String>, Vec<RowGroup>), String> {
+        let (header, mut rows) = to_table(store, self)?;
+        if let Some(distinct) = self.distinct.as_ref() {
+            rows = distinct_rows(rows, &self.columns, self.setdelimiter, distinct)?;
+        }
+        if !self.sort_by.is_empty() {
+            sort_rows(&mut rows, &self.columns, self.setdelimiter, &self.sort_by)?;
+        }
+        let groups = if let Some(group_by) = self.group_by {
+            group_rows(rows, &self.columns, self.setdelimiter, group_by)?
+        } else {
+            vec![(String::new(), rows)]
+        };
+        Ok((header, groups))
+    }
+
+    /// Writes annotations as nested JSON, with the annotations that each annotation targets nested under it (see [`to_json_nested()`]).
+    /// Sorting, grouping and the type do not apply.
+    pub fn to_json_nested(
+        &self,
+        writer: &mut impl Write,
+        store: &AnnotationStore,
+    ) -> Result<(), String> {
+        to_json_nested(writer, store, self)
+    }
+
+    /// Writes a spreadsheet (XLSX) to the specified file. There is one sheet per group if grouping is enabled,
+    /// otherwise one sheet per type or per resource.
+    pub fn to_xlsx(
+        &self,
+        store: &AnnotationStore,
+        filename: &str,
+        sheet_per_resource: bool,
+    ) -> Result<(), String> {
+        if self.sort_by.is_empty() && self.group_by.is_none() && self.distinct.is_none() {
+            return to_xlsx(store, self, filename, sheet_per_resource);
+        }
+        let (header, groups) = self.to_groups(store)?;
+        let sheets: Vec<(String, Vec<&Row>)> = if self.group_by.is_some() && !groups.is_empty() {
+            groups
+                .iter()
+                .map(|(value, rows)| (value.clone(), rows.iter().collect()))
+                .collect()
+        } else {
+            sheets_by_type_or_resource(
+                groups.iter().flat_map(|(_, rows)| rows.iter()),
+                self.tp,
+                sheet_per_resource,
+            )
+        };
+        write_xlsx(&header, &sheets, filename)
+    }
+}
+
+/// Imports annotations from TSV files, like `stam import`
+#[derive(Clone, Debug)]
+pub struct ImportJob<'a> {
+    pub(crate) columns: Option<Vec<&'a str>>,
+    pub(crate) existing_resource: Option<&'a str>,
+    pub(crate) new_resource: Option<&'a str>,
+    pub(crate) default_set: Option<&'a str>,
+    pub(crate) joins: Vec<&'a str>,
+    pub(crate) groups: Vec<&'a str>,
+    pub(crate) comments: bool,
+    pub(crate) sequential: bool,
+    pub(crate) case_sensitive: bool,
+    pub(crate) align_window: usize,
+    pub(crate) escape: bool,
+    pub(crate) null: &'a str,
+    pub(crate) subdelimiter: &'a str,
+    pub(crate) setdelimiter: &'a str,
+    pub(crate) outputdelimiter: &'a str,
+    pub(crate) outputdelimiter2: &'a str,
+    pub(crate) header: Option<bool>,
+    pub(crate) validation: ValidationMode,
+    pub(crate) utf8_offsets: bool,
+    pub(crate) dialect: Dialect,
+    pub(crate) verbose: bool,
+    pub(crate) progress: bool,
+}
+
+impl<'a> Default for ImportJob<'a> {
+    fn default() -> Self {
+        Self {
+            columns: None,
+            existing_resource: None,
+            new_resource: None,
+            default_set: None,
+            joins: Vec::new(),
+            groups: Vec::new(),
+            comments: true,
+            sequential: true,
+            case_sensitive: true,
+            align_window: 1000,
+            escape: true,
+            null: "-",
+            subdelimiter: "|",
+            setdelimiter: "/",
+            outputdelimiter: " ",
+            outputdelimiter2: "\n",
+            header: None,
+            validation: ValidationMode::Loose,
+            utf8_offsets: false,
+            dialect: Dialect::default(),
+            verbose: false,
+            progress: false,
+        }
+    }
+}
+
+impl<'a> ImportJob<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the column configuration explicitly, rather than reading it from the header
+    pub fn with_columns(mut self, columns: &[&'a str]) -> Self {
+        self.columns = Some(columns.to_vec());
+        self
+    }
+
+    /// Existing resource to use when the TSV data does not reference one
+    pub fn with_resource(mut self, resource: &'a str) -> Self {
+        self.existing_resource = Some(resource);
+        self
+    }
+
+    /// Filename of a resource to reconstruct from the TSV data
+    pub fn with_new_resource(mut self, resource: &'a str) -> Self {
+        self.new_resource = Some(resource);
+        self
+    }
+
+    /// Default annotation set for custom columns
+    pub fn with_annotationset(mut self, set: &'a str) -> Self {
+        self.default_set = Some(set);
+        self
+    }
+
+    /// Add a secondary TSV file with extra data for annotations, matched by ID (`file.tsv:column`, see [`JoinData::load()`])
+    pub fn with_join(mut self, spec: &'a str) -> Self {
+        self.joins.push(spec);
+        self
+    }
+
+    /// Add a grouping column (a custom column like `my_set/sentence`): all rows sharing a value in it are targeted by
+    /// one new annotation with that value
+    pub fn with_group(mut self, column: &'a str) -> Self {
+        self.groups.push(column);
+        self
+    }
+
+    /// Skip lines starting with `#` (default: true)
+    pub fn with_comments(mut self, comments: bool) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    /// Assume the rows are in the same order as the text when looking up offsets (default: true)
+    pub fn with_sequential(mut self, sequential: bool) -> Self {
+        self.sequential = sequential;
+        self
+    }
+
+    /// Match text case sensitively when looking up offsets (default: true)
+    pub fn with_case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// When aligning with an existing resource, first search only within this many characters after the previous
+    /// match (or around the offset hint), before searching the remainder of the text (default: 1000)
+    pub fn with_align_window(mut self, align_window: usize) -> Self {
+        self.align_window = align_window;
+        self
+    }
+
+    /// Interpret `\t` and `\n` in values (default: true)
+    pub fn with_escape(mut self, escape: bool) -> Self {
+        self.escape = escape;
+        self
+    }
+
+    /// Text that denotes NULL values (default: `-`)
+    pub fn with_null(mut self, null: &'a str) -> Self {
+        self.null = null;
+        self
+    }
+
+    /// Delimiter for multiple values in a single cell (default: `|`)
+    pub fn with_subdelimiter(mut self, subdelimiter: &'a str) -> Self {
+        self.subdelimiter = subdelimiter;
+        self
+    }
+
+    /// Delimiter between the set and the key in custom columns (default: `/`)
+    pub fn with_setdelimiter(mut self, setdelimiter: &'a str) -> Self {
+        self.setdelimiter = setdelimiter;
+        self
+    }
+
+    /// Delimiters inserted after each row and after each empty line when reconstructing text (default: a space and a newline)
+    pub fn with_outputdelimiters(mut self, row: &'a str, emptyline: &'a str) -> Self {
+        self.outputdelimiter = row;
+        self.outputdelimiter2 = emptyline;
+        self
+    }
+
+    /// Whether the first line is a header, `None` autodetects (default)
+    pub fn with_header(mut self, header: Option<bool>) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// How strictly to check the text in the TSV data against the resource (default: [`ValidationMode::Loose`])
+    pub fn with_validation(mut self, validation: ValidationMode) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Interpret the Offset, BeginOffset and EndOffset columns as UTF-8 bytes rather than unicode points (default: false)
+    pub fn with_utf8_offsets(mut self, utf8_offsets: bool) -> Self {
+        self.utf8_offsets = utf8_offsets;
+        self
+    }
+
+    /// The delimiter and quoting of the input files (default: plain TSV), for instance to read CSV
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Show a progress indicator on standard error while reading each file
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Imports the specified TSV files into the store. Returns a report on the data of the joined files that was added
+    /// to annotations already in the store, and the IDs in the joined files that did not match any annotation.
+    pub fn run(
+        &self,
+        store: &mut AnnotationStore,
+        filenames: &[&str],
+    ) -> Result<JoinReport, String> {
+        let mut joins = JoinData::default();
+        for spec in self.joins.iter() {
+            joins.load(spec, self)?;
+        }
+        let (newstore, mut report) = join_existing(store, &mut joins)?;
+        if let Some(newstore) = newstore {
+            *store = newstore;
+        }
+        for filename in filenames {
+            from_tsv(store, filename, self, &mut joins)?;
+        }
+        report.unmatched = joins.ids().map(|id| id.to_string()).collect();
+        report.unmatched.sort();
+        Ok(report)
+    }
+}
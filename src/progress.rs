@@ -0,0 +1,108 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// A simple progress indicator with an estimated time of arrival, written to standard error.
+/// The total amount of work must be known up front. If not enabled, all methods are no-ops.
+pub struct Progress {
+    label: String,
+    total: usize,
+    done: usize,
+    start: Instant,
+    last: Option<Instant>,
+    enabled: bool,
+}
+
+/// Minimum time between redraws
+const INTERVAL: Duration = Duration::from_millis(200);
+
+impl Progress {
+    pub fn new(label: impl Into<String>, total: usize, enabled: bool) -> Self {
+        Self {
+            label: label.into(),
+            total,
+            done: 0,
+            start: Instant::now(),
+            last: None,
+            enabled,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Advance the progress by the given amount
+    pub fn inc(&mut self, amount: usize) {
+        self.set(self.done + amount)
+    }
+
+    /// Set the amount of work done so far
+    pub fn set(&mut self, done: usize) {
+        self.done = done.min(self.total);
+        if self.enabled && self.done < self.total {
+            let now = Instant::now();
+            if self.last.map(|last| now - last >= INTERVAL).unwrap_or(true) {
+                self.last = Some(now);
+                self.draw(false);
+            }
+        }
+    }
+
+    /// Mark the work as completed, prints the final state and moves to the next line
+    pub fn finish(&mut self) {
+        if self.enabled {
+            self.done = self.total;
+            self.draw(true);
+        }
+    }
+
+    fn draw(&self, finished: bool) {
+        const WIDTH: usize = 30;
+        let fraction = if self.total == 0 {
+            1.0
+        } else {
+            self.done as f64 / self.total as f64
+        };
+        let filled = (fraction * WIDTH as f64) as usize;
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let time = if finished {
+            format!("done in {}", format_duration(elapsed))
+        } else if fraction > 0.0 {
+            format!("ETA {}", format_duration(elapsed / fraction - elapsed))
+        } else {
+            "ETA -".to_string()
+        };
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r{}: [{}{}] {:>3}% ({}/{}) {}   ",
+            self.label,
+            "#".repeat(filled),
+            " ".repeat(WIDTH - filled),
+            (fraction * 100.0) as usize,
+            self.done,
+            self.total,
+            time
+        );
+        if finished {
+            let _ = writeln!(stderr);
+        }
+        let _ = stderr.flush();
+    }
+}
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    if seconds >= 3600 {
+        format!(
+            "{}h{:02}m{:02}s",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else if seconds >= 60 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
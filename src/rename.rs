@@ -0,0 +1,377 @@
+use clap::{Arg, ArgAction};
+use serde_json::Value;
+use stam::{AnnotationStore, Regex};
+use std::collections::{HashMap, HashSet};
+
+use crate::rewrite::{rename_annotation_refs, StoreJson};
+
+pub fn rename_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("set")
+            .long("set")
+            .help("Rename an annotation set, the syntax is old=new. This option may be specified multiple times.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("key")
+            .long("key")
+            .help("Rename a data key, the syntax is set/old=new, where set is the ID of the annotation set before any renaming by --set. If the new key already exists, the old key is merged into it. This option may be specified multiple times.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("annotation-id")
+            .long("annotation-id")
+            .help("Rename annotations whose ID matches a regular expression, the syntax is regex=template (split at the last =). The template may refer to capture groups using $1, $2 or ${name}, for example: ^A(\\d+)$=ann.$1. This option may be specified multiple times, the first matching expression applies.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("setdelimiter")
+            .long("setdelimiter")
+            .env("STAM_SETDELIMITER")
+            .help("The delimiter between the annotation set and the key in --key")
+            .takes_value(true)
+            .default_value("/"),
+        Arg::with_name("outputfile")
+            .long("outputfile")
+            .short('o')
+            .help("Output filename for the annotation store, if not set the input store is overwritten")
+            .takes_value(true),
+    ]
+}
+
+/// The renames to carry out, see [`rename()`]
+#[derive(Clone, Debug, Default)]
+pub struct Renames {
+    /// Old and new set ID
+    pub sets: Vec<(String, String)>,
+    /// Set ID, old and new key
+    pub keys: Vec<(String, String, String)>,
+    /// Expression and replacement template for annotation IDs
+    pub annotation_ids: Vec<(Regex, String)>,
+}
+
+impl Renames {
+    /// Parses the renames as passed to the `--set`, `--key` and `--annotation-id` parameters
+    pub fn parse<'a>(
+        sets: impl Iterator<Item = &'a str>,
+        keys: impl Iterator<Item = &'a str>,
+        annotation_ids: impl Iterator<Item = &'a str>,
+        setdelimiter: &str,
+    ) -> Result<Self, String> {
+        let mut renames = Self::default();
+        for s in sets {
+            renames.add_set(s)?;
+        }
+        for s in keys {
+            renames.add_key(s, setdelimiter)?;
+        }
+        for s in annotation_ids {
+            renames.add_annotation_id(s)?;
+        }
+        Ok(renames)
+    }
+
+    /// Parses a set rename (`old=new`)
+    fn add_set(&mut self, s: &str) -> Result<(), String> {
+        let (old, new) = split_rename(s)?;
+        self.sets.push((old.to_string(), new.to_string()));
+        Ok(())
+    }
+
+    /// Parses a key rename (`set/old=new`)
+    fn add_key(&mut self, s: &str, setdelimiter: &str) -> Result<(), String> {
+        let (setkey, new) = split_rename(s)?;
+        let (set, old) = setkey.rsplit_once(setdelimiter).ok_or_else(|| {
+            format!(
+                "Invalid key rename: {} (expected set{}old=new)",
+                s, setdelimiter
+            )
+        })?;
+        self.keys
+            .push((set.to_string(), old.to_string(), new.to_string()));
+        Ok(())
+    }
+
+    /// Parses an annotation ID rename (`regex=template`)
+    fn add_annotation_id(&mut self, s: &str) -> Result<(), String> {
+        let (expression, template) = split_rename(s)?;
+        let expression = Regex::new(expression)
+            .map_err(|e| format!("Invalid regular expression {}: {}", expression, e))?;
+        self.annotation_ids.push((expression, template.to_string()));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty() && self.keys.is_empty() && self.annotation_ids.is_empty()
+    }
+}
+
+fn split_rename(s: &str) -> Result<(&str, &str), String> {
+    match s.rsplit_once('=') {
+        Some((old, new)) if !old.is_empty() && !new.is_empty() => Ok((old, new)),
+        _ => Err(format!("Invalid rename: {} (expected old=new)", s)),
+    }
+}
+
+/// What was changed by [`rename()`]
+#[derive(Clone, Debug, Default)]
+pub struct RenameReport {
+    /// Number of annotation sets renamed
+    pub sets: usize,
+    /// Number of data keys renamed
+    pub keys: usize,
+    /// Number of data keys merged into an existing key
+    pub merged_keys: usize,
+    /// Number of annotation data removed because, after merging keys, they were identical to other data
+    pub merged_data: usize,
+    /// Number of annotations renamed
+    pub annotations: usize,
+    /// Number of references (from annotations to data, sets and other annotations) updated
+    pub references: usize,
+    /// Changed sets that keep their original standoff file, as (ID, filename); these files must be rewritten
+    pub setfiles: Vec<(String, String)>,
+}
+
+impl RenameReport {
+    /// Returns true if nothing was changed
+    pub fn is_empty(&self) -> bool {
+        self.sets == 0 && self.keys == 0 && self.annotations == 0
+    }
+}
+
+/// Renames data keys, annotation sets and annotations (in that order) and updates all references to them.
+/// When a key is renamed to a key that already exists in the set, the keys are merged and any data that
+/// thereby becomes identical to other data (same key and value) is merged as well.
+///
+/// Returns the new store and a report.
+pub fn rename(
+    store: &AnnotationStore,
+    renames: &Renames,
+) -> Result<(AnnotationStore, RenameReport), String> {
+    let mut storejson = StoreJson::from_store(store)?;
+    let mut report = RenameReport::default();
+    //maps new set IDs to the original ones, to find the standoff files afterwards
+    let mut changedsets: HashMap<String, String> = HashMap::new();
+
+    for (set_id, old, new) in renames.keys.iter() {
+        rename_key(&mut storejson, set_id, old, new, &mut report)?;
+        changedsets.insert(set_id.clone(), set_id.clone());
+    }
+
+    for (old, new) in renames.sets.iter() {
+        if old == new {
+            continue;
+        }
+        if set_exists(&storejson, new) {
+            return Err(format!("Annotation set {} already exists", new));
+        }
+        let annotationset = storejson
+            .annotationsets
+            .iter_mut()
+            .find(|annotationset| annotationset["@id"].as_str() == Some(old.as_str()))
+            .ok_or_else(|| format!("Annotation set {} not found", old))?;
+        annotationset["@id"] = new.as_str().into();
+        report.sets += 1;
+        for annotation in storejson.annotations.iter_mut() {
+            if let Some(Value::Array(data)) = annotation.get_mut("data") {
+                for data in data.iter_mut() {
+                    if data["set"].as_str() == Some(old.as_str()) {
+                        data["set"] = new.as_str().into();
+                        report.references += 1;
+                    }
+                }
+            }
+            if let Some(target) = annotation.get_mut("target") {
+                report.references += rename_set_refs(target, old, new);
+            }
+        }
+        let original = changedsets.remove(old).unwrap_or_else(|| old.clone());
+        changedsets.insert(new.clone(), original);
+    }
+
+    if !renames.annotation_ids.is_empty() {
+        let mut idmap: HashMap<String, String> = HashMap::new();
+        for annotation in storejson.annotations.iter_mut() {
+            let old_id = match annotation.get("@id").and_then(|x| x.as_str()) {
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+            if let Some((expression, template)) = renames
+                .annotation_ids
+                .iter()
+                .find(|(expression, _)| expression.is_match(&old_id))
+            {
+                let new_id = expression.replace(&old_id, template.as_str()).to_string();
+                if new_id != old_id {
+                    annotation["@id"] = new_id.as_str().into();
+                    idmap.insert(old_id, new_id);
+                }
+            }
+        }
+        let mut ids = HashSet::new();
+        for annotation in storejson.annotations.iter() {
+            if let Some(id) = annotation.get("@id").and_then(|x| x.as_str()) {
+                if !ids.insert(id) {
+                    return Err(format!(
+                        "Renaming annotations would result in a duplicate ID: {}",
+                        id
+                    ));
+                }
+            }
+        }
+        report.annotations = idmap.len();
+        for annotation in storejson.annotations.iter_mut() {
+            if let Some(target) = annotation.get_mut("target") {
+                report.references += count_annotation_refs(target, &idmap);
+                rename_annotation_refs(target, &idmap);
+            }
+        }
+    }
+
+    //changed sets keep their original standoff file, which must be rewritten
+    for (set_id, original) in changedsets {
+        if let Some(filename) = storejson
+            .annotationset_filename(&original)
+            .map(|x| x.to_string())
+        {
+            storejson.set_annotationset_filename(&set_id, &filename);
+            report.setfiles.push((set_id, filename));
+        }
+    }
+    report.setfiles.sort();
+
+    Ok((storejson.into_store(store)?, report))
+}
+
+fn set_exists(storejson: &StoreJson, id: &str) -> bool {
+    storejson
+        .annotationsets
+        .iter()
+        .any(|annotationset| annotationset["@id"].as_str() == Some(id))
+}
+
+/// Renames (or merges) a key in a set, along with the data and annotations using it
+fn rename_key(
+    storejson: &mut StoreJson,
+    set_id: &str,
+    old: &str,
+    new: &str,
+    report: &mut RenameReport,
+) -> Result<(), String> {
+    if old == new {
+        return Ok(());
+    }
+    let annotationset = storejson
+        .annotationsets
+        .iter_mut()
+        .find(|annotationset| annotationset["@id"].as_str() == Some(set_id))
+        .ok_or_else(|| format!("Annotation set {} not found", set_id))?;
+    let keys = match annotationset.get_mut("keys") {
+        Some(Value::Array(keys)) => keys,
+        _ => return Err(format!("Key {} not found in set {}", old, set_id)),
+    };
+    let exists = |keys: &[Value], id: &str| keys.iter().any(|key| key["@id"].as_str() == Some(id));
+    if !exists(keys, old) {
+        return Err(format!("Key {} not found in set {}", old, set_id));
+    }
+    if exists(keys, new) {
+        keys.retain(|key| key["@id"].as_str() != Some(old));
+        report.merged_keys += 1;
+    } else {
+        for key in keys.iter_mut() {
+            if key["@id"].as_str() == Some(old) {
+                key["@id"] = new.into();
+            }
+        }
+    }
+    report.keys += 1;
+
+    //maps the IDs of data that became duplicates to the IDs of the data they are identical to
+    let mut idmap: HashMap<String, String> = HashMap::new();
+    if let Some(Value::Array(data)) = annotationset.get_mut("data") {
+        for data in data.iter_mut() {
+            if data["key"].as_str() == Some(old) {
+                data["key"] = new.into();
+            }
+        }
+        let mut seen: HashMap<String, String> = HashMap::new();
+        data.retain(|data| {
+            if data["key"].as_str() != Some(new) {
+                return true;
+            }
+            let id = data["@id"].as_str().unwrap_or("").to_string();
+            match seen.get(&data["value"].to_string()) {
+                Some(existing) if !id.is_empty() => {
+                    idmap.insert(id, existing.clone());
+                    false
+                }
+                Some(_) => true,
+                None => {
+                    if !id.is_empty() {
+                        seen.insert(data["value"].to_string(), id);
+                    }
+                    true
+                }
+            }
+        });
+        report.merged_data += idmap.len();
+    }
+
+    for annotation in storejson.annotations.iter_mut() {
+        if let Some(Value::Array(data)) = annotation.get_mut("data") {
+            for data in data.iter_mut() {
+                if data["set"].as_str() != Some(set_id) {
+                    continue;
+                }
+                if data["key"].as_str() == Some(old) {
+                    data["key"] = new.into();
+                    report.references += 1;
+                }
+                if let Some(existing) = data["@id"].as_str().and_then(|id| idmap.get(id)) {
+                    data["@id"] = existing.as_str().into();
+                    report.references += 1;
+                }
+            }
+            //an annotation may now refer to the same data twice
+            let mut seen = HashSet::new();
+            data.retain(|data| match data["@id"].as_str() {
+                Some(id) => seen.insert((data["set"].to_string(), id.to_string())),
+                None => true,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Renames the annotation set in all DataSetSelectors in a STAM JSON selector, returns the number of references updated
+fn rename_set_refs(selector: &mut Value, old: &str, new: &str) -> usize {
+    let mut count = 0;
+    if selector.get("@type").and_then(|x| x.as_str()) == Some("DataSetSelector") {
+        for field in ["dataset", "resource"] {
+            if selector.get(field).and_then(|x| x.as_str()) == Some(old) {
+                selector[field] = new.into();
+                count += 1;
+            }
+        }
+    } else if let Some(Value::Array(subselectors)) = selector.get_mut("selectors") {
+        for subselector in subselectors.iter_mut() {
+            count += rename_set_refs(subselector, old, new);
+        }
+    }
+    count
+}
+
+/// Counts the references to any of the annotations in the map in a STAM JSON selector
+fn count_annotation_refs(selector: &Value, map: &HashMap<String, String>) -> usize {
+    if selector.get("@type").and_then(|x| x.as_str()) == Some("AnnotationSelector") {
+        selector
+            .get("annotation")
+            .and_then(|x| x.as_str())
+            .map(|id| map.contains_key(id) as usize)
+            .unwrap_or(0)
+    } else if let Some(Value::Array(subselectors)) = selector.get("selectors") {
+        subselectors
+            .iter()
+            .map(|subselector| count_annotation_refs(subselector, map))
+            .sum()
+    } else {
+        0
+    }
+}
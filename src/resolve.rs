@@ -0,0 +1,397 @@
+//! Entity linking via a reconciliation service that implements the
+//! [Reconciliation Service API](https://www.w3.org/community/reports/reconciliation/CG-FINAL-specs-0.2-20230410/)
+//! (as used by OpenRefine), such as the one for Wikidata.
+
+use clap::Arg;
+use serde_json::{json, Map, Value};
+use stam::{AnnotationStore, Storable};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::filter::{DataFilter, FilterOperator};
+use crate::rewrite::StoreJson;
+
+pub const DEFAULT_ENDPOINT: &str = "https://wikidata.reconci.link/en/api";
+
+pub fn resolve_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("key")
+            .long("key")
+            .help("Resolve the text of the annotations with this data, e.g. my_set/entity or my_set/type=person. The syntax is that of --filter in other subcommands.")
+            .takes_value(true)
+            .required(true),
+        Arg::with_name("result-key")
+            .long("result-key")
+            .help("Add the identifiers as data with this set and key (set/key). Annotations that already have this data are skipped. Defaults to the key of --key with the suffix _id, in the same set.")
+            .takes_value(true),
+        Arg::with_name("endpoint")
+            .long("endpoint")
+            .env("STAM_RESOLVE_ENDPOINT")
+            .help("The URL of the reconciliation service")
+            .takes_value(true)
+            .default_value(DEFAULT_ENDPOINT),
+        Arg::with_name("type")
+            .long("type")
+            .help("Only consider candidates of this type, as defined by the reconciliation service (e.g. Q5 for humans in Wikidata)")
+            .takes_value(true),
+        Arg::with_name("min-score")
+            .long("min-score")
+            .help("Accept the best candidate if its score is at least this number, even if the service does not consider it a match. By default only matches are accepted.")
+            .takes_value(true),
+        Arg::with_name("batch-size")
+            .long("batch-size")
+            .help("The number of surface forms to send to the service in a single request")
+            .takes_value(true)
+            .default_value("10"),
+        Arg::with_name("delay")
+            .long("delay")
+            .help("Wait this many milliseconds between requests, to respect the rate limits of the service")
+            .takes_value(true)
+            .default_value("1000"),
+        Arg::with_name("cache")
+            .long("cache")
+            .help("TSV file to cache the results in, surface forms found in it are not sent to the service again. It is created if it does not exist yet.")
+            .takes_value(true),
+        Arg::with_name("setdelimiter")
+            .long("setdelimiter")
+            .env("STAM_SETDELIMITER")
+            .help("The delimiter between the annotation set and the key in --key and --result-key")
+            .takes_value(true)
+            .default_value("/"),
+        Arg::with_name("outputfile")
+            .long("outputfile")
+            .short('o')
+            .help("Output filename for the annotation store, if not set the input store is overwritten")
+            .takes_value(true),
+    ]
+}
+
+/// A candidate entity returned by the reconciliation service
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub id: String,
+    pub name: String,
+    pub score: f64,
+    /// Whether the service considers this a match
+    pub matched: bool,
+}
+
+/// Resolves surface forms to identifiers using a reconciliation service. Results (including the lack of a result)
+/// are cached, so each surface form is only sent to the service once.
+pub struct Resolver {
+    endpoint: String,
+    entity_type: Option<String>,
+    min_score: Option<f64>,
+    batch_size: usize,
+    delay: Duration,
+    cache: BTreeMap<String, Option<Candidate>>,
+    requests: usize,
+}
+
+impl Resolver {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            entity_type: None,
+            min_score: None,
+            batch_size: 10,
+            delay: Duration::from_millis(1000),
+            cache: BTreeMap::new(),
+            requests: 0,
+        }
+    }
+
+    /// Only consider candidates of this type
+    pub fn with_type(mut self, entity_type: &str) -> Self {
+        self.entity_type = Some(entity_type.to_string());
+        self
+    }
+
+    /// Accept the best candidate if its score is at least this number, even if it is not a match (by default only matches are accepted)
+    pub fn with_min_score(mut self, min_score: f64) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    /// The number of surface forms to send in a single request (default: 10)
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The time to wait between requests (default: 1 second)
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Loads cached results from a TSV file, if it exists
+    pub fn load_cache(&mut self, filename: &str) -> Result<(), String> {
+        let file = match File::open(filename) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(format!("Unable to open {}: {}", filename, e)),
+        };
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("Unable to read {}: {}", filename, e))?;
+            if i == 0 || line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 5 {
+                return Err(format!(
+                    "{}, line {}: expected 5 columns, got {}",
+                    filename,
+                    i + 1,
+                    fields.len()
+                ));
+            }
+            let candidate = if fields[1].is_empty() {
+                None
+            } else {
+                Some(Candidate {
+                    id: fields[1].to_string(),
+                    name: fields[2].to_string(),
+                    score: fields[3].parse().unwrap_or(0.0),
+                    matched: fields[4] == "true",
+                })
+            };
+            self.cache.insert(fields[0].to_string(), candidate);
+        }
+        Ok(())
+    }
+
+    /// Writes all results to a TSV file
+    pub fn save_cache(&self, filename: &str) -> Result<(), String> {
+        let file =
+            File::create(filename).map_err(|e| format!("Unable to create {}: {}", filename, e))?;
+        let mut writer = BufWriter::new(file);
+        let mut write = |line: String| writeln!(writer, "{}", line).map_err(|e| format!("{}", e));
+        write("Query\tId\tName\tScore\tMatch".to_string())?;
+        for (query, candidate) in self.cache.iter() {
+            match candidate {
+                Some(candidate) => write(format!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    query,
+                    candidate.id,
+                    candidate.name.replace(['\t', '\n'], " "),
+                    candidate.score,
+                    candidate.matched
+                ))?,
+                None => write(format!("{}\t\t\t\t", query))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of requests sent to the service so far
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    /// Returns the accepted candidate for a surface form, if it was resolved
+    pub fn get(&self, query: &str) -> Option<&Candidate> {
+        self.cache.get(query)?.as_ref().filter(|candidate| {
+            candidate.matched
+                || self
+                    .min_score
+                    .map(|min_score| candidate.score >= min_score)
+                    .unwrap_or(false)
+        })
+    }
+
+    /// Sends all surface forms that are not cached yet to the service, in batches
+    pub fn resolve<'a>(&mut self, queries: impl Iterator<Item = &'a str>) -> Result<(), String> {
+        let mut pending: Vec<&str> = queries.filter(|q| !self.cache.contains_key(*q)).collect();
+        pending.sort();
+        pending.dedup();
+        for batch in pending.chunks(self.batch_size) {
+            if self.requests > 0 {
+                sleep(self.delay);
+            }
+            let results = self.request(batch)?;
+            for (i, query) in batch.iter().enumerate() {
+                self.cache
+                    .insert(query.to_string(), results.get(&i).cloned().flatten());
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a single request, retrying a few times if the service asks us to slow down.
+    /// Returns the best candidate for each query, by index.
+    fn request(&mut self, batch: &[&str]) -> Result<HashMap<usize, Option<Candidate>>, String> {
+        let mut queries = Map::new();
+        for (i, query) in batch.iter().enumerate() {
+            let mut q = json!({ "query": query, "limit": 1 });
+            if let Some(entity_type) = self.entity_type.as_ref() {
+                q["type"] = entity_type.as_str().into();
+            }
+            queries.insert(format!("q{}", i), q);
+        }
+        let queries = Value::Object(queries).to_string();
+        let mut attempt = 0;
+        let response = loop {
+            self.requests += 1;
+            match ureq::post(&self.endpoint)
+                .timeout(Duration::from_secs(60))
+                .send_form(&[("queries", queries.as_str())])
+            {
+                Ok(response) => break response,
+                Err(ureq::Error::Status(429, response))
+                | Err(ureq::Error::Status(503, response))
+                    if attempt < 3 =>
+                {
+                    attempt += 1;
+                    let wait = response
+                        .header("Retry-After")
+                        .and_then(|x| x.parse().ok())
+                        .map(Duration::from_secs)
+                        .unwrap_or(self.delay * 5 * attempt);
+                    sleep(wait);
+                }
+                Err(e) => {
+                    return Err(format!(
+                        "Request to reconciliation service {} failed: {}",
+                        self.endpoint, e
+                    ))
+                }
+            }
+        };
+        let response: Value = response
+            .into_string()
+            .map_err(|e| format!("{}", e))
+            .and_then(|response| serde_json::from_str(&response).map_err(|e| format!("{}", e)))
+            .map_err(|e| format!("Invalid response from reconciliation service: {}", e))?;
+        let mut results = HashMap::new();
+        for i in 0..batch.len() {
+            let candidate = response
+                .get(format!("q{}", i))
+                .and_then(|result| result.get("result"))
+                .and_then(|result| result.as_array())
+                .and_then(|candidates| candidates.first())
+                .and_then(|candidate| {
+                    Some(Candidate {
+                        id: candidate.get("id")?.as_str()?.to_string(),
+                        name: candidate
+                            .get("name")
+                            .and_then(|x| x.as_str())
+                            .unwrap_or("")
+                            .to_string(),
+                        score: candidate
+                            .get("score")
+                            .and_then(|x| x.as_f64())
+                            .unwrap_or(0.0),
+                        matched: candidate
+                            .get("match")
+                            .and_then(|x| x.as_bool())
+                            .unwrap_or(false),
+                    })
+                });
+            results.insert(i, candidate);
+        }
+        Ok(results)
+    }
+}
+
+/// What was done by [`resolve()`]
+#[derive(Clone, Debug, Default)]
+pub struct ResolveReport {
+    /// Number of annotations that received an identifier
+    pub resolved: usize,
+    /// Number of annotations for which no (acceptable) candidate was found
+    pub unresolved: usize,
+    /// Number of annotations skipped because they already had an identifier
+    pub skipped: usize,
+    /// Changed sets that keep their original standoff file, as (ID, filename); these files must be rewritten
+    pub setfiles: Vec<(String, String)>,
+}
+
+/// Resolves the text of all annotations matching the filter and adds the identifiers of the accepted candidates as
+/// data with key `result_key` in set `result_set`. Annotations that already have such data are skipped.
+///
+/// Returns the new store and a report.
+pub fn resolve(
+    store: &AnnotationStore,
+    filter: &DataFilter,
+    result_set: &str,
+    result_key: &str,
+    resolver: &mut Resolver,
+) -> Result<(AnnotationStore, ResolveReport), String> {
+    let resultfilter = DataFilter {
+        set: result_set.to_string(),
+        key: result_key.to_string(),
+        op: FilterOperator::Any,
+        value: None,
+    };
+    let mut report = ResolveReport::default();
+    //surface forms by annotation ID
+    let mut queries: HashMap<String, String> = HashMap::new();
+    for annotation in store
+        .annotations()
+        .filter(|annotation| filter.test(annotation))
+    {
+        if resultfilter.test(&annotation) {
+            report.skipped += 1;
+            continue;
+        }
+        let id = match annotation.id() {
+            Some(id) => id.to_string(),
+            None => {
+                eprintln!("Warning: can not add data to an annotation without an ID");
+                continue;
+            }
+        };
+        let text: Vec<&str> = annotation.text().collect();
+        let text = text
+            .join(" ")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !text.is_empty() {
+            queries.insert(id, text);
+        }
+    }
+    resolver.resolve(queries.values().map(|x| x.as_str()))?;
+
+    let mut storejson = StoreJson::from_store(store)?;
+    for annotation in storejson.annotations.iter_mut() {
+        let query = match annotation
+            .get("@id")
+            .and_then(|x| x.as_str())
+            .and_then(|id| queries.get(id))
+        {
+            Some(query) => query,
+            None => continue,
+        };
+        match resolver.get(query) {
+            Some(candidate) => {
+                if let Some(Value::Array(data)) = annotation.get_mut("data") {
+                    data.push(json!({
+                        "@type": "AnnotationData",
+                        "set": result_set,
+                        "key": result_key,
+                        "value": { "@type": "String", "value": candidate.id }
+                    }));
+                }
+                report.resolved += 1;
+            }
+            None => report.unresolved += 1,
+        }
+    }
+
+    //the set that receives the data keeps its original standoff file, which must be rewritten
+    if report.resolved > 0 {
+        if let Some(filename) = storejson
+            .annotationset_filename(result_set)
+            .map(|x| x.to_string())
+        {
+            storejson.set_annotationset_filename(result_set, &filename);
+            report.setfiles.push((result_set.to_string(), filename));
+        }
+    }
+    Ok((storejson.into_store(store)?, report))
+}
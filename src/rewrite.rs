@@ -0,0 +1,665 @@
+use serde_json::{Map, Value};
+use stam::{
+    AnnotationDataSet, AnnotationStore, AssociatedFile, Configurable, Item, Storable, StoreFor,
+    TextResource, ToJson,
+};
+use std::collections::{HashMap, HashSet};
+
+/// A single edit on a text: replaces the characters `begin..end` (in unicode points) with `replacement`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+    pub begin: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+/// Maps offsets in an old text to offsets in a new text after edits have been applied
+#[derive(Clone, Debug, Default)]
+pub struct OffsetMap {
+    /// (old begin, old end, new begin, new end) for each edit, sorted
+    edits: Vec<(usize, usize, usize, usize)>,
+    oldlen: usize,
+    newlen: usize,
+}
+
+impl OffsetMap {
+    /// Maps a begin offset, a begin inside an edited span maps to the begin of the replacement
+    pub fn map_begin(&self, pos: usize) -> usize {
+        //the edits that end at or before the position
+        let i = self
+            .edits
+            .partition_point(|(_, oldend, _, _)| *oldend <= pos);
+        match self.edits.get(i) {
+            Some((oldbegin, _, newbegin, _)) if pos >= *oldbegin => *newbegin,
+            _ => self.shift(pos, i),
+        }
+    }
+
+    /// Maps an end offset, an end inside an edited span maps to the end of the replacement.
+    /// An end right at an insertion stays before the inserted text.
+    pub fn map_end(&self, pos: usize) -> usize {
+        //the edits that begin before the position, only the last of them may contain it
+        let i = self
+            .edits
+            .partition_point(|(oldbegin, _, _, _)| *oldbegin < pos);
+        match i.checked_sub(1).map(|last| self.edits[last]) {
+            Some((_, oldend, _, newend)) if pos < oldend => newend,
+            _ => self.shift(pos, i),
+        }
+    }
+
+    /// Shifts a position that is not inside an edited span by the difference in length of the first `n` edits
+    fn shift(&self, pos: usize, n: usize) -> usize {
+        match n.checked_sub(1).map(|last| self.edits[last]) {
+            Some((_, oldend, _, newend)) => pos + newend - oldend,
+            None => pos,
+        }
+    }
+
+    /// Length of the old text in unicode points
+    pub fn oldlen(&self) -> usize {
+        self.oldlen
+    }
+
+    /// Length of the new text in unicode points
+    pub fn newlen(&self) -> usize {
+        self.newlen
+    }
+}
+
+/// Applies edits to a text. The edits must be sorted and may not overlap.
+/// Returns the new text and a map to convert old offsets to new ones.
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> (String, OffsetMap) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut newtext = String::with_capacity(text.len());
+    let mut map = OffsetMap {
+        oldlen: chars.len(),
+        ..Default::default()
+    };
+    let mut cursor = 0;
+    let mut newcursor = 0;
+    for edit in edits {
+        assert!(edit.begin >= cursor && edit.end >= edit.begin && edit.end <= chars.len());
+        newtext.extend(chars[cursor..edit.begin].iter());
+        newcursor += edit.begin - cursor;
+        let newbegin = newcursor;
+        newtext += edit.replacement.as_str();
+        newcursor += edit.replacement.chars().count();
+        map.edits.push((edit.begin, edit.end, newbegin, newcursor));
+        cursor = edit.end;
+    }
+    newtext.extend(chars[cursor..].iter());
+    newcursor += chars.len() - cursor;
+    map.newlen = newcursor;
+    (newtext, map)
+}
+
+/// Rewrite all text selectors on `resource_id` in a STAM JSON selector, using the offset map.
+/// The resource is renamed to `new_id`. Relative offsets (in annotation selectors) are left untouched, see [`remap_relative_selector()`] for those.
+pub fn remap_selector(selector: &mut Value, resource_id: &str, new_id: &str, map: &OffsetMap) {
+    let targets_resource = selector.get("resource").and_then(|x| x.as_str()) == Some(resource_id);
+    match selector.get("@type").and_then(|x| x.as_str()) {
+        Some("TextSelector") if targets_resource => {
+            if let Some(offset) = selector.get_mut("offset") {
+                let begin = cursor_to_abs(&offset["begin"], map.oldlen());
+                let end = cursor_to_abs(&offset["end"], map.oldlen());
+                if let (Some(begin), Some(end)) = (begin, end) {
+                    let newbegin = map.map_begin(begin);
+                    let newend = map.map_end(end).max(newbegin);
+                    abs_to_cursor(&mut offset["begin"], newbegin, map.newlen());
+                    abs_to_cursor(&mut offset["end"], newend, map.newlen());
+                }
+            }
+            selector["resource"] = Value::String(new_id.to_string());
+        }
+        Some("ResourceSelector") if targets_resource => {
+            selector["resource"] = Value::String(new_id.to_string());
+        }
+        _ => {
+            if let Some(Value::Array(subselectors)) = selector.get_mut("selectors") {
+                for subselector in subselectors.iter_mut() {
+                    remap_selector(subselector, resource_id, new_id, map);
+                }
+            }
+        }
+    }
+}
+
+/// Rewrite relative offsets (in annotation selectors) in a STAM JSON selector, for annotations pointing at text of `resource_id`.
+/// The parent annotations are looked up in the original `store` to determine their absolute offsets. This complements
+/// [`remap_selector()`].
+pub fn remap_relative_selector(
+    selector: &mut Value,
+    store: &AnnotationStore,
+    resource_id: &str,
+    map: &OffsetMap,
+) {
+    match selector.get("@type").and_then(|x| x.as_str()) {
+        Some("AnnotationSelector") => {
+            let parent = selector
+                .get("annotation")
+                .and_then(|x| x.as_str())
+                .and_then(|id| store.annotation(&Item::IdRef(id)))
+                .and_then(|annotation| {
+                    annotation
+                        .textselections()
+                        .find(|textselection| textselection.resource().id() == Some(resource_id))
+                        .map(|textselection| (textselection.begin(), textselection.end()))
+                });
+            if let (Some((parentbegin, parentend)), Some(offset)) =
+                (parent, selector.get_mut("offset"))
+            {
+                let parentlen = parentend - parentbegin;
+                let begin = cursor_to_abs(&offset["begin"], parentlen);
+                let end = cursor_to_abs(&offset["end"], parentlen);
+                if let (Some(begin), Some(end)) = (begin, end) {
+                    let newparentbegin = map.map_begin(parentbegin);
+                    let newparentlen = map.map_end(parentend).max(newparentbegin) - newparentbegin;
+                    let newbegin = map.map_begin(parentbegin + begin) - newparentbegin;
+                    let newend = (map.map_end(parentbegin + end) - newparentbegin).max(newbegin);
+                    abs_to_cursor(&mut offset["begin"], newbegin, newparentlen);
+                    abs_to_cursor(&mut offset["end"], newend, newparentlen);
+                }
+            }
+        }
+        _ => {
+            if let Some(Value::Array(subselectors)) = selector.get_mut("selectors") {
+                for subselector in subselectors.iter_mut() {
+                    remap_relative_selector(subselector, store, resource_id, map);
+                }
+            }
+        }
+    }
+}
+
+/// Rewrite all references to annotations (in annotation selectors) in a STAM JSON selector, according to the map from old to new IDs
+pub fn rename_annotation_refs(selector: &mut Value, map: &HashMap<String, String>) {
+    if selector.get("@type").and_then(|x| x.as_str()) == Some("AnnotationSelector") {
+        if let Some(new_id) = selector
+            .get("annotation")
+            .and_then(|x| x.as_str())
+            .and_then(|id| map.get(id))
+        {
+            selector["annotation"] = Value::String(new_id.clone());
+        }
+    } else if let Some(Value::Array(subselectors)) = selector.get_mut("selectors") {
+        for subselector in subselectors.iter_mut() {
+            rename_annotation_refs(subselector, map);
+        }
+    }
+}
+
+/// Adds the resources and sets a STAM JSON selector refers to
+fn selector_references(selector: &Value, references: &mut References) {
+    if let Some(resource) = selector.get("resource").and_then(|x| x.as_str()) {
+        references.resources.insert(resource.to_string());
+    }
+    if let Some(set) = selector.get("annotationset").and_then(|x| x.as_str()) {
+        references.sets.insert(set.to_string());
+    }
+    if let Some(Value::Array(subselectors)) = selector.get("selectors") {
+        for subselector in subselectors.iter() {
+            selector_references(subselector, references);
+        }
+    }
+}
+
+/// Does the STAM JSON selector point to any of the annotations?
+fn refers_to(selector: &Value, ids: &HashSet<String>) -> bool {
+    if selector.get("@type").and_then(|x| x.as_str()) == Some("AnnotationSelector") {
+        selector
+            .get("annotation")
+            .and_then(|x| x.as_str())
+            .map(|id| ids.contains(id))
+            .unwrap_or(false)
+    } else if let Some(Value::Array(subselectors)) = selector.get("selectors") {
+        subselectors
+            .iter()
+            .any(|subselector| refers_to(subselector, ids))
+    } else {
+        false
+    }
+}
+
+fn cursor_to_abs(cursor: &Value, textlen: usize) -> Option<usize> {
+    let value = cursor.get("value")?.as_i64()?;
+    match cursor.get("@type")?.as_str()? {
+        "BeginAlignedCursor" => Some(value as usize),
+        "EndAlignedCursor" => Some((textlen as i64 + value) as usize),
+        _ => None,
+    }
+}
+
+fn abs_to_cursor(cursor: &mut Value, pos: usize, textlen: usize) {
+    if cursor["@type"].as_str() == Some("EndAlignedCursor") {
+        cursor["value"] = Value::from(pos as i64 - textlen as i64);
+    } else {
+        cursor["value"] = Value::from(pos);
+    }
+}
+
+/// The data, annotation sets and resources that annotations refer to
+#[derive(Clone, Debug, Default)]
+pub struct References {
+    /// Set ID and data ID
+    pub data: HashSet<(String, String)>,
+    pub sets: HashSet<String>,
+    pub resources: HashSet<String>,
+}
+
+/// A fully inlined STAM JSON representation of an annotation store, the resources, sets and
+/// annotations can be manipulated freely before turning it back into an annotation store.
+/// This is used for operations the library can not do in-place, like changing texts.
+pub struct StoreJson {
+    id: Option<String>,
+    pub resources: Vec<Value>,
+    pub annotationsets: Vec<Value>,
+    pub annotations: Vec<Value>,
+    /// The original JSON and filename of resources and sets, by ID
+    original_resources: HashMap<String, (Value, Option<String>)>,
+    original_sets: HashMap<String, (Value, Option<String>)>,
+    /// Filenames to assign to resources and sets when converting back to a store
+    filenames: HashMap<String, String>,
+    set_filenames: HashMap<String, String>,
+}
+
+impl StoreJson {
+    pub fn from_store(store: &AnnotationStore) -> Result<Self, String> {
+        let mut storejson = Self {
+            id: store.id().map(|x| x.to_string()),
+            resources: Vec::new(),
+            annotationsets: Vec::new(),
+            annotations: Vec::new(),
+            original_resources: HashMap::new(),
+            original_sets: HashMap::new(),
+            filenames: HashMap::new(),
+            set_filenames: HashMap::new(),
+        };
+        for resource in store.resources() {
+            let value = parse_json(resource.to_json_string(resource.config()))?;
+            if let Some(id) = resource.id() {
+                storejson.original_resources.insert(
+                    id.to_string(),
+                    (value.clone(), resource.filename().map(|x| x.to_string())),
+                );
+            }
+            storejson.resources.push(value);
+        }
+        for annotationset in store.annotationsets() {
+            let value = parse_json(annotationset.to_json_string(annotationset.config()))?;
+            if let Some(id) = annotationset.id() {
+                storejson.original_sets.insert(
+                    id.to_string(),
+                    (
+                        value.clone(),
+                        annotationset.filename().map(|x| x.to_string()),
+                    ),
+                );
+            }
+            storejson.annotationsets.push(value);
+        }
+        for annotation in store.annotations() {
+            storejson
+                .annotations
+                .push(parse_json(annotation.to_json_string(store))?);
+        }
+        Ok(storejson)
+    }
+
+    /// Associate a (new) filename with a resource
+    pub fn set_resource_filename(&mut self, id: &str, filename: &str) {
+        self.filenames.insert(id.to_string(), filename.to_string());
+    }
+
+    /// Returns the original filename of a resource, if any
+    pub fn resource_filename(&self, id: &str) -> Option<&str> {
+        self.original_resources
+            .get(id)
+            .and_then(|(_, filename)| filename.as_deref())
+    }
+
+    /// Associate a (new) filename with an annotation set
+    pub fn set_annotationset_filename(&mut self, id: &str, filename: &str) {
+        self.set_filenames
+            .insert(id.to_string(), filename.to_string());
+    }
+
+    /// Returns the original filename of an annotation set, if any
+    pub fn annotationset_filename(&self, id: &str) -> Option<&str> {
+        self.original_sets
+            .get(id)
+            .and_then(|(_, filename)| filename.as_deref())
+    }
+
+    /// Removes the annotations with the given IDs, as well as all annotations that (directly or indirectly) point to them
+    /// via an AnnotationSelector. Returns the IDs of all removed annotations.
+    pub fn remove_annotations(&mut self, ids: &[String]) -> Vec<String> {
+        let mut removed: HashSet<String> = ids.iter().cloned().collect();
+        loop {
+            let before = removed.len();
+            for annotation in self.annotations.iter() {
+                if let Some(id) = annotation.get("@id").and_then(|x| x.as_str()) {
+                    if !removed.contains(id)
+                        && annotation
+                            .get("target")
+                            .map(|selector| refers_to(selector, &removed))
+                            .unwrap_or(false)
+                    {
+                        removed.insert(id.to_string());
+                    }
+                }
+            }
+            if removed.len() == before {
+                break;
+            }
+        }
+        let mut result = Vec::new();
+        self.annotations.retain(|annotation| {
+            match annotation.get("@id").and_then(|x| x.as_str()) {
+                Some(id) if removed.contains(id) => {
+                    result.push(id.to_string());
+                    false
+                }
+                _ => true,
+            }
+        });
+        result
+    }
+
+    /// Collects the data, sets and resources referred to by the annotations
+    pub fn references(&self) -> References {
+        let mut references = References::default();
+        for annotation in self.annotations.iter() {
+            if let Some(Value::Array(data)) = annotation.get("data") {
+                for data in data {
+                    if let Some(set) = data.get("set").and_then(|x| x.as_str()) {
+                        references.sets.insert(set.to_string());
+                        if let Some(id) = data.get("@id").and_then(|x| x.as_str()) {
+                            references.data.insert((set.to_string(), id.to_string()));
+                        }
+                    }
+                }
+            }
+            if let Some(selector) = annotation.get("target") {
+                selector_references(selector, &mut references);
+            }
+        }
+        references
+    }
+
+    /// Builds a new annotation store. Resources and sets that were not changed keep their original filenames
+    /// (and will not be rewritten on save), changed ones are serialized inline unless a
+    /// filename was explicitly associated using [`Self::set_resource_filename()`] or [`Self::set_annotationset_filename()`].
+    /// In the latter case, the caller is responsible for writing the file.
+    pub fn into_store(self, original: &AnnotationStore) -> Result<AnnotationStore, String> {
+        let mut root = Map::new();
+        root.insert("@type".into(), "AnnotationStore".into());
+        if let Some(id) = self.id.as_ref() {
+            root.insert("@id".into(), id.as_str().into());
+        }
+        root.insert("resources".into(), Value::Array(self.resources.clone()));
+        root.insert(
+            "annotationsets".into(),
+            Value::Array(self.annotationsets.clone()),
+        );
+        root.insert("annotations".into(), Value::Array(self.annotations));
+        let json = Value::Object(root).to_string();
+        let mut store = AnnotationStore::from_str(&json, original.config().clone())
+            .map_err(|err| format!("Error rebuilding annotation store: {}", err))?;
+        if let Some(filename) = original.filename() {
+            store.set_filename(filename);
+        }
+        for value in self.resources.iter() {
+            if let Some(id) = value.get("@id").and_then(|x| x.as_str()) {
+                let filename = if let Some(filename) = self.filenames.get(id) {
+                    Some(filename.as_str())
+                } else {
+                    match self.original_resources.get(id) {
+                        Some((origvalue, Some(filename))) if origvalue == value => {
+                            Some(filename.as_str())
+                        }
+                        _ => None,
+                    }
+                };
+                if let Some(filename) = filename {
+                    let resource: &mut TextResource = store
+                        .get_mut(&Item::IdRef(id))
+                        .map_err(|err| format!("{}", err))?;
+                    resource.set_filename(filename);
+                }
+            }
+        }
+        for value in self.annotationsets.iter() {
+            if let Some(id) = value.get("@id").and_then(|x| x.as_str()) {
+                let filename = if let Some(filename) = self.set_filenames.get(id) {
+                    Some(filename.as_str())
+                } else {
+                    //annotations may also have added new keys or data to the set
+                    let rebuilt = store
+                        .annotationset(&Item::IdRef(id))
+                        .map(|annotationset| {
+                            parse_json(annotationset.to_json_string(annotationset.config()))
+                        })
+                        .transpose()?;
+                    match self.original_sets.get(id) {
+                        Some((origvalue, Some(filename)))
+                            if origvalue == value && rebuilt.as_ref() == Some(origvalue) =>
+                        {
+                            Some(filename.as_str())
+                        }
+                        _ => None,
+                    }
+                };
+                if let Some(filename) = filename {
+                    let annotationset: &mut AnnotationDataSet = store
+                        .get_mut(&Item::IdRef(id))
+                        .map_err(|err| format!("{}", err))?;
+                    annotationset.set_filename(filename);
+                }
+            }
+        }
+        Ok(store)
+    }
+}
+
+fn parse_json(json: Result<String, stam::StamError>) -> Result<Value, String> {
+    let json = json.map_err(|err| format!("{}", err))?;
+    serde_json::from_str(&json).map_err(|err| format!("{}", err))
+}
+
+/// Writes the text of a resource to the specified file (plain text or STAM JSON, depending on the extension)
+pub fn write_resource(store: &AnnotationStore, id: &str, filename: &str) -> Result<(), String> {
+    let resource = store
+        .resource(&Item::IdRef(id))
+        .ok_or_else(|| format!("Resource {} not found", id))?;
+    if filename.ends_with(".json") {
+        resource.to_json_file(filename, resource.config())
+    } else {
+        resource.to_txt_file(filename)
+    }
+    .map_err(|err| format!("Failed to write {}: {}", filename, err))
+}
+
+/// Writes an annotation set to the specified STAM JSON file
+pub fn write_annotationset(
+    store: &AnnotationStore,
+    id: &str,
+    filename: &str,
+) -> Result<(), String> {
+    let annotationset = store
+        .annotationset(&Item::IdRef(id))
+        .ok_or_else(|| format!("Annotation set {} not found", id))?;
+    annotationset
+        .to_json_file(filename, annotationset.config())
+        .map_err(|err| format!("Failed to write {}: {}", filename, err))
+}
+
+/// Inserts a suffix before the extension of a filename: `hello.txt` becomes `hello.suffix.txt`
+pub fn add_suffix(filename: &str, suffix: &str) -> String {
+    let basestart = filename.rfind('/').map(|x| x + 1).unwrap_or(0);
+    match filename[basestart..].find('.') {
+        Some(pos) if pos > 0 => format!(
+            "{}.{}{}",
+            &filename[..basestart + pos],
+            suffix,
+            &filename[basestart + pos..]
+        ),
+        _ => format!("{}.{}", filename, suffix),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn edit(begin: usize, end: usize, replacement: &str) -> TextEdit {
+        TextEdit {
+            begin,
+            end,
+            replacement: replacement.to_string(),
+        }
+    }
+
+    #[test]
+    fn apply_replacement() {
+        let (text, map) = apply_edits("hello world", &[edit(6, 11, "there")]);
+        assert_eq!(text, "hello there");
+        assert_eq!(map.oldlen(), 11);
+        assert_eq!(map.newlen(), 11);
+        assert_eq!(map.map_begin(0), 0);
+        assert_eq!(map.map_end(5), 5);
+        assert_eq!(map.map_begin(6), 6);
+        assert_eq!(map.map_end(11), 11);
+    }
+
+    #[test]
+    fn apply_unicode() {
+        //offsets are in unicode points, not bytes
+        let (text, map) = apply_edits("héllo wörld", &[edit(1, 2, "e"), edit(7, 8, "oe")]);
+        assert_eq!(text, "hello woerld");
+        assert_eq!(map.oldlen(), 11);
+        assert_eq!(map.newlen(), 12);
+        assert_eq!(map.map_begin(6), 6);
+        assert_eq!(map.map_end(11), 12);
+    }
+
+    #[test]
+    fn map_shifts_after_edits() {
+        //"the cat sat" -> "the big cat sat" -> "the big cat"
+        let (text, map) = apply_edits("the cat sat", &[edit(4, 4, "big "), edit(7, 11, "")]);
+        assert_eq!(text, "the big cat");
+        //"cat"
+        assert_eq!(map.map_begin(4), 8);
+        assert_eq!(map.map_end(7), 11);
+        //"the" is untouched
+        assert_eq!(map.map_begin(0), 0);
+        assert_eq!(map.map_end(3), 3);
+    }
+
+    #[test]
+    fn map_inside_edit() {
+        let (_, map) = apply_edits("hello world", &[edit(6, 11, "you")]);
+        //offsets inside the replaced span snap to the replacement
+        assert_eq!(map.map_begin(8), 6);
+        assert_eq!(map.map_end(8), 9);
+    }
+
+    #[test]
+    fn remap_text_selector() {
+        let (_, map) = apply_edits("the cat sat", &[edit(0, 4, "")]);
+        let mut selector = json!({
+            "@type": "TextSelector",
+            "resource": "old",
+            "offset": {
+                "@type": "Offset",
+                "begin": { "@type": "BeginAlignedCursor", "value": 4 },
+                "end": { "@type": "EndAlignedCursor", "value": -4 },
+            }
+        });
+        remap_selector(&mut selector, "old", "new", &map);
+        assert_eq!(selector["resource"], "new");
+        assert_eq!(selector["offset"]["begin"]["value"], 0);
+        assert_eq!(selector["offset"]["end"]["value"], -4);
+    }
+
+    #[test]
+    fn remap_other_resource() {
+        let (_, map) = apply_edits("the cat sat", &[edit(0, 4, "")]);
+        let mut selector = json!({
+            "@type": "CompositeSelector",
+            "selectors": [
+                { "@type": "ResourceSelector", "resource": "old" },
+                { "@type": "ResourceSelector", "resource": "other" },
+            ]
+        });
+        remap_selector(&mut selector, "old", "new", &map);
+        assert_eq!(selector["selectors"][0]["resource"], "new");
+        assert_eq!(selector["selectors"][1]["resource"], "other");
+    }
+
+    #[test]
+    fn suffix() {
+        assert_eq!(add_suffix("hello.txt", "fixed"), "hello.fixed.txt");
+        assert_eq!(add_suffix("dir.d/hello", "fixed"), "dir.d/hello.fixed");
+        assert_eq!(add_suffix(".hidden", "fixed"), ".hidden.fixed");
+        assert_eq!(
+            add_suffix("hello.store.json", "fixed"),
+            "hello.fixed.store.json"
+        );
+    }
+
+    #[test]
+    fn rename_refs() {
+        let mut map = HashMap::new();
+        map.insert("A1".to_string(), "B1".to_string());
+        let mut selector = json!({
+            "@type": "MultiSelector",
+            "selectors": [
+                { "@type": "AnnotationSelector", "annotation": "A1" },
+                { "@type": "AnnotationSelector", "annotation": "A2" },
+            ]
+        });
+        rename_annotation_refs(&mut selector, &map);
+        assert_eq!(selector["selectors"][0]["annotation"], "B1");
+        assert_eq!(selector["selectors"][1]["annotation"], "A2");
+    }
+
+    #[test]
+    fn map_at_insertion() {
+        let (text, map) = apply_edits("ab", &[edit(1, 1, "XY")]);
+        assert_eq!(text, "aXYb");
+        //an end right at the insertion stays before it, a begin moves after it
+        assert_eq!(map.map_end(1), 1);
+        assert_eq!(map.map_begin(1), 3);
+    }
+
+    #[test]
+    fn map_many_edits() {
+        //deletions, replacements and adjacent insertions, checked against the offsets in the texts themselves
+        let text = "the quick brown fox jumps";
+        let edits = [
+            edit(0, 0, ">"),
+            edit(0, 4, ""),
+            edit(10, 15, "red"),
+            edit(15, 15, "+"),
+            edit(15, 15, "-"),
+            edit(20, 25, "sleeps"),
+        ];
+        let (newtext, map) = apply_edits(text, &edits);
+        assert_eq!(newtext, ">quick red+- fox sleeps");
+        for (old, new) in [(4, 1), (10, 7), (16, 13), (19, 16)] {
+            assert_eq!(map.map_begin(old), new);
+            assert_eq!(map.map_end(old), new);
+        }
+        //inside an edited span
+        assert_eq!(map.map_begin(2), 1);
+        assert_eq!(map.map_end(12), 10);
+        assert_eq!(map.map_begin(12), 7);
+        //at an insertion
+        assert_eq!(map.map_begin(0), 1);
+        assert_eq!(map.map_end(15), 10);
+        assert_eq!(map.map_begin(15), 12);
+        assert_eq!(map.map_end(25), 23);
+    }
+}
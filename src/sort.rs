@@ -0,0 +1,156 @@
+use serde_json::Value;
+use stam::{AnnotationStore, Storable};
+use std::collections::HashMap;
+
+use crate::rewrite::{rename_annotation_refs, StoreJson};
+
+/// Rewrites the store with its annotations in a deterministic order: by resource, begin offset, end offset and finally ID.
+/// Annotations that do not reference any text come last. An annotation that points to other annotations is always placed after
+/// the annotations it points to.
+///
+/// If `id_template` is set, annotations receive new sequential IDs, `{n}` in the template is replaced by the number (1-indexed).
+/// If `data_id_template` is set, the data in each annotation set are sorted by key and value and likewise receive new IDs,
+/// here `{set}` may also be used in the template.
+///
+/// Returns the new store and a list of annotation set IDs with the filenames they still need to be written to.
+pub fn sort(
+    store: &AnnotationStore,
+    id_template: Option<&str>,
+    data_id_template: Option<&str>,
+) -> Result<(AnnotationStore, Vec<(String, String)>), String> {
+    let mut storejson = StoreJson::from_store(store)?;
+
+    //compute the sort keys and dependencies, indices correspond to the order in storejson.annotations
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut keys = Vec::with_capacity(store.annotations_len());
+    let mut dependencies: Vec<Vec<String>> = Vec::with_capacity(store.annotations_len());
+    for (i, annotation) in store.annotations().enumerate() {
+        let id = annotation.id().unwrap_or("").to_string();
+        index.insert(id.clone(), i);
+        let key = if let Some(textselection) = annotation.textselections().next() {
+            (
+                false,
+                textselection.resource().id().unwrap_or("").to_string(),
+                textselection.begin(),
+                textselection.end(),
+                id,
+            )
+        } else {
+            (true, String::new(), 0, 0, id)
+        };
+        keys.push(key);
+        dependencies.push(
+            annotation
+                .annotations(false, false)
+                .filter_map(|target| target.id().map(|x| x.to_string()))
+                .collect(),
+        );
+    }
+    let mut sorted: Vec<usize> = (0..keys.len()).collect();
+    sorted.sort_by(|a, b| keys[*a].cmp(&keys[*b]));
+
+    //emit in sorted order, but make sure dependencies come first
+    fn visit(
+        i: usize,
+        dependencies: &[Vec<String>],
+        index: &HashMap<String, usize>,
+        visited: &mut Vec<bool>,
+        order: &mut Vec<usize>,
+    ) {
+        if visited[i] {
+            return;
+        }
+        visited[i] = true;
+        for dependency in dependencies[i].iter() {
+            if let Some(j) = index.get(dependency) {
+                visit(*j, dependencies, index, visited, order);
+            }
+        }
+        order.push(i);
+    }
+    let mut visited = vec![false; keys.len()];
+    let mut order = Vec::with_capacity(keys.len());
+    for i in sorted {
+        visit(i, &dependencies, &index, &mut visited, &mut order);
+    }
+    let mut annotations: Vec<Option<Value>> = storejson.annotations.drain(..).map(Some).collect();
+    storejson.annotations = order
+        .into_iter()
+        .map(|i| {
+            annotations[i]
+                .take()
+                .expect("annotation must be emitted only once")
+        })
+        .collect();
+
+    if let Some(id_template) = id_template {
+        let mut idmap: HashMap<String, String> = HashMap::new();
+        for (n, annotation) in storejson.annotations.iter_mut().enumerate() {
+            let new_id = id_template.replace("{n}", &(n + 1).to_string());
+            if let Some(old_id) = annotation.get("@id").and_then(|x| x.as_str()) {
+                idmap.insert(old_id.to_string(), new_id.clone());
+            }
+            annotation["@id"] = new_id.into();
+        }
+        for annotation in storejson.annotations.iter_mut() {
+            if let Some(target) = annotation.get_mut("target") {
+                rename_annotation_refs(target, &idmap);
+            }
+        }
+    }
+
+    let mut changedsets = Vec::new();
+    if let Some(data_id_template) = data_id_template {
+        //maps (set, old data id) to new data id
+        let mut idmap: HashMap<(String, String), String> = HashMap::new();
+        for annotationset in storejson.annotationsets.iter_mut() {
+            let set_id = annotationset
+                .get("@id")
+                .and_then(|x| x.as_str())
+                .unwrap_or("")
+                .to_string();
+            if let Some(Value::Array(data)) = annotationset.get_mut("data") {
+                data.sort_by_cached_key(|data| {
+                    (data["key"].to_string(), data["value"].to_string())
+                });
+                for (n, data) in data.iter_mut().enumerate() {
+                    let new_id = data_id_template
+                        .replace("{set}", &set_id)
+                        .replace("{n}", &(n + 1).to_string());
+                    if let Some(old_id) = data.get("@id").and_then(|x| x.as_str()) {
+                        idmap.insert((set_id.clone(), old_id.to_string()), new_id.clone());
+                    }
+                    data["@id"] = new_id.into();
+                }
+            }
+            changedsets.push(set_id);
+        }
+        for annotation in storejson.annotations.iter_mut() {
+            if let Some(Value::Array(data)) = annotation.get_mut("data") {
+                for data in data.iter_mut() {
+                    let key = (
+                        data["set"].as_str().unwrap_or("").to_string(),
+                        data["@id"].as_str().unwrap_or("").to_string(),
+                    );
+                    if let Some(new_id) = idmap.get(&key) {
+                        data["@id"] = new_id.as_str().into();
+                    }
+                }
+            }
+        }
+    }
+    //changed sets keep their original standoff file, which must be rewritten
+    let mut setfiles = Vec::new();
+    for set_id in changedsets {
+        if let Some(filename) = storejson
+            .annotationset_filename(&set_id)
+            .map(|x| x.to_string())
+        {
+            storejson.set_annotationset_filename(&set_id, &filename);
+            setfiles.push((set_id, filename));
+        }
+    }
+
+    let newstore = storejson.into_store(store)?;
+    Ok((newstore, setfiles))
+}
@@ -0,0 +1,875 @@
+use stam::{
+    AnnotationBuilder, AnnotationDataBuilder, AnnotationHandle, AnnotationStore, FindRegexMatch,
+    Item, Offset, Regex, RegexSet, SelectorBuilder, Storable, Text, TextResourceHandle,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process::exit;
+
+use crate::filter::{DataFilter, FilterOperator};
+use crate::normalize::Normalizer;
+use crate::progress::Progress;
+
+struct Rule {
+    expression: Regex,
+    set: String,
+    /// The key and value of each data item to assign, values may reference capture groups like $1 $2 $3 or $name
+    data: Vec<(String, String)>,
+    /// Produce a separate annotation for each named capture group, with the name of the group as key (key `*` in the rules)
+    per_group: bool,
+    //only tag text covered by annotations matching this filter (may be annotations produced by other rules)
+    scope: Option<DataFilter>,
+}
+
+impl Rule {
+    /// Can annotations produced by this rule match the filter?
+    fn produces(&self, filter: &DataFilter) -> bool {
+        let value_matches = |value: &str| {
+            //does the value reference capture groups?
+            value.contains('$')
+                || match filter.op {
+                    FilterOperator::Equals => filter.value.as_deref() == Some(value),
+                    FilterOperator::NotEquals => filter.value.as_deref() != Some(value),
+                    //numeric comparisons: assume they may match
+                    _ => true,
+                }
+        };
+        filter.set == self.set
+            && if self.per_group {
+                self.expression
+                    .capture_names()
+                    .any(|name| name == Some(filter.key.as_str()))
+                    && value_matches(&self.data[0].1)
+            } else {
+                self.data
+                    .iter()
+                    .any(|(key, value)| *key == filter.key && value_matches(value))
+            }
+    }
+
+    /// Resolves references to capture groups in a value. `$0` refers to `own`, the text of the group being annotated, if any.
+    fn resolve_value(&self, value: &str, textmatch: &RuleMatch, own: Option<&str>) -> String {
+        if !value.contains('$') {
+            return value.to_string();
+        }
+        let mut value = value.to_string();
+        let text = |capnum: usize| {
+            textmatch
+                .spans
+                .iter()
+                .find(|(n, _, _, _)| *n == capnum)
+                .map(|(_, _, _, text)| *text)
+        };
+        //longest names first, so $day is not replaced in $days
+        let mut names: Vec<(usize, &str)> = self
+            .expression
+            .capture_names()
+            .enumerate()
+            .filter_map(|(capnum, name)| name.map(|name| (capnum, name)))
+            .collect();
+        names.sort_by_key(|(_, name)| std::cmp::Reverse(name.len()));
+        for (capnum, name) in names {
+            let text = text(capnum).unwrap_or("");
+            value = value
+                .replace(format!("${{{}}}", name).as_str(), text)
+                .replace(format!("${}", name).as_str(), text);
+        }
+        //highest numbers first, so $1 is not replaced in $10
+        for capnum in (0..self.expression.captures_len()).rev() {
+            let pattern = format!("${}", capnum);
+            if let Some(text) = if capnum == 0 {
+                own.or(text(0))
+            } else {
+                text(capnum)
+            } {
+                value = value.replace(pattern.as_str(), text);
+            }
+        }
+        value
+    }
+}
+
+/// Loads the rules from a TSV file, or from standard input if the filename is `-`
+fn load_tag_rules(filename: &str, setdelimiter: &str) -> Vec<Rule> {
+    let mut rules: Vec<Rule> = Vec::new();
+    let reader: Box<dyn BufRead> = if filename == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        let f = File::open(filename).unwrap_or_else(|e| {
+            eprintln!("Error opening rules {}: {}", filename, e);
+            exit(1)
+        });
+        Box::new(BufReader::new(f))
+    };
+    for (i, line) in reader.lines().enumerate() {
+        if let Ok(line) = line {
+            if !line.is_empty() && !line.starts_with("#") {
+                let fields: Vec<&str> = line.split("\t").collect();
+                if fields.len() != 4 && fields.len() != 5 {
+                    eprintln!(
+                        "Error parsing rules {} line {}: Expected 4 or 5 columns, got {}",
+                        filename,
+                        i + 1,
+                        fields.len()
+                    );
+                    exit(2)
+                }
+                let expression = Regex::new(fields[0]).unwrap_or_else(|e| {
+                    eprintln!("Error in rules {} line {}: {}", filename, i + 1, e);
+                    exit(1)
+                });
+                let scope = match fields.get(4) {
+                    Some(&"") | Some(&"-") | None => None,
+                    Some(filter) => {
+                        Some(DataFilter::parse(filter, setdelimiter).unwrap_or_else(|e| {
+                            eprintln!("Error in rules {} line {}: {}", filename, i + 1, e);
+                            exit(1)
+                        }))
+                    }
+                };
+                let per_group = fields[2] == "*";
+                if per_group && expression.capture_names().all(|name| name.is_none()) {
+                    eprintln!(
+                        "Error in rules {} line {}: key * requires named capture groups like (?P<name>...)",
+                        filename,
+                        i + 1
+                    );
+                    exit(1)
+                }
+                //multiple keys and values are separated by |, the value is only split if there are multiple keys
+                let data: Vec<(String, String)> = if fields[2].contains('|') {
+                    let keys: Vec<&str> = fields[2].split('|').collect();
+                    let values: Vec<&str> = fields[3].split('|').collect();
+                    if keys.len() != values.len() {
+                        eprintln!(
+                            "Error in rules {} line {}: {} keys but {} values",
+                            filename,
+                            i + 1,
+                            keys.len(),
+                            values.len()
+                        );
+                        exit(1)
+                    }
+                    keys.into_iter()
+                        .zip(values)
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .collect()
+                } else {
+                    vec![(fields[2].to_string(), fields[3].to_string())]
+                };
+                rules.push(Rule {
+                    expression,
+                    set: fields[1].to_string(),
+                    data,
+                    per_group,
+                    scope,
+                });
+            }
+        }
+    }
+    rules
+}
+
+/// Determines in which pass each rule must be applied: a rule whose scope matches annotations produced by
+/// other rules is applied in a later pass than those rules. Returns an error if rules depend on each other in a cycle.
+fn rule_passes(rules: &[Rule]) -> Result<Vec<usize>, String> {
+    fn visit(
+        i: usize,
+        rules: &[Rule],
+        passes: &mut Vec<Option<usize>>,
+        visiting: &mut Vec<usize>,
+    ) -> Result<usize, String> {
+        if let Some(pass) = passes[i] {
+            return Ok(pass);
+        }
+        if visiting.contains(&i) {
+            return Err(format!(
+                "Rules depend on each other in a cycle (rule {}: {})",
+                i + 1,
+                rules[i].expression
+            ));
+        }
+        visiting.push(i);
+        let mut pass = 0;
+        if let Some(scope) = rules[i].scope.as_ref() {
+            for (j, rule) in rules.iter().enumerate() {
+                if rule.produces(scope) {
+                    pass = pass.max(visit(j, rules, passes, visiting)? + 1);
+                }
+            }
+        }
+        visiting.pop();
+        passes[i] = Some(pass);
+        Ok(pass)
+    }
+    let mut passes = vec![None; rules.len()];
+    for i in 0..rules.len() {
+        visit(i, rules, &mut passes, &mut Vec::new())?;
+    }
+    Ok(passes.into_iter().map(|pass| pass.unwrap()).collect())
+}
+
+/// A match of a rule in the text
+struct RuleMatch<'t> {
+    /// Index of the rule
+    rule: usize,
+    /// The matching spans: capture group number (1-indexed, 0 if the rule has no capture groups),
+    /// begin and end offset in the resource (unicode points), and the matching text
+    spans: Vec<(usize, usize, usize, &'t str)>,
+}
+
+impl<'t> RuleMatch<'t> {
+    fn from_textmatch(textmatch: &FindRegexMatch<'t, '_>) -> Self {
+        Self {
+            rule: textmatch.expression_index(),
+            spans: if textmatch.capturegroups().is_empty() {
+                textmatch
+                    .textselections()
+                    .iter()
+                    .map(|textselection| {
+                        (
+                            0,
+                            textselection.begin(),
+                            textselection.end(),
+                            textselection.text(),
+                        )
+                    })
+                    .collect()
+            } else {
+                textmatch
+                    .capturegroups()
+                    .iter()
+                    .zip(textmatch.textselections().iter())
+                    .map(|(capnum, textselection)| {
+                        (
+                            *capnum,
+                            textselection.begin(),
+                            textselection.end(),
+                            textselection.text(),
+                        )
+                    })
+                    .collect()
+            },
+        }
+    }
+}
+
+/// The expressions of a group of rules, compiled so that large rule sets need not be searched one expression at a time
+struct Matcher {
+    expressions: Vec<Regex>,
+    /// All expressions in a single set, to determine in one pass which of them match a text at all
+    set: Option<RegexSet>,
+}
+
+impl Matcher {
+    fn new(rules: &[&Rule]) -> Self {
+        let expressions: Vec<_> = rules.iter().map(|rule| rule.expression.clone()).collect();
+        //compilation may fail for very large rule sets (size limits), we then fall back to searching expression by expression
+        let set = if expressions.len() > 2 {
+            RegexSet::new(expressions.iter().map(|x| x.as_str())).ok()
+        } else {
+            None
+        };
+        Self { expressions, set }
+    }
+}
+
+/// Finds all matches of the expressions in a text that starts at offset `begin` (in unicode points) in its resource.
+/// Like [`stam::Text::find_text_regex()`], matches are returned in textual order and overlapping matches are discarded
+/// unless `allow_overlap` is set.
+fn find_in_text<'t>(
+    text: &'t str,
+    begin: usize,
+    matcher: &Matcher,
+    allow_overlap: bool,
+) -> Vec<RuleMatch<'t>> {
+    //unicode point for each byte offset of a character boundary
+    let mut charpos: HashMap<usize, usize> = HashMap::new();
+    for (i, (bytepos, _)) in text.char_indices().enumerate() {
+        charpos.insert(bytepos, begin + i);
+    }
+    charpos.insert(text.len(), begin + text.chars().count());
+    let span = |capnum: usize, start: usize, end: usize| {
+        (capnum, charpos[&start], charpos[&end], &text[start..end])
+    };
+    //only search for the expressions that match at all
+    let candidates: Vec<usize> = match matcher.set.as_ref() {
+        Some(set) => set.matches(text).into_iter().collect(),
+        None => (0..matcher.expressions.len()).collect(),
+    };
+    //(begin, end, expression index) of the whole match, and the match
+    let mut matches: Vec<(usize, usize, usize, RuleMatch<'t>)> = Vec::new();
+    for i in candidates {
+        let expression = &matcher.expressions[i];
+        if expression.captures_len() > 1 {
+            for captures in expression.captures_iter(text) {
+                let m = captures.get(0).expect("group 0 must exist");
+                matches.push((
+                    m.start(),
+                    m.end(),
+                    i,
+                    RuleMatch {
+                        rule: i,
+                        spans: captures
+                            .iter()
+                            .enumerate()
+                            .skip(1)
+                            .filter_map(|(capnum, group)| {
+                                group.map(|group| span(capnum, group.start(), group.end()))
+                            })
+                            .collect(),
+                    },
+                ));
+            }
+        } else {
+            for m in expression.find_iter(text) {
+                matches.push((
+                    m.start(),
+                    m.end(),
+                    i,
+                    RuleMatch {
+                        rule: i,
+                        spans: vec![span(0, m.start(), m.end())],
+                    },
+                ));
+            }
+        }
+    }
+    matches.sort_by_key(|(begin, _, i, _)| (*begin, *i));
+    let mut result = Vec::new();
+    let mut cursor = 0;
+    for (begin, end, _, m) in matches {
+        if allow_overlap || begin >= cursor || result.is_empty() {
+            cursor = end;
+            result.push(m);
+        }
+    }
+    result
+}
+
+/// Like [`find_in_text()`], but searches a normalized version of the text. The offsets and the matching text in the results
+/// refer to the original text.
+fn find_in_normalized_text<'t>(
+    text: &'t str,
+    begin: usize,
+    matcher: &Matcher,
+    allow_overlap: bool,
+    normalizer: &Normalizer,
+) -> Vec<RuleMatch<'t>> {
+    let normalized = normalizer.normalize(text);
+    //byte offset of each character in the original text
+    let bytepos: Vec<usize> = text
+        .char_indices()
+        .map(|(bytepos, _)| bytepos)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    find_in_text(&normalized.text, 0, matcher, allow_overlap)
+        .into_iter()
+        .map(|textmatch| RuleMatch {
+            rule: textmatch.rule,
+            spans: textmatch
+                .spans
+                .into_iter()
+                .map(|(capnum, spanbegin, spanend, _)| {
+                    let (spanbegin, spanend) = normalized.to_original(spanbegin, spanend);
+                    (
+                        capnum,
+                        begin + spanbegin,
+                        begin + spanend,
+                        &text[bytepos[spanbegin]..bytepos[spanend]],
+                    )
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Builds the annotation(s) for a regular expression match: one, or one per named capture group if the rule says so.
+/// If a scope annotation (and the offset where its text begins) is passed, the annotations will target the scope annotation
+/// via an AnnotationSelector with a relative offset.
+fn build_annotations(
+    rule: &Rule,
+    textmatch: &RuleMatch,
+    resource: TextResourceHandle,
+    scope: Option<(AnnotationHandle, usize)>,
+) -> Vec<AnnotationBuilder<'static>> {
+    let selector = |begin: usize, end: usize| {
+        if let Some((scope_handle, scope_begin)) = scope {
+            SelectorBuilder::AnnotationSelector(
+                Item::Handle(scope_handle),
+                Some(Offset::simple(begin - scope_begin, end - scope_begin)),
+            )
+        } else {
+            SelectorBuilder::TextSelector(Item::Handle(resource), Offset::simple(begin, end))
+        }
+    };
+    let databuilder = |key: &str, value: String| {
+        AnnotationDataBuilder::new()
+            .with_annotationset(Item::Id(rule.set.clone()))
+            .with_key(Item::Id(key.to_string()))
+            .with_value(value.into())
+    };
+    if rule.per_group {
+        let names: Vec<Option<&str>> = rule.expression.capture_names().collect();
+        return textmatch
+            .spans
+            .iter()
+            .filter_map(|(capnum, begin, end, text)| {
+                let name = names.get(*capnum).copied().flatten()?;
+                let value = rule.resolve_value(&rule.data[0].1, textmatch, Some(text));
+                Some(
+                    AnnotationBuilder::new()
+                        .with_target(selector(*begin, *end))
+                        .with_data_builder(databuilder(name, value)),
+                )
+            })
+            .collect();
+    }
+    let mut selectors: Vec<SelectorBuilder> = textmatch
+        .spans
+        .iter()
+        .map(|(_, begin, end, _)| selector(*begin, *end))
+        .collect();
+    let selector = if selectors.len() == 1 {
+        //build an annotation with a single selector
+        selectors.pop().expect("there must be a selector")
+    } else {
+        //result references multiple groups, build an annotation with a CompositeSelector
+        SelectorBuilder::CompositeSelector(selectors)
+    };
+    let mut annotation = AnnotationBuilder::new().with_target(selector);
+    for (key, value) in rule.data.iter() {
+        annotation = annotation
+            .with_data_builder(databuilder(key, rule.resolve_value(value, textmatch, None)));
+    }
+    vec![annotation]
+}
+
+/// Options for [`tag()`]
+#[derive(Clone, Copy)]
+pub struct TagOptions<'a> {
+    allow_overlap: bool,
+    scope: &'a [DataFilter],
+    link_scope: bool,
+    setdelimiter: &'a str,
+    normalizer: Option<&'a Normalizer>,
+    progress: bool,
+}
+
+impl<'a> Default for TagOptions<'a> {
+    fn default() -> Self {
+        Self {
+            allow_overlap: false,
+            scope: &[],
+            link_scope: false,
+            setdelimiter: "/",
+            normalizer: None,
+            progress: false,
+        }
+    }
+}
+
+impl<'a> TagOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow regular expression matches to overlap (default: false)
+    pub fn with_allow_overlap(mut self, allow_overlap: bool) -> Self {
+        self.allow_overlap = allow_overlap;
+        self
+    }
+
+    /// Only tag text covered by annotations matching any of these filters (by default all text is tagged)
+    pub fn with_scope(mut self, scope: &'a [DataFilter]) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Let the new annotations point to the scope annotation (with a relative offset) rather than directly to the text;
+    /// this requires scope annotations consisting of a single text selection (default: false)
+    pub fn with_link_scope(mut self, link_scope: bool) -> Self {
+        self.link_scope = link_scope;
+        self
+    }
+
+    /// Delimiter between the set and the key in the scope column of the rules (default: `/`)
+    pub fn with_setdelimiter(mut self, setdelimiter: &'a str) -> Self {
+        self.setdelimiter = setdelimiter;
+        self
+    }
+
+    /// Match the rules against a normalized version of the text (e.g. lowercased), the annotations still refer to the original text
+    pub fn with_normalizer(mut self, normalizer: Option<&'a Normalizer>) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Show a progress indicator on standard error
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+}
+
+/// Tags the text using the regular expression rules. If scope filters are provided, only text
+/// covered by annotations matching any of the filters will be tagged, see [`TagOptions`].
+///
+/// Rules may have a scope of their own, which takes precedence over the scope in the options. If a rule's scope refers to data produced by other rules,
+/// the rules are applied in multiple passes, so the annotations of earlier passes are available to later ones.
+pub fn tag(store: &mut AnnotationStore, rulefile: &str, options: &TagOptions) {
+    let rules = load_tag_rules(rulefile, options.setdelimiter);
+    eprintln!("Loaded {} expressions from {}", rules.len(), rulefile);
+    let passes = rule_passes(&rules).unwrap_or_else(|e| {
+        eprintln!("Error in rules {}: {}", rulefile, e);
+        exit(1);
+    });
+    let passcount = passes.iter().max().map(|x| x + 1).unwrap_or(0);
+    for pass in 0..passcount {
+        //group the rules of this pass by their scope
+        let mut groups: Vec<(Vec<DataFilter>, Vec<&Rule>)> = Vec::new();
+        for (rule, _) in rules
+            .iter()
+            .zip(passes.iter())
+            .filter(|(_, rulepass)| **rulepass == pass)
+        {
+            let rulescope = if let Some(rulescope) = rule.scope.as_ref() {
+                vec![rulescope.clone()]
+            } else {
+                options.scope.to_vec()
+            };
+            if let Some((_, grouprules)) = groups.iter_mut().find(|(s, _)| *s == rulescope) {
+                grouprules.push(rule);
+            } else {
+                groups.push((rulescope, vec![rule]));
+            }
+        }
+        if passcount > 1 {
+            eprintln!(
+                "Pass {}: applying {} rule(s)",
+                pass + 1,
+                groups.iter().map(|(_, rules)| rules.len()).sum::<usize>()
+            );
+        }
+        let mut annotations = Vec::new();
+        for (rulescope, grouprules) in groups {
+            annotations.extend(tag_scope(store, &grouprules, &rulescope, options));
+        }
+        //now we add the actual annotations (can't be combined with previous step because we can't have mutability during iteration)
+        let mut annotate_progress =
+            Progress::new("Adding annotations", annotations.len(), options.progress);
+        for annotation in annotations {
+            annotate_progress.inc(1);
+            store.annotate(annotation).unwrap_or_else(|err| {
+                eprintln!("Failed to add annotation: {}", err);
+                exit(1)
+            });
+        }
+        annotate_progress.finish();
+    }
+}
+
+/// Applies the rules to the text within the scope (or to all text if there is no scope), returns the annotations to add
+fn tag_scope(
+    store: &AnnotationStore,
+    rules: &[&Rule],
+    scope: &[DataFilter],
+    options: &TagOptions,
+) -> Vec<AnnotationBuilder<'static>> {
+    let TagOptions {
+        allow_overlap,
+        link_scope,
+        normalizer,
+        progress,
+        ..
+    } = *options;
+    let matcher = Matcher::new(rules);
+    //search the text and build annotations
+    if scope.is_empty() {
+        let mut annotations = Vec::new();
+        let mut progress = Progress::new(
+            "Tagging text",
+            store.resources().map(|resource| resource.textlen()).sum(),
+            progress,
+        );
+        let mut done = 0; //characters in the resources that were already searched
+        for resource in store.resources() {
+            let resource = resource.unwrap();
+            if normalizer.is_some() || matcher.set.is_none() {
+                let resource_handle = resource.handle().expect("resource must have handle");
+                let textmatches = if let Some(normalizer) = normalizer {
+                    find_in_normalized_text(resource.text(), 0, &matcher, allow_overlap, normalizer)
+                } else {
+                    find_in_text(resource.text(), 0, &matcher, allow_overlap)
+                };
+                for textmatch in textmatches {
+                    let rule = rules.get(textmatch.rule).expect("rule must exist");
+                    annotations.extend(build_annotations(rule, &textmatch, resource_handle, None));
+                }
+                done += resource.textlen();
+                progress.set(done);
+                continue;
+            }
+            let matches = match resource.find_text_regex(
+                &matcher.expressions,
+                matcher.set.as_ref(),
+                allow_overlap,
+            ) {
+                Ok(matches) => matches,
+                Err(_) => continue, //ignore errors, like AnnotationStore::find_text_regex() does
+            };
+            for textmatch in matches {
+                //get the matching rule
+                let rule = rules
+                    .get(textmatch.expression_index())
+                    .expect("rule must exist");
+                let rulematch = RuleMatch::from_textmatch(&textmatch);
+                if let Some((_, _, end, _)) = rulematch.spans.last() {
+                    progress.set(done + end);
+                }
+                annotations.extend(build_annotations(
+                    rule,
+                    &rulematch,
+                    resource.handle().expect("resource must have handle"),
+                    None,
+                ));
+            }
+            done += resource.textlen();
+            progress.set(done);
+        }
+        progress.finish();
+        annotations
+    } else {
+        let mut annotations = Vec::new();
+        let mut scopecount = 0;
+        //scope annotations may overlap or nest, a span is only tagged once by each rule
+        let mut seen = HashSet::new();
+        let scope_annotations: Vec<_> = store
+            .annotations()
+            .filter(|annotation| scope.iter().any(|filter| filter.test(annotation)))
+            .collect();
+        let mut progress = Progress::new(
+            "Tagging scope annotations",
+            scope_annotations.len(),
+            progress,
+        );
+        for scope_annotation in scope_annotations {
+            scopecount += 1;
+            progress.set(scopecount);
+            let textselections: Vec<_> = scope_annotation.textselections().collect();
+            if link_scope && textselections.len() > 1 {
+                eprintln!(
+                    "Warning: scope annotation {} has multiple text selections, new annotations will not be linked to it",
+                    scope_annotation.id().unwrap_or("(no id)")
+                );
+            }
+            for textselection in textselections.iter() {
+                let link = if link_scope && textselections.len() == 1 {
+                    Some((
+                        scope_annotation
+                            .handle()
+                            .expect("annotation must have handle"),
+                        textselection.begin(),
+                    ))
+                } else {
+                    None
+                };
+                let resource = textselection
+                    .resource()
+                    .handle()
+                    .expect("resource must have handle");
+                //(we don't use TextSelection::find_text_regex() here as it computes wrong offsets for text selections not at the start of a resource)
+                let textmatches = if let Some(normalizer) = normalizer {
+                    find_in_normalized_text(
+                        textselection.text(),
+                        textselection.begin(),
+                        &matcher,
+                        allow_overlap,
+                        normalizer,
+                    )
+                } else {
+                    find_in_text(
+                        textselection.text(),
+                        textselection.begin(),
+                        &matcher,
+                        allow_overlap,
+                    )
+                };
+                for textmatch in textmatches {
+                    let spans: Vec<(usize, usize)> = textmatch
+                        .spans
+                        .iter()
+                        .map(|(_, begin, end, _)| (*begin, *end))
+                        .collect();
+                    if !seen.insert((resource, spans, textmatch.rule)) {
+                        continue;
+                    }
+                    let rule = rules.get(textmatch.rule).expect("rule must exist");
+                    annotations.extend(build_annotations(rule, &textmatch, resource, link));
+                }
+            }
+        }
+        progress.finish();
+        eprintln!("Tagged within {} scope annotation(s)", scopecount);
+        annotations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(expression: &str, key: &str, value: &str, scope: Option<&str>) -> Rule {
+        Rule {
+            expression: Regex::new(expression).unwrap(),
+            set: "test".to_string(),
+            data: vec![(key.to_string(), value.to_string())],
+            per_group: key == "*",
+            scope: scope.map(|scope| DataFilter::parse(scope, "/").unwrap()),
+        }
+    }
+
+    fn matches(text: &str, rules: &[Rule], allow_overlap: bool) -> Vec<(usize, usize, usize)> {
+        let rules: Vec<&Rule> = rules.iter().collect();
+        let matcher = Matcher::new(&rules);
+        find_in_text(text, 0, &matcher, allow_overlap)
+            .into_iter()
+            .flat_map(|m| {
+                let rule = m.rule;
+                m.spans
+                    .into_iter()
+                    .map(move |(_, begin, end, _)| (rule, begin, end))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn passes_without_scope() {
+        let rules = vec![
+            rule("\\w+", "type", "word", None),
+            rule("\\d+", "type", "number", None),
+        ];
+        assert_eq!(rule_passes(&rules).unwrap(), vec![0, 0]);
+    }
+
+    #[test]
+    fn passes_follow_scope() {
+        let rules = vec![
+            rule("[A-Z]", "type", "capital", Some("test/type=sentence")),
+            rule("[^.]+\\.", "type", "sentence", Some("test/type=paragraph")),
+            rule("(?s).+", "type", "paragraph", None),
+        ];
+        assert_eq!(rule_passes(&rules).unwrap(), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn passes_value_from_capture_group() {
+        //a value referencing a capture group may produce anything
+        let rules = vec![
+            rule("x", "type", "cross", Some("test/type=word")),
+            rule("(\\w+)", "type", "$1", None),
+        ];
+        assert_eq!(rule_passes(&rules).unwrap(), vec![1, 0]);
+    }
+
+    #[test]
+    fn passes_cycle() {
+        let rules = vec![
+            rule("a", "type", "a", Some("test/type=b")),
+            rule("b", "type", "b", Some("test/type=a")),
+        ];
+        let err = rule_passes(&rules).unwrap_err();
+        assert!(err.contains("cycle"), "{}", err);
+    }
+
+    #[test]
+    fn find_without_overlap() {
+        let rules = vec![
+            rule("quick brown", "type", "a", None),
+            rule("brown fox", "type", "b", None),
+        ];
+        assert_eq!(
+            matches("the quick brown fox", &rules, false),
+            vec![(0, 4, 15)]
+        );
+        assert_eq!(
+            matches("the quick brown fox", &rules, true),
+            vec![(0, 4, 15), (1, 10, 19)]
+        );
+    }
+
+    #[test]
+    fn find_overlapping_candidates() {
+        //the match of the first rule wins, the overlapping match of the second rule is discarded rather than resumed
+        //after it
+        let rules = vec![
+            rule("fox", "type", "animal", None),
+            rule("\\w+", "type", "word", None),
+        ];
+        assert_eq!(
+            matches("foxes jump", &rules, false),
+            vec![(0, 0, 3), (1, 6, 10)]
+        );
+        assert_eq!(
+            matches("foxes jump", &rules, true),
+            vec![(0, 0, 3), (1, 0, 5), (1, 6, 10)]
+        );
+        //the same holds when the candidates are determined by a set
+        let rules = vec![
+            rule("fox", "type", "animal", None),
+            rule("\\w+", "type", "word", None),
+            rule("\\d+", "type", "number", None),
+        ];
+        assert_eq!(
+            matches("foxes jump", &rules, false),
+            vec![(0, 0, 3), (1, 6, 10)]
+        );
+    }
+
+    #[test]
+    fn find_unicode_offsets() {
+        let rule = rule("wörld", "type", "a", None);
+        let rules = vec![&rule];
+        let matcher = Matcher::new(&rules);
+        let found = find_in_text("héllo wörld", 10, &matcher, false);
+        assert_eq!(found[0].spans, vec![(0, 16, 21, "wörld")]);
+    }
+
+    #[test]
+    fn find_capture_groups() {
+        let rules = vec![rule("(\\d+)-(\\d+)", "range", "$1 to $2", None)];
+        assert_eq!(
+            matches("pages 10-12", &rules, false),
+            vec![(0, 6, 8), (0, 9, 11)]
+        );
+    }
+
+    #[test]
+    fn find_normalized() {
+        let rule = rule("quick brown", "type", "a", None);
+        let rules = vec![&rule];
+        let matcher = Matcher::new(&rules);
+        let normalizer = Normalizer::new().with_casefold(true).with_whitespace(true);
+        let found =
+            find_in_normalized_text("The Quick  Brown fox", 0, &matcher, false, &normalizer);
+        assert_eq!(found[0].spans, vec![(0, 4, 16, "Quick  Brown")]);
+    }
+
+    #[test]
+    fn resolve_references() {
+        let rule = rule(
+            "(?P<day>\\d+)/(?P<month>\\d+)",
+            "date",
+            "$month-$day ($0)",
+            None,
+        );
+        let rules = vec![&rule];
+        let matcher = Matcher::new(&rules);
+        let found = find_in_text("on 14/10", 0, &matcher, false);
+        assert_eq!(
+            rule.resolve_value(&rule.data[0].1, &found[0], Some("own")),
+            "10-14 (own)"
+        );
+    }
+}
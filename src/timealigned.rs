@@ -0,0 +1,705 @@
+//! Importers for time-aligned annotation, as used for speech: ELAN (`.eaf`) and Praat TextGrid files.
+//! STAM offsets are character-based, so a transcript resource is generated from the tier contents,
+//! and the original timestamps are kept as annotation data. Annotations with such time data can
+//! be exported as subtitles (SRT or WebVTT).
+
+use clap::Arg;
+use stam::{
+    Annotation, AnnotationBuilder, AnnotationDataBuilder, AnnotationStore, Config, Item, Offset,
+    SelectorBuilder, StoreFor, TextResource, WrappedItem,
+};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use crate::filter::{DataFilter, Selection};
+
+/// The annotation set used for time-aligned data if none is specified
+pub const TIMEALIGNED_SET: &str = "speech";
+/// The key holding the begin time (in seconds) of a segment
+pub const BEGIN_TIME_KEY: &str = "begin_time";
+/// The key holding the end time (in seconds) of a segment
+pub const END_TIME_KEY: &str = "end_time";
+
+pub fn subtitle_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("begin-time-key")
+            .long("begin-time-key")
+            .help("For --format srt and vtt: the set/key holding the begin time of an annotation, in seconds")
+            .takes_value(true)
+            .default_value("speech/begin_time"),
+        Arg::with_name("end-time-key")
+            .long("end-time-key")
+            .help("For --format srt and vtt: the set/key holding the end time of an annotation, in seconds")
+            .takes_value(true)
+            .default_value("speech/end_time"),
+        Arg::with_name("max-line-length")
+            .long("max-line-length")
+            .help("For --format srt and vtt: wrap the text of a subtitle at word boundaries so lines have at most this many characters (longer words are kept whole)")
+            .takes_value(true)
+            .default_value("42"),
+        Arg::with_name("max-lines")
+            .long("max-lines")
+            .help("For --format srt and vtt: the maximum number of lines per subtitle, longer texts are split over multiple subtitles, dividing the time in proportion to their length")
+            .takes_value(true)
+            .default_value("2"),
+    ]
+}
+
+/// A segment of a tier: an interval (or, for Praat point tiers, a point) in time with its text
+#[derive(Clone, Debug)]
+pub struct TimedSegment {
+    /// ID of the segment, unique within the file
+    pub id: String,
+    pub text: String,
+    /// Begin time in seconds, if aligned
+    pub begin: Option<f64>,
+    /// End time in seconds, if aligned
+    pub end: Option<f64>,
+}
+
+/// A tier of time-aligned segments
+#[derive(Clone, Debug)]
+pub struct Tier {
+    pub name: String,
+    pub participant: Option<String>,
+    pub segments: Vec<TimedSegment>,
+}
+
+/// Parses an ELAN annotation document. Times of reference annotations (on symbolic tiers) are taken from the annotation they refer to.
+pub fn parse_eaf(xml: &str) -> Result<Vec<Tier>, String> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| format!("Invalid XML: {}", e))?;
+    let root = doc.root_element();
+    if !root.has_tag_name("ANNOTATION_DOCUMENT") {
+        return Err("Not an ELAN annotation document".to_string());
+    }
+    //time slots are in milliseconds, unaligned slots have no value
+    let mut timeslots: HashMap<&str, f64> = HashMap::new();
+    for slot in root.descendants().filter(|n| n.has_tag_name("TIME_SLOT")) {
+        if let (Some(id), Some(value)) =
+            (slot.attribute("TIME_SLOT_ID"), slot.attribute("TIME_VALUE"))
+        {
+            if let Ok(ms) = value.parse::<f64>() {
+                timeslots.insert(id, ms / 1000.0);
+            }
+        }
+    }
+    let mut times: HashMap<&str, (Option<f64>, Option<f64>)> = HashMap::new();
+    let mut references: HashMap<&str, &str> = HashMap::new();
+    for node in root.descendants() {
+        if let Some(id) = node.attribute("ANNOTATION_ID") {
+            if node.has_tag_name("ALIGNABLE_ANNOTATION") {
+                let slot = |name: &str| {
+                    node.attribute(name)
+                        .and_then(|slot| timeslots.get(slot).copied())
+                };
+                times.insert(id, (slot("TIME_SLOT_REF1"), slot("TIME_SLOT_REF2")));
+            } else if let Some(reference) = node.attribute("ANNOTATION_REF") {
+                references.insert(id, reference);
+            }
+        }
+    }
+    let mut tiers = Vec::new();
+    for tiernode in root.children().filter(|n| n.has_tag_name("TIER")) {
+        let name = tiernode
+            .attribute("TIER_ID")
+            .ok_or_else(|| "Tier without TIER_ID".to_string())?;
+        let mut tier = Tier {
+            name: name.to_string(),
+            participant: tiernode
+                .attribute("PARTICIPANT")
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_string()),
+            segments: Vec::new(),
+        };
+        for node in tiernode
+            .descendants()
+            .filter(|n| n.has_tag_name("ALIGNABLE_ANNOTATION") || n.has_tag_name("REF_ANNOTATION"))
+        {
+            let id = node
+                .attribute("ANNOTATION_ID")
+                .ok_or_else(|| format!("Annotation without ANNOTATION_ID in tier {}", name))?;
+            let text = node
+                .children()
+                .find(|n| n.has_tag_name("ANNOTATION_VALUE"))
+                .and_then(|n| n.text())
+                .unwrap_or("");
+            let (begin, end) = resolve_times(id, &times, &references);
+            tier.segments.push(TimedSegment {
+                id: id.to_string(),
+                text: text.to_string(),
+                begin,
+                end,
+            });
+        }
+        tiers.push(tier);
+    }
+    Ok(tiers)
+}
+
+/// Returns the times of an ELAN annotation, following references (with a limit, in case of cycles)
+fn resolve_times<'a>(
+    mut id: &'a str,
+    times: &HashMap<&'a str, (Option<f64>, Option<f64>)>,
+    references: &HashMap<&'a str, &'a str>,
+) -> (Option<f64>, Option<f64>) {
+    for _ in 0..100 {
+        if let Some(times) = times.get(id) {
+            return *times;
+        }
+        match references.get(id) {
+            Some(reference) => id = reference,
+            None => break,
+        }
+    }
+    (None, None)
+}
+
+enum TextGridToken {
+    Number(f64),
+    Text(String),
+    Flag(String),
+}
+
+/// Splits a TextGrid (in the long or the short text format) into its values. Like Praat, this ignores
+/// the labels (`xmin =`), the indices in square brackets and comments (after `!`).
+fn textgrid_tokens(text: &str) -> Vec<TextGridToken> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut s = String::new();
+                while let Some(c) = chars.next() {
+                    if c == '"' {
+                        //a double quote is escaped by doubling it
+                        if chars.peek() == Some(&'"') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    s.push(c);
+                }
+                tokens.push(TextGridToken::Text(s));
+            }
+            '[' => for _ in chars.by_ref().take_while(|c| *c != ']') {},
+            '!' => for _ in chars.by_ref().take_while(|c| *c != '\n') {},
+            '<' => {
+                let flag: String = chars.by_ref().take_while(|c| *c != '>').collect();
+                tokens.push(TextGridToken::Flag(flag));
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let mut s = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || "eE+-.".contains(*c)) {
+                    s.push(c);
+                }
+                if let Ok(n) = s.parse() {
+                    tokens.push(TextGridToken::Number(n));
+                }
+            }
+            c if c.is_alphabetic() => {
+                while chars
+                    .next_if(|c| c.is_alphanumeric() || *c == '_')
+                    .is_some()
+                {}
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// Parses a Praat TextGrid, in the long or the short text format. Both interval tiers and point tiers
+/// (where the begin and end time are the same) are supported.
+pub fn parse_textgrid(text: &str) -> Result<Vec<Tier>, String> {
+    let mut tokens = textgrid_tokens(text).into_iter();
+    let next_number =
+        |tokens: &mut std::vec::IntoIter<TextGridToken>, what: &str| match tokens.next() {
+            Some(TextGridToken::Number(n)) => Ok(n),
+            _ => Err(format!("Invalid TextGrid: expected a number for {}", what)),
+        };
+    let next_text = |tokens: &mut std::vec::IntoIter<TextGridToken>, what: &str| match tokens.next()
+    {
+        Some(TextGridToken::Text(s)) => Ok(s),
+        _ => Err(format!("Invalid TextGrid: expected a string for {}", what)),
+    };
+    if next_text(&mut tokens, "the file type")? != "ooTextFile"
+        || next_text(&mut tokens, "the object class")? != "TextGrid"
+    {
+        return Err("Not a Praat TextGrid in text format".to_string());
+    }
+    next_number(&mut tokens, "xmin")?;
+    next_number(&mut tokens, "xmax")?;
+    match tokens.next() {
+        Some(TextGridToken::Flag(flag)) if flag == "exists" => {}
+        //no tiers
+        _ => return Ok(Vec::new()),
+    }
+    let size = next_number(&mut tokens, "the number of tiers")? as usize;
+    let mut tiers = Vec::with_capacity(size);
+    for _ in 0..size {
+        let class = next_text(&mut tokens, "the tier class")?;
+        let name = next_text(&mut tokens, "the tier name")?;
+        next_number(&mut tokens, "xmin")?;
+        next_number(&mut tokens, "xmax")?;
+        let count = next_number(&mut tokens, "the number of intervals")? as usize;
+        let mut tier = Tier {
+            name: name.clone(),
+            participant: None,
+            segments: Vec::with_capacity(count),
+        };
+        for i in 0..count {
+            let (begin, end) = match class.as_str() {
+                "IntervalTier" => (
+                    next_number(&mut tokens, "xmin")?,
+                    next_number(&mut tokens, "xmax")?,
+                ),
+                "TextTier" => {
+                    let time = next_number(&mut tokens, "the time of a point")?;
+                    (time, time)
+                }
+                _ => return Err(format!("Unsupported tier class in TextGrid: {}", class)),
+            };
+            tier.segments.push(TimedSegment {
+                id: format!("{}.{}", name, i + 1),
+                text: next_text(&mut tokens, "the text")?,
+                begin: Some(begin),
+                end: Some(end),
+            });
+        }
+        tiers.push(tier);
+    }
+    Ok(tiers)
+}
+
+/// Reads a TextGrid file, which Praat may write in UTF-16 (recognized by its byte order mark) or UTF-8
+fn read_textgrid_file(filename: &str) -> Result<String, String> {
+    let bytes =
+        std::fs::read(filename).map_err(|e| format!("Error reading {}: {}", filename, e))?;
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| from_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16(&units).map_err(|e| format!("Error decoding {}: {}", filename, e))
+    };
+    match bytes.as_slice() {
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8(rest.to_vec())
+            .map_err(|e| format!("Error decoding {}: {}", filename, e)),
+        _ => String::from_utf8(bytes).map_err(|e| format!("Error decoding {}: {}", filename, e)),
+    }
+}
+
+/// Returns the ID (and filename) of the transcript resource generated for a time-aligned file
+pub fn transcript_filename(filename: &str) -> String {
+    Path::new(filename)
+        .with_extension("transcript.txt")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Adds a transcript resource with the text of all tiers, and an annotation for each segment that has text.
+/// The transcript has one line per segment, tiers are separated by an empty line. Each annotation has the text
+/// as value of a key named after its tier, the begin and end time in seconds (keys `begin_time` and `end_time`,
+/// if aligned) and the participant (if known), all in the set `set`. Annotation IDs are prefixed with the
+/// resource ID. Returns the number of annotations added.
+pub fn import_tiers(
+    store: &mut AnnotationStore,
+    tiers: &[Tier],
+    resource_id: &str,
+    set: &str,
+) -> Result<usize, String> {
+    if store.resource(&Item::IdRef(resource_id)).is_some() {
+        return Err(format!("Resource {} already exists", resource_id));
+    }
+    let mut text = String::new();
+    let mut pos = 0; //in unicode points
+    let mut spans: Vec<(&Tier, &TimedSegment, String, usize, usize)> = Vec::new();
+    for tier in tiers {
+        let mut first = true;
+        for segment in tier.segments.iter() {
+            //newlines are reserved to separate the segments
+            let segmenttext = segment
+                .text
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            if segmenttext.is_empty() {
+                continue;
+            }
+            if first && !text.is_empty() {
+                text.push('\n');
+                pos += 1;
+            }
+            first = false;
+            let length = segmenttext.chars().count();
+            text.push_str(&segmenttext);
+            text.push('\n');
+            spans.push((tier, segment, segmenttext, pos, pos + length));
+            pos += length + 1;
+        }
+    }
+    let resource = TextResource::new(resource_id.to_string(), Config::default())
+        .with_string(text)
+        .with_filename(resource_id);
+    let handle = store
+        .insert(resource)
+        .map_err(|e| format!("Error adding transcript to store: {}", e))?;
+    let mut count = 0;
+    for (tier, segment, segmenttext, begin, end) in spans {
+        let data = |key: &str| {
+            AnnotationDataBuilder::new()
+                .with_annotationset(Item::IdRef(set))
+                .with_key(Item::Id(key.to_string()))
+        };
+        let mut builder = AnnotationBuilder::new()
+            .with_id(format!("{}.{}", resource_id, segment.id))
+            .with_target(SelectorBuilder::TextSelector(
+                Item::Handle(handle),
+                Offset::simple(begin, end),
+            ))
+            .with_data_builder(data(&tier.name).with_value(segmenttext.into()));
+        if let Some(time) = segment.begin {
+            builder = builder.with_data_builder(data(BEGIN_TIME_KEY).with_value(time.into()));
+        }
+        if let Some(time) = segment.end {
+            builder = builder.with_data_builder(data(END_TIME_KEY).with_value(time.into()));
+        }
+        if let Some(participant) = tier.participant.as_ref() {
+            builder = builder
+                .with_data_builder(data("participant").with_value(participant.as_str().into()));
+        }
+        store
+            .annotate(builder)
+            .map_err(|e| format!("Error adding annotation: {}", e))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Imports an ELAN annotation document (`.eaf`), see [`import_tiers()`]. The transcript resource is named after the file,
+/// unless `resource` is set.
+pub fn from_eaf(
+    store: &mut AnnotationStore,
+    filename: &str,
+    resource: Option<&str>,
+    set: Option<&str>,
+) -> Result<usize, String> {
+    let xml = std::fs::read_to_string(filename)
+        .map_err(|e| format!("Error reading {}: {}", filename, e))?;
+    let tiers = parse_eaf(&xml).map_err(|e| format!("Error parsing {}: {}", filename, e))?;
+    let resource_id = resource
+        .map(|resource| resource.to_string())
+        .unwrap_or_else(|| transcript_filename(filename));
+    import_tiers(store, &tiers, &resource_id, set.unwrap_or(TIMEALIGNED_SET))
+}
+
+/// Imports a Praat TextGrid, see [`import_tiers()`]. The transcript resource is named after the file, unless `resource` is set.
+pub fn from_textgrid(
+    store: &mut AnnotationStore,
+    filename: &str,
+    resource: Option<&str>,
+    set: Option<&str>,
+) -> Result<usize, String> {
+    let text = read_textgrid_file(filename)?;
+    let tiers = parse_textgrid(&text).map_err(|e| format!("Error parsing {}: {}", filename, e))?;
+    let resource_id = resource
+        .map(|resource| resource.to_string())
+        .unwrap_or_else(|| transcript_filename(filename));
+    import_tiers(store, &tiers, &resource_id, set.unwrap_or(TIMEALIGNED_SET))
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SubtitleFormat {
+    Srt,
+    WebVtt,
+}
+
+/// A subtitle: a text shown from the begin to the end time (in seconds)
+#[derive(Clone, Debug)]
+pub struct Cue {
+    pub begin: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// Returns the value of the data, as a number of seconds
+fn time_of(filter: &DataFilter, annotation: &WrappedItem<Annotation>) -> Option<f64> {
+    filter
+        .value_of(annotation)
+        .and_then(|value| value.parse::<f64>().ok())
+}
+
+/// Collects a cue for each annotation in the selection that has both a begin and an end time, with the text it
+/// refers to (whitespace is collapsed). The cues are sorted by time.
+pub fn subtitle_cues(
+    store: &AnnotationStore,
+    begin_key: &DataFilter,
+    end_key: &DataFilter,
+    selection: &Selection,
+) -> Vec<Cue> {
+    let mut cues: Vec<Cue> = Vec::new();
+    for annotation in store.annotations() {
+        if let (Some(begin), Some(end)) = (
+            time_of(begin_key, &annotation),
+            time_of(end_key, &annotation),
+        ) {
+            if !selection.test(&annotation) {
+                continue;
+            }
+            let text: Vec<&str> = annotation
+                .text()
+                .flat_map(|t| t.split_whitespace())
+                .collect();
+            if !text.is_empty() {
+                cues.push(Cue {
+                    begin,
+                    end,
+                    text: text.join(" "),
+                });
+            }
+        }
+    }
+    cues.sort_by(|a, b| {
+        a.begin
+            .total_cmp(&b.begin)
+            .then_with(|| a.end.total_cmp(&b.end))
+    });
+    cues
+}
+
+/// Wraps a text at word boundaries into lines of at most `max_length` characters, words longer than that get a line of their own
+pub fn wrap_lines(text: &str, max_length: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_length {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Formats a time in seconds as hours, minutes, seconds and milliseconds, with the separator before the milliseconds
+fn format_time(seconds: f64, separator: char) -> String {
+    let ms = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+/// Writes the cues as subtitles. The text of each cue is wrapped into lines of at most `max_line_length` characters;
+/// if that yields more than `max_lines` lines, the cue is split into multiple subtitles and its time is divided over
+/// them in proportion to the length of their text. Returns the number of subtitles written.
+pub fn to_subtitles(
+    writer: &mut impl Write,
+    cues: &[Cue],
+    format: SubtitleFormat,
+    max_line_length: usize,
+    max_lines: usize,
+) -> Result<usize, String> {
+    let mut write = |text: String| write!(writer, "{}", text).map_err(|e| format!("{}", e));
+    if format == SubtitleFormat::WebVtt {
+        write("WEBVTT\n\n".to_string())?;
+    }
+    let mut count = 0;
+    for cue in cues {
+        let lines = wrap_lines(&cue.text, max_line_length);
+        let chunks: Vec<&[String]> = lines.chunks(max_lines.max(1)).collect();
+        let length =
+            |chunk: &[String]| chunk.iter().map(|line| line.chars().count()).sum::<usize>();
+        let total: usize = chunks.iter().map(|chunk| length(chunk)).sum();
+        let mut begin = cue.begin;
+        for chunk in chunks {
+            let end = if total == 0 {
+                cue.end
+            } else {
+                (begin + (cue.end - cue.begin) * length(chunk) as f64 / total as f64).min(cue.end)
+            };
+            count += 1;
+            let text = chunk.join("\n");
+            write(match format {
+                SubtitleFormat::Srt => format!(
+                    "{}\n{} --> {}\n{}\n\n",
+                    count,
+                    format_time(begin, ','),
+                    format_time(end, ','),
+                    text
+                ),
+                SubtitleFormat::WebVtt => format!(
+                    "{} --> {}\n{}\n\n",
+                    format_time(begin, '.'),
+                    format_time(end, '.'),
+                    text.replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('>', "&gt;")
+                ),
+            })?;
+            begin = end;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EAF: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ANNOTATION_DOCUMENT>
+    <TIME_ORDER>
+        <TIME_SLOT TIME_SLOT_ID="ts1" TIME_VALUE="500"/>
+        <TIME_SLOT TIME_SLOT_ID="ts2" TIME_VALUE="1750"/>
+        <TIME_SLOT TIME_SLOT_ID="ts3"/>
+    </TIME_ORDER>
+    <TIER TIER_ID="speaker" PARTICIPANT="A">
+        <ANNOTATION>
+            <ALIGNABLE_ANNOTATION ANNOTATION_ID="a1" TIME_SLOT_REF1="ts1" TIME_SLOT_REF2="ts2">
+                <ANNOTATION_VALUE>hello there</ANNOTATION_VALUE>
+            </ALIGNABLE_ANNOTATION>
+        </ANNOTATION>
+        <ANNOTATION>
+            <ALIGNABLE_ANNOTATION ANNOTATION_ID="a2" TIME_SLOT_REF1="ts2" TIME_SLOT_REF2="ts3">
+                <ANNOTATION_VALUE>bye</ANNOTATION_VALUE>
+            </ALIGNABLE_ANNOTATION>
+        </ANNOTATION>
+    </TIER>
+    <TIER TIER_ID="translation" PARENT_REF="speaker">
+        <ANNOTATION>
+            <REF_ANNOTATION ANNOTATION_ID="a3" ANNOTATION_REF="a1">
+                <ANNOTATION_VALUE>hallo daar</ANNOTATION_VALUE>
+            </REF_ANNOTATION>
+        </ANNOTATION>
+    </TIER>
+</ANNOTATION_DOCUMENT>"#;
+
+    const TEXTGRID_LONG: &str = r#"File type = "ooTextFile"
+Object class = "TextGrid"
+
+xmin = 0
+xmax = 2.5
+tiers? <exists>
+size = 2
+item []:
+    item [1]:
+        class = "IntervalTier"
+        name = "words"
+        xmin = 0
+        xmax = 2.5
+        intervals: size = 2
+        intervals [1]:
+            xmin = 0
+            xmax = 1.2
+            text = "say ""hi"""
+        intervals [2]:
+            xmin = 1.2
+            xmax = 2.5
+            text = ""
+    item [2]:
+        class = "TextTier"
+        name = "events"
+        xmin = 0
+        xmax = 2.5
+        points: size = 1
+        points [1]:
+            number = 0.8 ! a comment
+            mark = "click"
+"#;
+
+    const TEXTGRID_SHORT: &str = "File type = \"ooTextFile\"\nObject class = \"TextGrid\"\n\n0\n2.5\n<exists>\n1\n\"IntervalTier\"\n\"words\"\n0\n2.5\n1\n0\n2.5\n\"all\"\n";
+
+    #[test]
+    fn eaf() {
+        let tiers = parse_eaf(EAF).unwrap();
+        assert_eq!(tiers.len(), 2);
+        assert_eq!(tiers[0].name, "speaker");
+        assert_eq!(tiers[0].participant.as_deref(), Some("A"));
+        let segments = &tiers[0].segments;
+        assert_eq!(segments[0].text, "hello there");
+        assert_eq!(
+            (segments[0].begin, segments[0].end),
+            (Some(0.5), Some(1.75))
+        );
+        //unaligned time slot
+        assert_eq!((segments[1].begin, segments[1].end), (Some(1.75), None));
+        //reference annotations take the times of the annotation they refer to
+        let segment = &tiers[1].segments[0];
+        assert_eq!(segment.id, "a3");
+        assert_eq!(segment.text, "hallo daar");
+        assert_eq!((segment.begin, segment.end), (Some(0.5), Some(1.75)));
+    }
+
+    #[test]
+    fn eaf_invalid() {
+        assert!(parse_eaf("<TextGrid/>").is_err());
+        assert!(parse_eaf("not xml").is_err());
+    }
+
+    #[test]
+    fn textgrid_long() {
+        let tiers = parse_textgrid(TEXTGRID_LONG).unwrap();
+        assert_eq!(tiers.len(), 2);
+        assert_eq!(tiers[0].name, "words");
+        assert_eq!(tiers[0].segments[0].id, "words.1");
+        assert_eq!(tiers[0].segments[0].text, "say \"hi\"");
+        assert_eq!(tiers[0].segments[1].begin, Some(1.2));
+        assert_eq!(tiers[0].segments[1].end, Some(2.5));
+        //points have the same begin and end
+        let point = &tiers[1].segments[0];
+        assert_eq!(point.text, "click");
+        assert_eq!((point.begin, point.end), (Some(0.8), Some(0.8)));
+    }
+
+    #[test]
+    fn textgrid_short() {
+        let tiers = parse_textgrid(TEXTGRID_SHORT).unwrap();
+        assert_eq!(tiers.len(), 1);
+        assert_eq!(tiers[0].segments.len(), 1);
+        assert_eq!(tiers[0].segments[0].text, "all");
+        assert_eq!(tiers[0].segments[0].end, Some(2.5));
+    }
+
+    #[test]
+    fn textgrid_invalid() {
+        assert!(
+            parse_textgrid("File type = \"ooTextFile\"\nObject class = \"Pitch 1\"\n").is_err()
+        );
+        //truncated
+        assert!(parse_textgrid(&TEXTGRID_SHORT[..TEXTGRID_SHORT.len() - 8]).is_err());
+    }
+
+    #[test]
+    fn wrap() {
+        assert_eq!(
+            wrap_lines("the quick brown fox", 10),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(
+            wrap_lines("a verylongword b", 4),
+            vec!["a", "verylongword", "b"]
+        );
+        assert!(wrap_lines("  ", 10).is_empty());
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(format_time(0.0, ','), "00:00:00,000");
+        assert_eq!(format_time(3723.4567, '.'), "01:02:03.457");
+        assert_eq!(format_time(-1.0, ','), "00:00:00,000");
+    }
+}
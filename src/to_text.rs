@@ -0,0 +1,112 @@
+use stam::{AnnotationStore, Cursor, Item, Offset, Text};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::exit;
+
+use crate::tsv::offset_from_utf8;
+
+pub fn to_text(store: &AnnotationStore, resource_ids: Vec<&str>) {
+    for resource_id in resource_ids {
+        if let Some(resource) = store.resource(&Item::Id(resource_id.to_string())) {
+            eprintln!(
+                "--------------------------- {} ---------------------------",
+                resource_id
+            );
+            println!("{}", resource.text());
+        } else {
+            eprintln!("Error: Resource with ID {} does not exist", resource_id);
+            exit(1);
+        }
+    }
+}
+
+/// A span of text to print
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub resource: String,
+    pub offset: Offset,
+    /// Identifier for the span, defaults to resource#begin-end
+    pub id: String,
+}
+
+/// Loads spans from a TSV file (or stdin if the filename is `-`) with the resource ID, begin offset, end offset
+/// and optionally an identifier on each line. Offsets may be negative to count from the end of the text.
+/// Empty lines, lines starting with `#`, and a header on the first line are skipped.
+pub fn load_spans(filename: &str) -> Result<Vec<Span>, String> {
+    let reader: Box<dyn BufRead> = if filename == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        let f = File::open(filename).map_err(|e| format!("Error opening {}: {}", filename, e))?;
+        Box::new(BufReader::new(f))
+    };
+    let mut spans = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("Error reading {}: {}", filename, e))?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let cells: Vec<&str> = line.split('\t').collect();
+        if cells.len() < 3 {
+            return Err(format!(
+                "{} line {}: expected resource, begin and end columns",
+                filename,
+                i + 1
+            ));
+        }
+        let begin: Result<Cursor, _> = cells[1].try_into();
+        let end: Result<Cursor, _> = cells[2].try_into();
+        let (begin, end) = match (begin, end) {
+            (Ok(begin), Ok(end)) => (begin, end),
+            //header
+            _ if i == 0 => continue,
+            _ => {
+                return Err(format!(
+                    "{} line {}: invalid offset {}-{}",
+                    filename,
+                    i + 1,
+                    cells[1],
+                    cells[2]
+                ))
+            }
+        };
+        spans.push(Span {
+            resource: cells[0].to_string(),
+            offset: Offset::new(begin, end),
+            id: match cells.get(3) {
+                Some(id) if !id.is_empty() => id.to_string(),
+                _ => format!("{}#{}-{}", cells[0], cells[1], cells[2]),
+            },
+        });
+    }
+    Ok(spans)
+}
+
+/// Prints the text of each span, followed by the separator. If `with_id` is set, each text is preceded by the
+/// identifier of the span and a tab.
+pub fn print_spans(
+    writer: &mut impl Write,
+    store: &AnnotationStore,
+    spans: &[Span],
+    separator: &str,
+    with_id: bool,
+    utf8_offsets: bool,
+) -> Result<(), String> {
+    for span in spans {
+        let resource = store
+            .resource(&Item::Id(span.resource.clone()))
+            .ok_or_else(|| format!("Resource with ID {} does not exist", span.resource))?;
+        let offset = if utf8_offsets {
+            offset_from_utf8(&resource, &span.offset).map_err(|e| format!("{}: {}", span.id, e))?
+        } else {
+            span.offset.clone()
+        };
+        let text = resource
+            .text_by_offset(&offset)
+            .map_err(|e| format!("{}: {}", span.id, e))?;
+        if with_id {
+            write!(writer, "{}\t", span.id).map_err(|e| format!("{}", e))?;
+        }
+        write!(writer, "{}{}", text, separator).map_err(|e| format!("{}", e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,2778 @@
+use clap::{Arg, ArgAction};
+use stam::{
+    Annotation, AnnotationBuilder, AnnotationData, AnnotationDataBuilder, AnnotationDataSet,
+    AnnotationHandle, AnnotationStore, Config, Cursor, DataKey, DataOperator, DataValue, Item,
+    Offset, Selector, StamError, Storable, StoreFor, Text, TextResource, TextResourceHandle,
+    TextSelection, TextSelectionOperator, WrappedItem,
+};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::rc::Rc;
+
+use crate::filter::{DataFilter, Selection};
+use crate::pipeline::{ExportJob, ImportJob};
+use crate::progress::Progress;
+use crate::rewrite::StoreJson;
+
+pub fn tsv_arguments_common<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("subdelimiter")
+            .long("subdelimiter")
+            .env("STAM_SUBDELIMITER")
+            .help("Delimiter for multiple values in a single column")
+            .takes_value(true)
+            .default_value("|"),
+        Arg::with_name("setdelimiter")
+            .long("setdelimiter")
+            .env("STAM_SETDELIMITER")
+            .help(
+                "The delimiter between the annotation set and the key in custom columns. If the delimiter occurs multiple times, only the rightmost one is considered (the others are part of the set)"
+            )
+            .takes_value(true)
+            .default_value("/"),
+        Arg::with_name("null")
+            .long("null")
+            .env("STAM_NULL")
+            .help("Text to use for NULL values")
+            .takes_value(true)
+            .default_value("-"),
+        Arg::with_name("delimiter")
+            .long("delimiter")
+            .env("STAM_DELIMITER")
+            .help("The delimiter between columns, a single character (use 'tab' for a tab). Any delimiter other than a tab implies --quoted, so CSV can be read and written with --delimiter ,")
+            .takes_value(true)
+            .default_value("tab"),
+        Arg::with_name("quoted")
+            .long("quoted")
+            .help("Cells may be quoted with double quotes (as in CSV), so they can hold the delimiter, double quotes (doubled) and newlines. On import, tabs and newlines inside quoted cells are read as the escape sequences \\t and \\n. On export, cells are quoted where needed."),
+    ]
+}
+
+/// How columns are delimited and cells are quoted (like a CSV dialect)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub quoted: bool,
+}
+
+impl Default for Dialect {
+    /// Plain TSV, without quoting
+    fn default() -> Self {
+        Self {
+            delimiter: b'\t',
+            quoted: false,
+        }
+    }
+}
+
+impl Dialect {
+    /// Parses the delimiter (`tab` or `\t` for a tab, otherwise a single ASCII character). Any delimiter other than a tab
+    /// implies quoting.
+    pub fn new(delimiter: &str, quoted: bool) -> Result<Self, String> {
+        let delimiter = match delimiter {
+            "tab" | "\\t" => b'\t',
+            delimiter if delimiter.len() == 1 && delimiter.is_ascii() => delimiter.as_bytes()[0],
+            _ => {
+                return Err(format!(
+                    "Invalid delimiter {:?}, must be a single character",
+                    delimiter
+                ))
+            }
+        };
+        Ok(Self {
+            delimiter,
+            quoted: quoted || delimiter != b'\t',
+        })
+    }
+
+    /// Formats cells as a row (without a trailing newline), quoting cells where needed
+    pub fn format_row<S: AsRef<str>>(&self, cells: &[S]) -> String {
+        let delimiter = self.delimiter as char;
+        let mut row = String::new();
+        for (i, cell) in cells.iter().enumerate() {
+            let cell = cell.as_ref();
+            if i > 0 {
+                row.push(delimiter);
+            }
+            if self.quoted && cell.contains([delimiter, '"', '\n', '\r']) {
+                row.push('"');
+                row.push_str(&cell.replace('"', "\"\""));
+                row.push('"');
+            } else {
+                row.push_str(cell);
+            }
+        }
+        row
+    }
+
+    /// Reads the lines of a file in this dialect, in the internal form with tab-separated cells. If quoting is enabled,
+    /// tabs and newlines in cells are written as escape sequences. A byte order mark at the start is skipped.
+    pub fn read_lines(
+        &self,
+        filename: &str,
+    ) -> Result<Box<dyn Iterator<Item = Result<String, String>>>, String> {
+        let f = File::open(filename)
+            .map_err(|e| format!("Error opening TSV file {}: {}", filename, e))?;
+        let filename = filename.to_string();
+        let strip_bom = |(i, line): (usize, String)| {
+            if i == 0 {
+                line.strip_prefix('\u{feff}')
+                    .map(|x| x.to_string())
+                    .unwrap_or(line)
+            } else {
+                line
+            }
+        };
+        if !self.quoted {
+            return Ok(Box::new(BufReader::new(f).lines().enumerate().map(
+                move |(i, line)| {
+                    line.map(|line| strip_bom((i, line)))
+                        .map_err(|e| format!("Error reading {}: {}", filename, e))
+                },
+            )));
+        }
+        //the csv reader skips blank lines, but they are significant (e.g. as paragraph breaks when
+        //reconstructing text), so they are recovered from the bytes preceding each record
+        let mut data: Vec<u8> = Vec::new();
+        BufReader::new(f)
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Error reading {}: {}", filename, e))?;
+        let data: Rc<[u8]> = data.into();
+        let reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .comment(None)
+            .from_reader(std::io::Cursor::new(data.clone()));
+        Ok(Box::new(
+            reader
+                .into_records()
+                .flat_map(move |record| {
+                    let record = match record {
+                        Ok(record) => record,
+                        Err(e) => return vec![Err(format!("Error reading {}: {}", filename, e))],
+                    };
+                    let begin = record
+                        .position()
+                        .map(|position| position.byte() as usize)
+                        .unwrap_or(0);
+                    let mut lines: Vec<Result<String, String>> = (0..blank_lines(&data, begin))
+                        .map(|_| Ok(String::new()))
+                        .collect();
+                    let cells: Vec<String> = record
+                        .iter()
+                        .map(|cell| {
+                            cell.replace('\t', "\\t")
+                                .replace("\r\n", "\\n")
+                                .replace('\n', "\\n")
+                        })
+                        .collect();
+                    lines.push(Ok(cells.join("\t")));
+                    lines
+                })
+                .enumerate()
+                .map(move |(i, line)| line.map(|line| strip_bom((i, line)))),
+        ))
+    }
+}
+
+/// Counts the blank lines at the given byte position of CSV data, which the csv reader attributes to the start of the next
+/// record. The second half of the line terminator of the previous record may also be found there.
+fn blank_lines(data: &[u8], begin: usize) -> usize {
+    let mut pos = begin;
+    if pos > 0 && data.get(pos - 1) == Some(&b'\r') && data.get(pos) == Some(&b'\n') {
+        pos += 1;
+    }
+    let mut count = 0;
+    while let Some(c) = data.get(pos) {
+        match c {
+            b'\r' if data.get(pos + 1) == Some(&b'\n') => pos += 1,
+            b'\r' | b'\n' => {}
+            _ => break,
+        }
+        count += 1;
+        pos += 1;
+    }
+    count
+}
+
+pub fn tsv_arguments_out<'a>() -> Vec<clap::Arg<'a>> {
+    let mut args: Vec<Arg> = tsv_arguments_common();
+    args.push(
+        Arg::with_name("type")
+            .long("type")
+            .help("Select the data type to focus on for the TSV output.")
+            .long_help(
+                "Choose one from the following types (case insensitive):
+
+* Annotation
+* AnnotationData
+* AnnotationDataSet
+* DataKey
+* TextResource
+* TextSelection
+",
+            )
+            .takes_value(true)
+            .default_value("Annotation"),
+    );
+    args.push(
+        Arg::with_name("columns")
+            .long("columns")
+            .env("STAM_EXPORT_COLUMNS")
+            .short('C')
+            .help("Column Format, comma separated list of column names to output")
+            .long_help(
+                "Choose from the following known columns names (case insensitive, comma seperated list):
+
+* Type                 - Outputs the type of the row (Annotation,AnnotationData), useful in Nested mode.
+* Id                   - Outputs the ID of the row item
+* Annotation           - Outputs the ID of the associated Annotation
+* AnnotationData       - Outputs the ID of the associated AnnotationData
+* AnnotationDataSet    - Outputs the ID of the associated AnnotationDataSet
+* TextResource         - Output the associated resource identifier
+* DataKey              - Outputs the ID of the associated DataKey
+* DataValue            - Outputs the data value 
+* TextSelection        - Outputs any associated text selection(s) as a combination of resource identifier(s) with an offset
+* Text                 - Outputs the associated text
+* Offset               - Outputs offset pair in unicode character points (0-indexed, end is non-inclusive)
+* BeginOffset          - Outputs begin offset in unicode character points
+* EndOffset            - Outputs end offset in unicode character points
+* Utf8Offset           - Outputs offset pair in UTF-8 bytes  (0-indexed, end is non inclusive)
+* BeginUtf8Offset      - Outputs begin offset in UTF-8 bytes
+* EndUtf8Offset        - Outputs end offset in UTF8-bytes
+* RelativeBeginOffset  - Outputs begin offset in unicode character points, relative to the enclosing annotation: the parent
+                         (see below), or the annotation selected by --relative-to
+* RelativeEndOffset    - Outputs end offset in unicode character points, relative to the enclosing annotation
+* Parent               - Outputs the ID(s) of the annotation(s) this annotation targets via an AnnotationSelector
+* Children             - Outputs the ID(s) of the annotation(s) that target this annotation via an AnnotationSelector
+* Depth                - Outputs the nesting depth of the annotation: 0 if it does not target other annotations, otherwise one more than its deepest parent
+* Ignore               - Always outputs the NULL value
+
+In addition to the above columns, you may also set a *custom* column by  specifying an AnnotationDataSet and DataKey within, seperated by the set/key delimiter (by default a slash). The rows will then be filled with the
+data values corresponding to the data key. Which works great when you set --type Annotation and want specific AnnotationData outputted. Example:
+
+* my_set/part_of_speech
+* my_set/lemma
+
+",
+            )
+            .takes_value(true)
+            .default_value("Type,Id,AnnotationDataSet,DataKey,DataValue,Text,TextSelection"),
+    );
+    args.push(
+        Arg::with_name("no-header")
+            .long("no-header")
+            .short('H')
+            .help("Do not output a header on the first line")
+            .takes_value(false),
+    );
+    args.push(
+        Arg::with_name("format")
+            .long("format")
+            .env("STAM_EXPORT_FORMAT")
+            .short('F')
+            .help("Output format: tsv (to standard output), xlsx (spreadsheet, requires --outputfile), brat (Brat standoff .txt and .ann files per resource, written to the directory in --outputfile, see --brat-map), json-nested (annotations as JSON objects with the columns as keys, with their data and the annotations they target nested under them, to --outputfile or standard output), or srt or vtt (subtitles from the text of annotations with time data, see --begin-time-key, to --outputfile or standard output). The xlsx output has one sheet per type of row.")
+            .takes_value(true)
+            .possible_values(["tsv", "xlsx", "brat", "json-nested", "srt", "vtt"])
+            .default_value("tsv"),
+    );
+    args.push(
+        Arg::with_name("outputfile")
+            .long("outputfile")
+            .short('o')
+            .help("Output file, required for --format xlsx. For --format brat this is the output directory (default: current directory). For TSV output with --group-by, {group} in the filename is replaced by the value of each group, producing one file per group. Likewise, {resource} is replaced by the ID of the resource, producing one file per resource (and group).")
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("sort-by")
+            .long("sort-by")
+            .help("Sort the output by the values in these columns (comma separated list, in order of priority). The columns must also be included in --columns. Values are compared numerically where possible.")
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("distinct")
+            .long("distinct")
+            .help("Suppress duplicate rows, keeping the first. If columns are given (comma separated, e.g. --distinct=Text,my_set/pos), rows count as duplicates when they have the same values in those columns. The columns must also be included in --columns.")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true),
+    );
+    args.push(
+        Arg::with_name("group-by")
+            .long("group-by")
+            .help("Group the output by the value in this column, which must also be included in --columns. Each group is preceded by a comment line in TSV output, or written to a file of its own if --outputfile contains {group}. For XLSX output, each group gets its own sheet.")
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("relative-to")
+            .long("relative-to")
+            .help("For the RelativeBeginOffset and RelativeEndOffset columns: compute the offsets relative to the annotation matching this filter whose text encloses the text (e.g. my_set/type=sentence), rather than relative to the parent annotation. The syntax is set/key, set/key=value, etc.")
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("data")
+            .long("data")
+            .help("Only export annotations with this data. The syntax is set/key=value, or set/key, set/key!=value, set/key>number, etc. Multiple are allowed, an annotation must match all of them. Applies to --type Annotation.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+    );
+    args.push(
+        Arg::with_name("missing")
+            .long("missing")
+            .help("Only export annotations that lack this data, e.g. set/lemma for annotations without a lemma. Use this together with --data to select the annotations to check (e.g. --data set/type=token). The syntax is that of --data. Multiple are allowed, an annotation may match none of them. The number of such annotations is reported. Applies to --type Annotation.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+    );
+    args.push(
+        Arg::with_name("text-matches")
+            .long("text-matches")
+            .help("Only export annotations of which the text matches this regular expression (use ^ and $ to match the text as a whole). Multiple are allowed, the text must match all of them. Applies to --type Annotation.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+    );
+    args.push(
+        Arg::with_name("sheet-per-resource")
+            .long("sheet-per-resource")
+            .help("For --format xlsx: create one sheet per text resource rather than per type")
+            .takes_value(false),
+    );
+    args
+}
+
+pub fn tsv_arguments_in<'a>() -> Vec<clap::Arg<'a>> {
+    let mut args: Vec<Arg> = tsv_arguments_common();
+    args.push(
+        Arg::with_name("columns")
+            .long("columns")
+            .short('C')
+            .help("Column Format, comma separated list of column names to output")
+            .long_help(
+                "Choose from the following known columns names (case insensitive, comma seperated list):
+
+* Id                   - The ID of the annotation item
+* Annotation           - (same as above) 
+* AnnotationData       - The ID of the annotation data, used with `DataKey` and `DataValue`
+* AnnotationDataSet    - The ID of the associated AnnotationDataSet
+* TextResource         - The ID/filename of text resource, IDs are assumed to be filenames by this importer
+* DataKey              - The key
+* DataValue            - The value
+* TextSelection        - A combination of resource identifier(s) with an offset in the following format: resource#beginoffset-endoffset
+* Text                 - The text of the selection, target of the annotation 
+* Offset               - Offset in unicode character points (0-indexed, end is non-inclusive) seperated by a hyphen: beginoffset-endoffset
+* BeginOffset          - Begin offset in unicode character points
+* EndOffset            - End offset in unicode character points
+* OffsetHint           - Approximate begin offset in unicode character points. When aligning with an existing resource
+                         (no Offset columns), the text is only searched for close to this offset (see --align-window).
+
+In addition of the above columns, you may also parse a *custom* column by specifying an AnnotationDataSet and DataKey , separated by the set/key delimiter (by default a slash). Example:
+
+* my_set/part_of_speech
+* my_set/lemma
+
+",
+            )
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("no-header")
+            .long("no-header")
+            .short('H')
+            .help("Data starts on the first line, there is no header row")
+            .takes_value(false),
+    );
+    args.push(
+        Arg::with_name("no-seq")
+            .long("no-seq")
+            .short('Q')
+            .help("Rows in TSV file are not sequential but in arbitrary order"),
+    );
+    args.push(
+        Arg::with_name("inputfile")
+            .long("inputfile")
+            .short('f')
+            .help("TSV file (or Brat, ELAN or TextGrid file, see --format) to import. This option may be specified multiple times.")
+            .action(ArgAction::Append)
+            .required(true)
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("format")
+            .long("format")
+            .env("STAM_IMPORT_FORMAT")
+            .short('F')
+            .help("Input format: tsv, brat (Brat standoff .ann files, the text is read from the .txt file with the same name unless --resource is set), elan (ELAN .eaf files) or textgrid (Praat TextGrid files). Brat labels are imported as values of the key 'type' in the set specified by --annotationset (default: brat). For elan and textgrid, a transcript resource is generated from the tiers (named after the file, or as set by --new-resource), each segment is annotated with its text as the value of a key named after its tier, and its times (in seconds) as the values of the keys 'begin_time' and 'end_time', in the set specified by --annotationset (default: speech).")
+            .takes_value(true)
+            .possible_values(["tsv", "brat", "elan", "textgrid"])
+            .default_value("tsv"),
+    );
+    args.push(
+        Arg::with_name("resource")
+            .long("resource")
+            .help("Interpret data in the TSV file as pertaining to this existing text resource (a plain text file), unless made explicit in the data otherwise. The file must be present and will be loaded. If necessary, data will be aligned automatically to this resource.")
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("new-resource")
+            .long("new-resource")
+            .help(
+                "Interpret data in the TSV file as pertaining to this text resource, and reconstruct it from the data. Will write a separate txt file unless you provide the --no-include option.",
+            )
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("annotationset")
+            .long("annotationset")
+            .help(
+                "Interpret data in the TSV file as pertaining to this annotation set (unless made explicit in the data otherwise)",
+            )
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("validate")
+            .long("validate")
+            .help(
+                "Do text validation, values: strict, loose (case insensitive testing, this is the default), no"
+            )
+            .default_value("loose")
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("no-case")
+            .long("no-case")
+            .help("Do case insensitive matching when attempting to align text from the TSV input with a text resource"),
+    );
+    args.push(
+        Arg::with_name("align-window")
+            .long("align-window")
+            .help("When aligning text from the TSV input with a text resource, first search only this many characters after the end of the previous match, or around the offset in the OffsetHint column, before searching the entire remainder of the text. This keeps the alignment fast on large texts.")
+            .takes_value(true)
+            .default_value("1000"),
+    );
+    args.push(
+        Arg::with_name("no-escape")
+            .long("no-escape")
+            .help("Do not parse escape sequences for tabs (\\t) and newlines (\\n), leave as is"),
+    );
+    args.push(Arg::with_name("no-comments").long("no-comments").help(
+        "Do not allow comments, if not set, all lines starting with # are treated as comments",
+    ));
+    args.push(
+        Arg::with_name("join")
+            .long("join")
+            .help("Secondary TSV file with extra data for annotations, matched by annotation ID. The syntax is file.tsv:column, where column is the name of the header column holding the annotation IDs (default: Id). The file name ends at the first colon, write a colon in it as \\:. All other columns must be custom columns (set/key, or keys in the set specified by --annotationset). This option may be specified multiple times.")
+            .action(ArgAction::Append)
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("group")
+            .long("group")
+            .help("Interpret this custom column (set/key, or a key in the set specified by --annotationset) as a grouping column, like a sentence or document ID. Rather than adding its value to each row, all rows that share a value are combined into one new annotation with that value, which targets the annotations of the rows. This option may be specified multiple times.")
+            .action(ArgAction::Append)
+            .takes_value(true),
+    );
+    args.push(
+        Arg::with_name("outputdelimiter")
+            .long("outputdelimiter")
+            .help("Output delimiter when reconstructing text, after each row, this string is outputted. In most scenarios, like when having one word per row, you'll want this to be a space (which is the default).")
+            .takes_value(true)
+            .default_value(" "),
+    );
+    args.push(
+        Arg::with_name("outputdelimiter2")
+            .long("outputdelimiter2")
+            .help("Output delimiter when reconstructing text and when an empty line is found in the input data. In most scenarios, you will want this to be a newline (the default)")
+            .takes_value(true)
+            .default_value("\n"),
+    );
+    args
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Column {
+    Type,
+    Id,
+    Annotation,
+    TextResource,
+    AnnotationData,
+    AnnotationDataSet,
+    Offset,
+    BeginOffset,
+    EndOffset,
+    /// Approximate begin offset, only used to constrain the search when aligning with an existing text
+    OffsetHint,
+    Utf8Offset,
+    BeginUtf8Offset,
+    EndUtf8Offset,
+    /// Offsets relative to the enclosing annotation: the one matching the filter, or the parent annotation if there is no filter
+    RelativeBeginOffset(Option<DataFilter>),
+    RelativeEndOffset(Option<DataFilter>),
+    DataKey,
+    DataValue,
+    Text,
+    TextSelection,
+    Parent,
+    Children,
+    Depth,
+    Ignore,
+    Custom {
+        set: String,
+        key: String,
+    },
+    /// A custom column that is not added to the annotation of the row, but groups rows: all rows sharing a value
+    /// are targeted by one enclosing annotation that carries the value (import only)
+    Group {
+        set: String,
+        key: String,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ValidationMode {
+    Strict,
+    Loose,
+    No,
+}
+
+impl TryFrom<&str> for ValidationMode {
+    type Error = String;
+    fn try_from(val: &str) -> Result<Self, Self::Error> {
+        let val_lower = val.to_lowercase();
+        match val_lower.as_str() {
+            "strict" | "yes" => Ok(Self::Strict),
+            "loose" => Ok(Self::Loose),
+            "no" => Ok(Self::No),
+            _ => Err(format!(
+                "Unknown value for --validate: {}, see --help for allowed values",
+                val
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Type {
+    Annotation,
+    AnnotationDataSet,
+    AnnotationData,
+    DataKey,
+    TextResource,
+    TextSelection,
+}
+
+impl TryFrom<&str> for Type {
+    type Error = String;
+    fn try_from(val: &str) -> Result<Self, Self::Error> {
+        let val_lower = val.to_lowercase();
+        match val_lower.as_str() {
+            "annotation" | "annotations" => Ok(Self::Annotation),
+            "annotationdataset" | "dataset" | "annotationset" | "annotationdatasets"
+            | "datasets" | "annotationsets" => Ok(Self::AnnotationDataSet),
+            "data" | "annotationdata" | "datavalue" | "datavalues" => Ok(Self::AnnotationData),
+            "datakey" | "datakeys" | "key" | "keys" => Ok(Self::DataKey),
+            "resource" | "textresource" | "resources" | "textresources" => Ok(Self::TextResource),
+            "textselection" | "textselections" => Ok(Self::TextSelection),
+            _ => Err(format!(
+                "Unknown type: {}, see --help for allowed values",
+                val
+            )),
+        }
+    }
+}
+
+impl Type {
+    fn as_str(&self) -> &str {
+        match self {
+            Self::Annotation => "Annotation",
+            Self::AnnotationData => "AnnotationData",
+            Self::AnnotationDataSet => "AnnotationDataSet",
+            Self::DataKey => "DataKey",
+            Self::TextResource => "TextResource",
+            Self::TextSelection => "TextSelection",
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Column {
+    fn parse(val: &str, setdelimiter: &str) -> Result<Self, String> {
+        if val.find(setdelimiter).is_some() {
+            let (set, key) = val.rsplit_once(setdelimiter).unwrap();
+            Ok(Self::Custom {
+                set: set.to_string(),
+                key: key.to_string(),
+            })
+        } else {
+            let val_lower = val.to_lowercase();
+            match val_lower.as_str() {
+                "type" => Ok(Self::Type),
+                "id" => Ok(Self::Id),
+                "annotationid" | "annotation" => Ok(Self::Annotation),
+                "annotationdatasetid"
+                | "annotationdataset"
+                | "set"
+                | "setid"
+                | "datasetid"
+                | "dataset" => Ok(Self::AnnotationDataSet),
+                "resource" | "resourceid" | "textresource" | "textresources" => {
+                    Ok(Self::TextResource)
+                }
+                "annotationdataid" | "dataid" => Ok(Self::AnnotationData),
+                "offset" => Ok(Self::Offset),
+                "beginoffset" | "begin" | "start" | "startoffset" => Ok(Self::BeginOffset),
+                "endoffset" | "end" => Ok(Self::EndOffset),
+                "offsethint" | "hint" => Ok(Self::OffsetHint),
+                "utf8offset" => Ok(Self::Utf8Offset),
+                "beginutf8offset" | "beginutf8" | "beginbyte" | "startbyte" | "startutf8"
+                | "startutf8offset" => Ok(Self::BeginUtf8Offset),
+                "endutf8offset" | "endutf8" | "endbyte" => Ok(Self::EndUtf8Offset),
+                "relativebeginoffset" | "relativebegin" => Ok(Self::RelativeBeginOffset(None)),
+                "relativeendoffset" | "relativeend" => Ok(Self::RelativeEndOffset(None)),
+                "datakey" | "key" | "datakeyid" | "keyid" => Ok(Self::DataKey),
+                "datavalue" | "value" => Ok(Self::DataValue),
+                "text" => Ok(Self::Text),
+                "textselections" | "textselection" => Ok(Self::TextSelection),
+                "parent" | "parents" => Ok(Self::Parent),
+                "children" | "child" => Ok(Self::Children),
+                "depth" => Ok(Self::Depth),
+                "ignore" => Ok(Self::Ignore),
+                _ => Err(format!(
+                    "Unknown column: {}, see --help for allowed values",
+                    val
+                )),
+            }
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Type => write!(f, "Type"),
+            Self::Id => write!(f, "Id"),
+            Self::Annotation => write!(f, "Annotation"),
+            Self::TextResource => write!(f, "TextResource"),
+            Self::AnnotationData => write!(f, "AnnotationData"),
+            Self::AnnotationDataSet => write!(f, "AnnotationDataSet"),
+            Self::Offset => write!(f, "Offset"),
+            Self::BeginOffset => write!(f, "BeginOffset"),
+            Self::EndOffset => write!(f, "EndOffset"),
+            Self::OffsetHint => write!(f, "OffsetHint"),
+            Self::Utf8Offset => write!(f, "Utf8Offset"),
+            Self::BeginUtf8Offset => write!(f, "BeginUtf8Offset"),
+            Self::EndUtf8Offset => write!(f, "EndUtf8Offset"),
+            Self::RelativeBeginOffset(_) => write!(f, "RelativeBeginOffset"),
+            Self::RelativeEndOffset(_) => write!(f, "RelativeEndOffset"),
+            Self::DataKey => write!(f, "DataKey"),
+            Self::DataValue => write!(f, "DataValue"),
+            Self::Text => write!(f, "Text"),
+            Self::TextSelection => write!(f, "TextSelection"),
+            Self::Parent => write!(f, "Parent"),
+            Self::Children => write!(f, "Children"),
+            Self::Depth => write!(f, "Depth"),
+            Self::Ignore => write!(f, "Ignore"),
+            Self::Custom { set, key } | Self::Group { set, key } => {
+                write!(f, "{}/{}", set, key)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Context<'a> {
+    id: Option<&'a str>,
+    textselections: Option<&'a Vec<WrappedItem<'a, TextSelection>>>,
+    text: Option<&'a str>,
+    annotation: Option<WrappedItem<'a, Annotation>>,
+    data: Option<WrappedItem<'a, AnnotationData>>,
+    resource: Option<WrappedItem<'a, TextResource>>,
+    set: Option<WrappedItem<'a, AnnotationDataSet>>,
+    key: Option<WrappedItem<'a, DataKey>>,
+    value: Option<&'a DataValue>,
+}
+
+impl<'a> Context<'a> {
+    /// Returns the ID of the resource this context pertains to, if there are multiple, the first one is returned
+    fn resource_id(&self) -> Option<String> {
+        if let Some(resource) = &self.resource {
+            resource.id().map(|id| id.to_string())
+        } else if let Some(textselection) = self.textselections.and_then(|x| x.first()) {
+            textselection.resource().id().map(|id| id.to_string())
+        } else if let Some(annotation) = &self.annotation {
+            annotation
+                .textselections()
+                .next()
+                .and_then(|textselection| textselection.resource().id().map(|id| id.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+impl Column {
+    /// Does this column need the text selections of an annotation?
+    fn needs_textselections(&self) -> bool {
+        matches!(
+            self,
+            Column::TextSelection
+                | Column::Text
+                | Column::Offset
+                | Column::BeginOffset
+                | Column::EndOffset
+                | Column::OffsetHint
+                | Column::Utf8Offset
+                | Column::BeginUtf8Offset
+                | Column::EndUtf8Offset
+                | Column::RelativeBeginOffset(_)
+                | Column::RelativeEndOffset(_)
+        )
+    }
+
+    /// Returns the value of this column for the given context, as a string
+    fn value(&self, tp: Type, context: &Context, delimiter: &str, null: &str) -> String {
+        //helper to join a value derived from each of the text selections (if any)
+        let join_textselections = |f: &dyn Fn(&WrappedItem<TextSelection>) -> String| {
+            if let Some(textselections) = context.textselections {
+                textselections
+                    .iter()
+                    .map(f)
+                    .collect::<Vec<String>>()
+                    .join(delimiter)
+            } else {
+                null.to_string()
+            }
+        };
+        match self {
+            Column::Type => tp.as_str().to_string(),
+            Column::Id => context.id.unwrap_or(null).to_string(),
+            Column::TextSelection => join_textselections(&|textselection| {
+                format!(
+                    "{}#{}-{}",
+                    textselection.resource().id().unwrap_or(""),
+                    textselection.begin(),
+                    textselection.end()
+                )
+            }),
+            Column::Offset => join_textselections(&|textselection| {
+                format!("{}-{}", textselection.begin(), textselection.end())
+            }),
+            Column::BeginOffset | Column::OffsetHint => {
+                join_textselections(&|textselection| format!("{}", textselection.begin()))
+            }
+            Column::EndOffset => {
+                join_textselections(&|textselection| format!("{}", textselection.end()))
+            }
+            Column::Utf8Offset => join_textselections(&|textselection| {
+                format!(
+                    "{}-{}",
+                    textselection
+                        .resource()
+                        .utf8byte(textselection.begin())
+                        .expect("offset must be valid"),
+                    textselection
+                        .resource()
+                        .utf8byte(textselection.end())
+                        .expect("offset must be valid"),
+                )
+            }),
+            Column::BeginUtf8Offset => join_textselections(&|textselection| {
+                format!(
+                    "{}",
+                    textselection
+                        .resource()
+                        .utf8byte(textselection.begin())
+                        .expect("offset must be valid"),
+                )
+            }),
+            Column::EndUtf8Offset => join_textselections(&|textselection| {
+                format!(
+                    "{}",
+                    textselection
+                        .resource()
+                        .utf8byte(textselection.end())
+                        .expect("offset must be valid"),
+                )
+            }),
+            Column::Text => {
+                if let Some(text) = context.text {
+                    text.to_string()
+                } else {
+                    join_textselections(&|textselection| textselection.text().replace('\n', " "))
+                }
+            }
+            Column::Annotation => context
+                .annotation
+                .as_ref()
+                .map(|annotation| annotation.id().unwrap_or(null))
+                .unwrap_or(null)
+                .to_string(),
+            Column::AnnotationData => context
+                .data
+                .as_ref()
+                .map(|data| data.id().unwrap_or(null))
+                .unwrap_or(null)
+                .to_string(),
+            Column::AnnotationDataSet => context
+                .set
+                .as_ref()
+                .map(|set| set.id().unwrap_or(null))
+                .unwrap_or(null)
+                .to_string(),
+            Column::TextResource => context
+                .resource
+                .as_ref()
+                .map(|resource| resource.id().unwrap_or(null))
+                .unwrap_or(null)
+                .to_string(),
+            Column::DataKey => context
+                .key
+                .as_ref()
+                .map(|key| key.id().unwrap_or(null))
+                .unwrap_or(null)
+                .to_string(),
+            Column::DataValue => context
+                .value
+                .as_ref()
+                .map(|value| value.to_string())
+                .unwrap_or(null.to_string()),
+            Column::RelativeBeginOffset(filter) => join_textselections(&|textselection| {
+                enclosing_begin(context, textselection, filter.as_ref())
+                    .map(|begin| (textselection.begin() - begin).to_string())
+                    .unwrap_or(null.to_string())
+            }),
+            Column::RelativeEndOffset(filter) => join_textselections(&|textselection| {
+                enclosing_begin(context, textselection, filter.as_ref())
+                    .map(|begin| (textselection.end() - begin).to_string())
+                    .unwrap_or(null.to_string())
+            }),
+            Column::Parent => {
+                let parents: Vec<String> = context
+                    .annotation
+                    .iter()
+                    .flat_map(|annotation| annotation.annotations(false, false))
+                    .map(|parent| parent.id().unwrap_or(null).to_string())
+                    .collect();
+                if parents.is_empty() {
+                    null.to_string()
+                } else {
+                    parents.join(delimiter)
+                }
+            }
+            Column::Children => {
+                let children: Vec<String> = context
+                    .annotation
+                    .iter()
+                    .filter_map(|annotation| annotation.annotations_reverse())
+                    .flatten()
+                    .map(|child| child.id().unwrap_or(null).to_string())
+                    .collect();
+                if children.is_empty() {
+                    null.to_string()
+                } else {
+                    children.join(delimiter)
+                }
+            }
+            Column::Depth => {
+                if let Some(annotation) = &context.annotation {
+                    annotation_depth(annotation).to_string()
+                } else {
+                    null.to_string()
+                }
+            }
+            Column::Custom { set, key } => {
+                let values: Vec<String> = context
+                    .annotation
+                    .iter()
+                    .flat_map(|annotation| {
+                        annotation
+                            .find_data(Some(set.into()), Some(key.into()), DataOperator::Any)
+                            .into_iter()
+                            .flatten()
+                    })
+                    .map(|annotationdata| annotationdata.value().to_string())
+                    .collect();
+                if values.is_empty() {
+                    null.to_string()
+                } else {
+                    values.join(delimiter)
+                }
+            }
+            _ => null.to_string(),
+        }
+    }
+}
+
+/// Returns the begin offset of the annotation that encloses the text selection: the first annotation matching the filter, or if no filter is given, the first
+/// parent annotation (targeted via an AnnotationSelector). Returns None if there is no such annotation.
+fn enclosing_begin(
+    context: &Context,
+    textselection: &WrappedItem<TextSelection>,
+    filter: Option<&DataFilter>,
+) -> Option<usize> {
+    let annotation = context.annotation.as_ref()?;
+    let encloses = |enclosing: &WrappedItem<Annotation>| -> Option<usize> {
+        enclosing
+            .textselections()
+            .find(|other| {
+                other.resource().handle() == textselection.resource().handle()
+                    && other.begin() <= textselection.begin()
+                    && textselection.end() <= other.end()
+            })
+            .map(|other| other.begin())
+    };
+    if let Some(filter) = filter {
+        textselection
+            .find_annotations(
+                TextSelectionOperator::Embedded {
+                    all: false,
+                    negate: false,
+                },
+                annotation.store(),
+            )
+            .filter(|enclosing| enclosing.handle() != annotation.handle() && filter.test(enclosing))
+            .find_map(|enclosing| encloses(&enclosing))
+    } else {
+        annotation
+            .annotations(false, false)
+            .filter_map(|parent| {
+                parent
+                    .handle()
+                    .and_then(|handle| annotation.store().annotation(&Item::Handle(handle)))
+            })
+            .find_map(|parent| encloses(&parent))
+    }
+}
+
+/// Computes the nesting depth of an annotation, following AnnotationSelectors to the annotations it targets.
+/// An annotation that targets no other annotations has depth 0. Cycles are not followed.
+fn annotation_depth(annotation: &WrappedItem<Annotation>) -> usize {
+    fn depth(
+        annotation: &WrappedItem<Annotation>,
+        store: &AnnotationStore,
+        visited: &mut Vec<AnnotationHandle>,
+    ) -> usize {
+        let mut maxdepth = 0;
+        for parent in annotation.annotations(false, false) {
+            if let Some(handle) = parent.handle() {
+                if !visited.contains(&handle) {
+                    visited.push(handle);
+                    let parent = store
+                        .annotation(&Item::Handle(handle))
+                        .expect("annotation must exist");
+                    maxdepth = maxdepth.max(depth(&parent, store, visited) + 1);
+                    visited.pop();
+                }
+            }
+        }
+        maxdepth
+    }
+    let mut visited: Vec<AnnotationHandle> = annotation.handle().into_iter().collect();
+    depth(annotation, annotation.store(), &mut visited)
+}
+
+#[derive(Debug)]
+pub struct Columns(Vec<Column>);
+
+impl Columns {
+    /// Replaces the offset columns (Offset, BeginOffset, EndOffset) by their UTF-8 counterparts, if `utf8` is set
+    pub fn with_utf8_offsets(self, utf8: bool) -> Self {
+        if !utf8 {
+            return self;
+        }
+        Self(
+            self.0
+                .into_iter()
+                .map(|column| match column {
+                    Column::Offset => Column::Utf8Offset,
+                    Column::BeginOffset => Column::BeginUtf8Offset,
+                    Column::EndOffset => Column::EndUtf8Offset,
+                    column => column,
+                })
+                .collect(),
+        )
+    }
+
+    /// Replaces the UTF-8 offset columns (Utf8Offset, BeginUtf8Offset, EndUtf8Offset) by their counterparts in unicode
+    /// points, the reverse of [`Self::with_utf8_offsets()`], so exported files can be imported again. This requires `utf8`
+    /// to be set, as the offset columns of an import are interpreted as UTF-8 bytes only then.
+    pub fn without_utf8_offsets(self, utf8: bool) -> Result<Self, String> {
+        self.0
+            .into_iter()
+            .map(|column| match column {
+                Column::Utf8Offset | Column::BeginUtf8Offset | Column::EndUtf8Offset if !utf8 => {
+                    Err(format!(
+                        "Column {} holds UTF-8 byte offsets, importing it requires --utf8",
+                        column
+                    ))
+                }
+                Column::Utf8Offset => Ok(Column::Offset),
+                Column::BeginUtf8Offset => Ok(Column::BeginOffset),
+                Column::EndUtf8Offset => Ok(Column::EndOffset),
+                column => Ok(column),
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map(Self)
+    }
+
+    /// Sets the filter that selects the enclosing annotations for the relative offset columns
+    pub fn with_relative_to(self, filter: Option<&DataFilter>) -> Self {
+        if filter.is_none() {
+            return self;
+        }
+        Self(
+            self.0
+                .into_iter()
+                .map(|column| match column {
+                    Column::RelativeBeginOffset(_) => Column::RelativeBeginOffset(filter.cloned()),
+                    Column::RelativeEndOffset(_) => Column::RelativeEndOffset(filter.cloned()),
+                    column => column,
+                })
+                .collect(),
+        )
+    }
+
+    fn printrow(
+        &self,
+        tp: Type,
+        context: &Context,
+        delimiter: &str,
+        null: &str,
+        dialect: &Dialect,
+    ) {
+        let cells: Vec<String> = self
+            .0
+            .iter()
+            .map(|column| column.value(tp, context, delimiter, null))
+            .collect();
+        println!("{}", dialect.format_row(&cells));
+    }
+
+    fn printheader(&self, dialect: &Dialect) {
+        let cells: Vec<String> = self.0.iter().map(|column| column.to_string()).collect();
+        println!("{}", dialect.format_row(&cells));
+    }
+
+    fn index(&self, coltype: &Column) -> Option<usize> {
+        for (i, col) in self.0.iter().enumerate() {
+            if col == coltype {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn has(&self, coltype: &Column) -> bool {
+        self.index(coltype).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn iter(&self) -> std::slice::Iter<'_, Column> {
+        self.0.iter()
+    }
+}
+
+/// Writes the rows of an export job as TSV (or another dialect) to standard output, without sorting or grouping
+pub fn to_tsv(store: &AnnotationStore, job: &ExportJob) -> Result<(), String> {
+    let columns = job.parse_columns()?;
+
+    if job.header {
+        columns.printheader(&job.dialect);
+    }
+
+    export_rows(
+        store,
+        &columns,
+        job.tp,
+        job.flatten,
+        &job.selection,
+        &mut |tp, context| columns.printrow(tp, context, job.delimiter, job.null, &job.dialect),
+    );
+    Ok(())
+}
+
+/// Writes a header and rows as TSV (or another dialect)
+pub fn write_table(
+    writer: &mut impl Write,
+    header: Option<&[String]>,
+    rows: &[Row],
+    dialect: &Dialect,
+) -> Result<(), String> {
+    if let Some(header) = header {
+        writeln!(writer, "{}", dialect.format_row(header)).map_err(|e| format!("{}", e))?;
+    }
+    for row in rows {
+        writeln!(writer, "{}", dialect.format_row(&row.cells)).map_err(|e| format!("{}", e))?;
+    }
+    Ok(())
+}
+
+/// A single exported row, with all values already converted to strings
+pub struct Row {
+    pub tp: Type,
+    /// The resource the row pertains to (if any), the first one if there are multiple
+    pub resource: Option<String>,
+    pub cells: Vec<String>,
+}
+
+/// Like [`to_tsv()`], but returns the header and all rows rather than printing them. Used for other output formats.
+pub fn to_table(
+    store: &AnnotationStore,
+    job: &ExportJob,
+) -> Result<(Vec<String>, Vec<Row>), String> {
+    let columns = job.parse_columns()?;
+    let header = columns.iter().map(|column| column.to_string()).collect();
+    let mut rows = Vec::new();
+    export_rows(
+        store,
+        &columns,
+        job.tp,
+        job.flatten,
+        &job.selection,
+        &mut |tp, context| {
+            rows.push(Row {
+                tp,
+                resource: context.resource_id(),
+                cells: columns
+                    .iter()
+                    .map(|column| column.value(tp, context, job.delimiter, job.null))
+                    .collect(),
+            })
+        },
+    );
+    Ok((header, rows))
+}
+
+/// Writes annotations as JSON, mirroring the verbose TSV output as a tree: each annotation is an object with the
+/// values of the columns, its data (`data`, with the values of the columns for each data item) and the annotations it
+/// targets (`annotations`), recursively. The top level consists of the annotations that are not targeted by any other
+/// annotation. Values equal to `null` are output as JSON null.
+pub fn to_json_nested(
+    writer: &mut impl Write,
+    store: &AnnotationStore,
+    job: &ExportJob,
+) -> Result<(), String> {
+    let columns = job.parse_columns()?;
+    let want_textselections = columns.iter().any(|column| column.needs_textselections());
+    let object = |tp: Type, context: &Context| {
+        let mut object = serde_json::Map::new();
+        for column in columns.iter() {
+            let value = column.value(tp, context, job.delimiter, job.null);
+            object.insert(
+                column.to_string(),
+                if value == job.null {
+                    serde_json::Value::Null
+                } else {
+                    value.into()
+                },
+            );
+        }
+        object
+    };
+    fn nest(
+        annotation: &WrappedItem<Annotation>,
+        store: &AnnotationStore,
+        want_textselections: bool,
+        object: &dyn Fn(Type, &Context) -> serde_json::Map<String, serde_json::Value>,
+        visited: &mut Vec<AnnotationHandle>,
+    ) -> serde_json::Value {
+        let textselections: Option<Vec<_>> = if want_textselections {
+            Some(annotation.textselections().collect())
+        } else {
+            None
+        };
+        let context = Context {
+            id: annotation.id(),
+            annotation: Some(annotation.clone()),
+            textselections: textselections.as_ref(),
+            ..Context::default()
+        };
+        let mut result = object(Type::Annotation, &context);
+        let data: Vec<serde_json::Value> = annotation
+            .data()
+            .map(|data| {
+                let context = Context {
+                    id: data.id(),
+                    annotation: Some(annotation.clone()),
+                    key: Some(data.key()),
+                    data: Some(data.clone()),
+                    set: Some(data.set().wrap_in(store).unwrap()),
+                    value: Some(data.value()),
+                    ..Context::default()
+                };
+                object(Type::AnnotationData, &context).into()
+            })
+            .collect();
+        result.insert("data".to_string(), data.into());
+        let mut targets = Vec::new();
+        for target in annotation.annotations(false, false) {
+            if let Some(handle) = target.handle() {
+                //cycles are not followed
+                if !visited.contains(&handle) {
+                    visited.push(handle);
+                    let target = store
+                        .annotation(&Item::Handle(handle))
+                        .expect("annotation must exist");
+                    targets.push(nest(&target, store, want_textselections, object, visited));
+                    visited.pop();
+                }
+            }
+        }
+        if !targets.is_empty() {
+            result.insert("annotations".to_string(), targets.into());
+        }
+        result.into()
+    }
+    let toplevel: Vec<serde_json::Value> = store
+        .annotations()
+        .filter(|annotation| {
+            if job.selection.is_empty() {
+                annotation
+                    .annotations_reverse()
+                    .map(|mut children| children.next().is_none())
+                    .unwrap_or(true)
+            } else {
+                job.selection.test(annotation)
+            }
+        })
+        .map(|annotation| {
+            let mut visited = annotation.handle().into_iter().collect();
+            nest(
+                &annotation,
+                store,
+                want_textselections,
+                &object,
+                &mut visited,
+            )
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut *writer, &toplevel).map_err(|e| format!("{}", e))?;
+    writeln!(writer).map_err(|e| format!("{}", e))
+}
+
+/// Sorts rows by the values in the specified columns (in order of priority), which must be part of the column configuration.
+/// Values are compared numerically where possible. The sort is stable.
+pub fn sort_rows(
+    rows: &mut [Row],
+    columnconfig: &[&str],
+    setdelimiter: &str,
+    sort_by: &[&str],
+) -> Result<(), String> {
+    let indices = sort_by
+        .iter()
+        .map(|name| column_position(columnconfig, setdelimiter, name))
+        .collect::<Result<Vec<_>, String>>()?;
+    rows.sort_by(|a, b| {
+        indices
+            .iter()
+            .map(|i| compare_values(&a.cells[*i], &b.cells[*i]))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    Ok(())
+}
+
+/// Removes rows that duplicate an earlier row, keeping the first occurrence. If `distinct` columns are passed, rows
+/// are compared by the values in those columns only, otherwise by all values. The columns must be part of the column configuration.
+pub fn distinct_rows(
+    rows: Vec<Row>,
+    columnconfig: &[&str],
+    setdelimiter: &str,
+    distinct: &[&str],
+) -> Result<Vec<Row>, String> {
+    let indices = distinct
+        .iter()
+        .map(|name| column_position(columnconfig, setdelimiter, name))
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut seen: HashSet<Vec<String>> = HashSet::new();
+    Ok(rows
+        .into_iter()
+        .filter(|row| {
+            seen.insert(if indices.is_empty() {
+                row.cells.clone()
+            } else {
+                indices.iter().map(|i| row.cells[*i].clone()).collect()
+            })
+        })
+        .collect())
+}
+
+/// A group of rows, along with the value they have in common
+pub type RowGroup = (String, Vec<Row>);
+
+/// Groups rows by the value in the specified column, which must be part of the column configuration.
+/// Groups are returned in order of first appearance.
+pub fn group_rows(
+    rows: Vec<Row>,
+    columnconfig: &[&str],
+    setdelimiter: &str,
+    group_by: &str,
+) -> Result<Vec<RowGroup>, String> {
+    let index = column_position(columnconfig, setdelimiter, group_by)?;
+    let mut groups: Vec<RowGroup> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for row in rows {
+        let value = row.cells[index].clone();
+        if let Some(pos) = positions.get(&value) {
+            groups[*pos].1.push(row);
+        } else {
+            positions.insert(value.clone(), groups.len());
+            groups.push((value, vec![row]));
+        }
+    }
+    Ok(groups)
+}
+
+/// Returns the index of the named column in the column configuration
+fn column_position(columnconfig: &[&str], setdelimiter: &str, name: &str) -> Result<usize, String> {
+    let columns = parse_columns(columnconfig, setdelimiter)?;
+    let column = Column::parse(name, setdelimiter)?;
+    columns.index(&column).ok_or_else(|| {
+        format!(
+            "Column {} must also be one of the output columns (--columns)",
+            name
+        )
+    })
+}
+
+/// Compares two cell values, numerically if both are numbers. Values starting with a number (like offsets) are
+/// compared by that number first.
+fn compare_values(a: &str, b: &str) -> Ordering {
+    if let (Ok(x), Ok(y)) = (a.parse::<f64>(), b.parse::<f64>()) {
+        return x.partial_cmp(&y).unwrap_or(Ordering::Equal);
+    }
+    let leading_number = |s: &str| -> Option<(usize, usize)> {
+        let digits = s.chars().take_while(|c| c.is_ascii_digit()).count();
+        s[..digits].parse().ok().map(|n| (n, digits))
+    };
+    if let (Some((x, xlen)), Some((y, ylen))) = (leading_number(a), leading_number(b)) {
+        x.cmp(&y)
+            .then_with(|| compare_values(&a[xlen..], &b[ylen..]))
+    } else {
+        a.cmp(b)
+    }
+}
+
+/// Parses the column configuration for export
+pub fn parse_columns(columnconfig: &[&str], setdelimiter: &str) -> Result<Columns, String> {
+    Ok(Columns(
+        columnconfig
+            .iter()
+            .map(|col| Column::parse(col, setdelimiter))
+            .collect::<Result<Vec<_>, String>>()?,
+    ))
+}
+
+/// Produces all rows to export for the given type, `emit` is called for each row
+fn export_rows(
+    store: &AnnotationStore,
+    columns: &Columns,
+    tp: Type,
+    flatten: bool,
+    selection: &Selection,
+    emit: &mut dyn FnMut(Type, &Context),
+) {
+    match tp {
+        Type::Annotation => {
+            let want_textselections = columns.0.iter().any(|column| column.needs_textselections());
+            for annotation in store
+                .annotations()
+                .filter(|annotation| selection.test(annotation))
+            {
+                let textselections: Option<Vec<_>> = if want_textselections {
+                    Some(annotation.textselections().collect())
+                } else {
+                    None
+                };
+                if !flatten {
+                    let context = Context {
+                        id: annotation.id(),
+                        annotation: Some(annotation.clone()), //clones only the WrappedItem::Borrowed(), cheap
+                        textselections: textselections.as_ref(),
+                        ..Context::default()
+                    };
+                    emit(Type::Annotation, &context);
+                }
+                for data in annotation.data() {
+                    let context = Context {
+                        id: if flatten { annotation.id() } else { data.id() },
+                        annotation: Some(annotation.clone()),
+                        textselections: if flatten {
+                            textselections.as_ref()
+                        } else {
+                            None
+                        },
+                        key: Some(data.key()),
+                        data: Some(data.clone()),
+                        set: Some(data.set().wrap_in(store).unwrap()),
+                        value: Some(data.value()),
+                        ..Context::default()
+                    };
+                    emit(
+                        if flatten {
+                            Type::Annotation
+                        } else {
+                            Type::AnnotationData
+                        },
+                        &context,
+                    );
+                }
+            }
+        }
+        Type::AnnotationDataSet | Type::AnnotationData | Type::DataKey => {
+            for set in store.annotationsets() {
+                if !flatten || tp == Type::AnnotationDataSet {
+                    let context = Context {
+                        id: set.id(),
+                        set: Some(set.clone()),
+                        ..Context::default()
+                    };
+                    emit(Type::AnnotationDataSet, &context);
+                }
+                if tp == Type::AnnotationData {
+                    for data in set.data() {
+                        let context = Context {
+                            id: data.id(),
+                            set: Some(set.clone()),
+                            key: Some(data.key()),
+                            value: Some(data.value()),
+                            ..Context::default()
+                        };
+                        emit(Type::AnnotationData, &context);
+                    }
+                } else if tp == Type::DataKey {
+                    for key in set.keys() {
+                        let context = Context {
+                            id: key.id(),
+                            set: Some(set.clone()),
+                            key: Some(key.clone()),
+                            ..Context::default()
+                        };
+                        emit(Type::DataKey, &context);
+                    }
+                }
+            }
+        }
+        Type::TextResource | Type::TextSelection => {
+            for res in store.resources() {
+                if !flatten || tp == Type::TextResource {
+                    let context = Context {
+                        id: res.id(),
+                        resource: Some(res.clone()),
+                        text: if res.text().len() > 1024 || res.text().find("\n").is_some() {
+                            Some("[too long to display]")
+                        } else {
+                            Some(res.text())
+                        },
+                        ..Context::default()
+                    };
+                    emit(Type::TextResource, &context);
+                }
+                if tp == Type::TextSelection {
+                    for textselection in res.textselections() {
+                        let id = format!(
+                            "{}#{}-{}",
+                            res.id().unwrap_or(""),
+                            textselection.begin(),
+                            textselection.end()
+                        );
+                        let text = Some(textselection.text());
+                        let textselections = vec![textselection];
+                        let context = Context {
+                            id: Some(id.as_str()),
+                            resource: Some(res.clone()),
+                            textselections: Some(&textselections),
+                            text,
+                            ..Context::default()
+                        };
+                        emit(Type::TextSelection, &context);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseMode {
+    Simple,
+    /// Align with an existing text resource
+    AlignWithText,
+    /// Reconstruct a text resource from scratch
+    ReconstructText,
+    ///  Tag all occurrences
+    MultiTag,
+    /// Interpret the data as metadata on a resource
+    Metadata,
+}
+
+impl ParseMode {
+    pub fn new(
+        columns: &Columns,
+        existing_resource: Option<&str>,
+        sequential: bool,
+    ) -> Result<Self, &'static str> {
+        if columns.has(&Column::Text) {
+            if columns.has(&Column::Offset)
+                || (columns.has(&Column::BeginOffset) && columns.has(&Column::EndOffset))
+                || columns.has(&Column::TextSelection)
+            {
+                Ok(Self::Simple)
+            } else {
+                //no offset information
+                if columns.has(&Column::TextResource)
+                    || existing_resource.is_some()
+                    || columns.has(&Column::TextSelection)
+                {
+                    if sequential {
+                        Ok(Self::AlignWithText)
+                    } else {
+                        Ok(Self::MultiTag)
+                    }
+                } else {
+                    if sequential {
+                        Ok(Self::ReconstructText)
+                    } else {
+                        Err("Can not reconstruct a text if rows in input data are not sequential")
+                    }
+                }
+            }
+        } else if !columns.has(&Column::Offset)
+            && !columns.has(&Column::BeginOffset)
+            && !columns.has(&Column::EndOffset)
+            && !columns.has(&Column::TextSelection)
+        {
+            if columns.has(&Column::TextResource) || existing_resource.is_some() {
+                eprintln!("Warning: Data has neither a Text nor an Offset column, interpreting data as metadata");
+                Ok(Self::Metadata)
+            } else {
+                Err("Data has neither a Text nor an Offset column")
+            }
+        } else {
+            Err("Unable to determine how to parse this data based on the available columns. Make sure there is at least an Offset or Text column")
+        }
+    }
+}
+
+/// Imports a TSV file (or another dialect) into the store, with the options of the import job. Data from joined
+/// files is taken from `joins` as the annotations are added.
+pub fn from_tsv(
+    store: &mut AnnotationStore,
+    filename: &str,
+    job: &ImportJob,
+    joins: &mut JoinData,
+) -> Result<(), String> {
+    let ImportJob {
+        columns: ref columnconfig,
+        existing_resource,
+        new_resource,
+        default_set,
+        ref groups,
+        comments,
+        sequential,
+        escape,
+        null: nullvalue,
+        setdelimiter,
+        outputdelimiter,  //outputted after each row when reconstructing text (space)
+        outputdelimiter2, //outputted after each empty line when reconstructing text (newline)
+        header,           //None means autodetect
+        utf8_offsets,     //offsets are in UTF-8 bytes rather than unicode points
+        ref dialect,
+        verbose,
+        progress, //show a progress indicator
+        ..
+    } = *job;
+    let filesize = std::fs::metadata(filename)
+        .map(|m| m.len() as usize)
+        .unwrap_or(0);
+    let mut progress = Progress::new(format!("Reading {}", filename), filesize, progress);
+    let lines = dialect.read_lines(filename)?;
+
+    let mut columns: Option<Columns> = None;
+    let mut parsemode: Option<ParseMode> = None;
+    let mut cursors: HashMap<TextResourceHandle, usize> = HashMap::new(); //used in AlignWithText mode to keep track of the begin of text offset (per resource)
+    let mut buffer: Vec<String> = Vec::new(); //used in ReconstructText mode for a second pass over the data
+    let mut bufferbegin: usize = 0; //line number where the buffer begins
+    let mut texts: HashMap<String, String> = HashMap::new(); //used in ReconstructText mode
+    let mut buffered_delimiter: Option<String> = None; // used in ReconstructText mode
+    let mut members = GroupMembers::default();
+
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        progress.inc(line.len() + 1);
+        if line.is_empty() {
+            buffered_delimiter = Some(outputdelimiter2.to_string()); //only affects ReconstructText mode
+        } else if comments && !line.is_empty() && line.get(0..1) == Some("#") {
+            //this is a comment, ignore
+            continue;
+        } else if i == 0 && columns.is_none() && header != Some(false) {
+            if verbose {
+                eprintln!("Parsing first row as header...")
+            }
+            columns = Some(Columns(
+                line.split("\t")
+                    .map(|col| {
+                        parse_column(col, default_set, setdelimiter).map_err(|err| {
+                            format!("Unable to parse first line of TSV file as header (please provide a column configuration explicitly if the input file has none): {}. You may consider setting --annotationset if you want to interpret this column as a key in the specified annotationset", err)
+                        })
+                    })
+                    .collect::<Result<Vec<_>, String>>()?,
+            )
+            .without_utf8_offsets(utf8_offsets)?);
+            set_group_columns(columns.as_mut().unwrap(), groups, default_set, setdelimiter)?;
+            parsemode = Some(
+                ParseMode::new(columns.as_ref().unwrap(), existing_resource, sequential)
+                    .map_err(|e| format!("Can't determine parse mode: {}", e))?,
+            );
+            if verbose {
+                eprintln!("Columns: {:?}", columns.as_ref().unwrap());
+                eprintln!("Parse mode: {:?}", parsemode.unwrap());
+            }
+        } else if i == 0 && columns.is_some() && header != Some(false) {
+            if verbose {
+                eprintln!("Skipping first row (assuming to be a header)...")
+            }
+            continue; //skip header row
+        } else {
+            if columns.is_none() {
+                let columnconfig = columnconfig
+                    .as_ref()
+                    .ok_or("Please provide a configuration for the columns")?;
+                columns = Some(
+                    Columns(
+                        columnconfig
+                            .iter()
+                            .map(|col| {
+                                parse_column(col, default_set, setdelimiter).map_err(|err| {
+                                    format!("Unable to parse provided column: {}", err)
+                                })
+                            })
+                            .collect::<Result<Vec<_>, String>>()?,
+                    )
+                    .without_utf8_offsets(utf8_offsets)?,
+                );
+                set_group_columns(columns.as_mut().unwrap(), groups, default_set, setdelimiter)?;
+                parsemode = Some(
+                    ParseMode::new(columns.as_ref().unwrap(), existing_resource, sequential)
+                        .map_err(|e| format!("Can't determine parse mode: {}", e))?,
+                );
+                if verbose {
+                    eprintln!("Columns: {:?}", columns.as_ref().unwrap());
+                    eprintln!("Parse mode: {:?}", parsemode.unwrap())
+                }
+            }
+            if let (Some(columns), Some(parsemode)) = (&columns, parsemode) {
+                if parsemode == ParseMode::ReconstructText {
+                    if let Err(e) = reconstruct_text(
+                        &line,
+                        columns,
+                        &mut texts,
+                        existing_resource,
+                        new_resource,
+                        outputdelimiter,
+                        &mut buffered_delimiter,
+                    ) {
+                        return Err(format!("Error reconstructing text (line {}): {}", i + 1, e));
+                    }
+                    if buffer.is_empty() {
+                        bufferbegin = i;
+                    }
+                    buffer.push(line);
+                } else {
+                    match parse_row(store, &line, columns, parsemode, job, joins, &mut cursors) {
+                        Ok(handle) => members.add(&line, columns, nullvalue, handle),
+                        Err(e) => return Err(format!("Error parsing tsv line {}: {}", i + 1, e)),
+                    }
+                }
+            }
+        }
+    }
+
+    progress.finish();
+
+    if parsemode == Some(ParseMode::ReconstructText) {
+        if verbose {
+            eprintln!("Creating resources...");
+        }
+        for (filename, text) in texts {
+            if verbose {
+                eprintln!("Creating resource {} (length={})", filename, text.len());
+            }
+            let resource = TextResource::new(filename.clone(), Config::default())
+                .with_string(text)
+                .with_filename(&filename);
+            if let Err(e) = store.insert(resource) {
+                return Err(format!("Error adding reconstructed text to store: {}", e));
+            }
+        }
+        if verbose {
+            eprintln!("Parsing buffered rows...");
+        }
+        let parsemode = ParseMode::AlignWithText;
+        let columns = columns.unwrap();
+        let mut progress = Progress::new("Parsing rows", buffer.len(), progress.enabled());
+        for (i, line) in buffer.iter().enumerate() {
+            progress.inc(1);
+            match parse_row(store, line, &columns, parsemode, job, joins, &mut cursors) {
+                Ok(handle) => members.add(line, &columns, nullvalue, handle),
+                Err(e) => {
+                    return Err(format!(
+                        "Error parsing tsv line {}: {}",
+                        i + bufferbegin + 1,
+                        e
+                    ))
+                }
+            }
+        }
+        progress.finish();
+    }
+    let count = members.annotate(store, escape)?;
+    if verbose && count > 0 {
+        eprintln!("Added {} group annotation(s)", count);
+    }
+    Ok(())
+}
+
+/// Marks the columns passed via `--group` as grouping columns
+fn set_group_columns(
+    columns: &mut Columns,
+    groups: &[&str],
+    default_set: Option<&str>,
+    setdelimiter: &str,
+) -> Result<(), String> {
+    for group in groups {
+        let group = parse_column(group, default_set, setdelimiter)?;
+        let column = columns
+            .0
+            .iter_mut()
+            .find(|column| **column == group)
+            .ok_or_else(|| format!("Grouping column {} not found", group))?;
+        match column {
+            Column::Custom { set, key } => {
+                *column = Column::Group {
+                    set: set.clone(),
+                    key: key.clone(),
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Grouping column {} must be a custom column (set/key)",
+                    group
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The annotations of the rows per grouping column and value, the values in order of first appearance
+#[derive(Debug, Default)]
+struct GroupMembers {
+    groups: Vec<((String, String, String), Vec<AnnotationHandle>)>,
+    index: HashMap<(String, String, String), usize>,
+}
+
+impl GroupMembers {
+    fn add(&mut self, line: &str, columns: &Columns, nullvalue: &str, handle: AnnotationHandle) {
+        for (column, cell) in columns.iter().zip(line.split('\t')) {
+            if let Column::Group { set, key } = column {
+                if cell.is_empty() || cell == nullvalue {
+                    continue;
+                }
+                let group = (set.clone(), key.clone(), cell.to_string());
+                let i = *self.index.entry(group.clone()).or_insert_with(|| {
+                    self.groups.push((group, Vec::new()));
+                    self.groups.len() - 1
+                });
+                self.groups[i].1.push(handle);
+            }
+        }
+    }
+
+    /// Adds the group annotations to the store, returns how many were added
+    fn annotate(self, store: &mut AnnotationStore, escape: bool) -> Result<usize, String> {
+        let count = self.groups.len();
+        for ((set, key, value), handles) in self.groups {
+            let value: DataValue = if escape {
+                unescape(&value).into()
+            } else {
+                value.into()
+            };
+            store
+                .annotate(
+                    AnnotationBuilder::new()
+                        .with_selector(Selector::CompositeSelector(
+                            handles
+                                .into_iter()
+                                .map(|handle| Selector::AnnotationSelector(handle, None))
+                                .collect(),
+                        ))
+                        .with_data_builder(
+                            AnnotationDataBuilder::new()
+                                .with_annotationset(Item::Id(set))
+                                .with_key(Item::Id(key))
+                                .with_value(value),
+                        ),
+                )
+                .map_err(|e| format!("Error adding group annotation: {}", e))?;
+        }
+        Ok(count)
+    }
+}
+
+pub fn reconstruct_text(
+    line: &str,
+    columns: &Columns,
+    texts: &mut HashMap<String, String>,
+    existing_resource: Option<&str>,
+    new_resource: Option<&str>,
+    output_delimiter: &str,
+    buffered_delimiter: &mut Option<String>,
+) -> Result<(), String> {
+    let cells: Vec<&str> = line.split("\t").collect();
+    if cells.len() != columns.len() {
+        return Err(format!(
+            "Number of cells is not equal to number of columns in header ({} vs {})",
+            cells.len(),
+            columns.len()
+        ));
+    }
+    let resource_file: &str =
+        parse_resource_file(&cells, columns, existing_resource, new_resource)?;
+    let textcolumn = columns.index(&Column::Text);
+    if !texts.contains_key(resource_file) {
+        texts.insert(resource_file.to_string(), String::new());
+    }
+    if let Some(text) = texts.get_mut(resource_file) {
+        if let Some(buffered_delimiter) = buffered_delimiter {
+            text.push_str(buffered_delimiter);
+        }
+        text.push_str(cells[textcolumn.expect("there must be a text column")]);
+        *buffered_delimiter = Some(output_delimiter.to_string());
+    }
+    Ok(())
+}
+
+/// Parses a single row and adds the annotation to the store
+pub fn parse_row(
+    store: &mut AnnotationStore,
+    line: &str,
+    columns: &Columns,
+    parsemode: ParseMode,
+    job: &ImportJob,
+    joins: &mut JoinData,
+    cursors: &mut HashMap<TextResourceHandle, usize>,
+) -> Result<AnnotationHandle, String> {
+    let cells: Vec<&str> = line.split("\t").collect();
+    if cells.len() != columns.len() {
+        return Err(format!(
+            "Number of cells is not equal to number of columns in header ({} vs {})",
+            cells.len(),
+            columns.len()
+        ));
+    }
+    let resource_file: &str =
+        parse_resource_file(&cells, columns, job.existing_resource, job.new_resource)?;
+    let resource_handle: TextResourceHandle = get_resource_handle(store, resource_file)?;
+    let textcolumn = columns.index(&Column::Text);
+    let selector = match parsemode {
+        ParseMode::Simple if job.utf8_offsets => {
+            match build_selector(&cells, columns, resource_handle)? {
+                Selector::TextSelector(resource_handle, offset) => {
+                    let resource = store
+                        .resource(&Item::Handle(resource_handle))
+                        .expect("resource must exist");
+                    Selector::TextSelector(resource_handle, offset_from_utf8(&resource, &offset)?)
+                }
+                selector => selector,
+            }
+        }
+        ParseMode::Simple => build_selector(&cells, columns, resource_handle)?,
+        ParseMode::AlignWithText => align_with_text(
+            store,
+            resource_handle,
+            &cells,
+            textcolumn.expect("text column is required when parsemode is set to AlignWithText"),
+            columns.index(&Column::OffsetHint),
+            job,
+            cursors,
+        )?,
+        _ => return Err("Not implemented yet".to_string()),
+    };
+    let mut annotationbuilder = build_annotation(
+        &cells,
+        columns,
+        job.default_set,
+        job.subdelimiter,
+        job.escape,
+        job.null,
+    )?;
+    annotationbuilder = annotationbuilder.with_selector(selector);
+    if let Some(id) = columns
+        .index(&Column::Id)
+        .or_else(|| columns.index(&Column::Annotation))
+        .and_then(|i| cells.get(i))
+    {
+        for databuilder in joins.take(id) {
+            annotationbuilder = annotationbuilder.with_data_builder(databuilder);
+        }
+    }
+    match store.annotate(annotationbuilder) {
+        Err(e) => Err(format!("{}", e)),
+        Ok(handle) => {
+            if parsemode == ParseMode::Simple {
+                if let Some(textcolumn) = textcolumn {
+                    validate_text(store, handle, &cells, textcolumn, job.validation)?;
+                }
+            }
+            Ok(handle)
+        }
+    }
+}
+
+/// Finds the text in the cell in the resource, searching from the end of the previous match (the cursor).
+/// The search is first restricted to a window after the cursor or, if there is a hint column, around the hinted offset,
+/// and only if that fails the entire remainder of the text is searched. With a hint, the match nearest to it is chosen.
+pub fn align_with_text(
+    store: &AnnotationStore,
+    resource_handle: TextResourceHandle,
+    cells: &[&str],
+    textcolumn: usize,
+    hintcolumn: Option<usize>,
+    job: &ImportJob,
+    cursors: &mut HashMap<TextResourceHandle, usize>,
+) -> Result<Selector, String> {
+    let textfragment = cells[textcolumn];
+    if textfragment.is_empty() {
+        return Err("Value in text column can not be empty".to_string());
+    }
+    let hint: Option<usize> = if let Some(hintcolumn) = hintcolumn {
+        match cells[hintcolumn] {
+            "" => None,
+            cell => Some(
+                cell.parse()
+                    .map_err(|e| format!("Invalid offset hint '{}': {}", cell, e))?,
+            ),
+        }
+    } else {
+        None
+    };
+    let cursor = cursors.entry(resource_handle).or_insert(0);
+    let resource = store
+        .resource(&Item::from(resource_handle))
+        .expect("resource must exist");
+    let textlen = resource.textlen();
+    let fragmentlen = textfragment.chars().count();
+    let find = |begin: usize, end: usize| -> Result<Option<(usize, usize)>, String> {
+        let searchtext = resource
+            .textselection(&Offset::simple(begin, end))
+            .map_err(|e| format!("{}", e))?;
+        let mut matches: Box<dyn Iterator<Item = (usize, usize)>> = if job.case_sensitive {
+            Box::new(
+                searchtext
+                    .find_text(textfragment)
+                    .map(|textselection| (textselection.begin(), textselection.end())),
+            )
+        } else {
+            Box::new(
+                searchtext
+                    .find_text_nocase(textfragment)
+                    .map(|textselection| (textselection.begin(), textselection.end())),
+            )
+        };
+        //with a hint, the match nearest to the hinted offset wins, otherwise the first one
+        Ok(match hint {
+            Some(hint) => matches.min_by_key(|(begin, _)| begin.abs_diff(hint)),
+            None => matches.next(),
+        })
+    };
+    //first search within the window
+    let windowbegin = match hint {
+        Some(hint) => hint.saturating_sub(job.align_window).max(*cursor),
+        None => *cursor,
+    };
+    let windowend = (match hint {
+        Some(hint) => hint,
+        None => *cursor,
+    } + job.align_window
+        + fragmentlen)
+        .min(textlen);
+    let mut found = None;
+    if windowbegin < windowend {
+        found = find(windowbegin, windowend)?;
+    }
+    if found.is_none() && (windowbegin > *cursor || windowend < textlen) && *cursor < textlen {
+        //fall back to searching the entire remainder of the text
+        found = find(*cursor, textlen)?;
+    }
+    if let Some((begin, end)) = found {
+        *cursor = end;
+        Ok(Selector::TextSelector(
+            resource_handle,
+            Offset::simple(begin, end),
+        ))
+    } else {
+        Err(format!(
+            "Unable to align specified text with the underlying resource: '{}' (lost track after character position {})",
+            textfragment,
+            *cursor
+        ))
+    }
+}
+
+pub fn validate_text(
+    store: &AnnotationStore,
+    annotation_handle: AnnotationHandle,
+    cells: &[&str],
+    textcolumn: usize,
+    validation: ValidationMode,
+) -> Result<(), String> {
+    if validation == ValidationMode::No {
+        return Ok(());
+    }
+    if let Some(annotation) = store.annotation(&Item::from(annotation_handle)) {
+        let text: Vec<&str> = annotation.text().collect();
+        if text.is_empty() {
+            return Err("No text found".to_string());
+        } else if text.len() == 1 {
+            if !match validation {
+                ValidationMode::Strict => {
+                    &text[0] == cells.get(textcolumn).expect("cell must exist")
+                }
+                ValidationMode::Loose => {
+                    text[0].to_lowercase()
+                        == cells
+                            .get(textcolumn)
+                            .expect("cell must exist")
+                            .to_lowercase()
+                }
+                ValidationMode::No => true,
+            } {
+                return Err(format!(
+                    "Text validation failed, TSV expects '{}', data has '{}'",
+                    cells.get(textcolumn).unwrap(),
+                    &text[0]
+                ));
+            }
+        } else {
+            let text: String = text.join(" ");
+            if !match validation {
+                ValidationMode::Strict => {
+                    &text.as_str() == cells.get(textcolumn).expect("cell must exist")
+                }
+                ValidationMode::Loose => {
+                    text.to_lowercase()
+                        == cells
+                            .get(textcolumn)
+                            .expect("cell must exist")
+                            .to_lowercase()
+                }
+                ValidationMode::No => true,
+            } {
+                return Err(format!(
+                    "Text validation failed, TSV expects '{}', data has '{}'",
+                    cells.get(textcolumn).unwrap(),
+                    &text.as_str()
+                ));
+            }
+        }
+    } else {
+        return Err("Annotation not found (should never happen)".to_string());
+    }
+    Ok(())
+}
+
+pub fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some(c) => {
+                    result.push('\\');
+                    result.push(c);
+                }
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c)
+        }
+    }
+    result
+}
+
+pub fn build_annotation<'a>(
+    cells: &'a [&'a str],
+    columns: &Columns,
+    default_set: Option<&'a str>,
+    subdelimiter: &str,
+    escape: bool,
+    nullvalue: &str,
+) -> Result<AnnotationBuilder<'a>, String> {
+    let mut annotationbuilder = AnnotationBuilder::new();
+    if let Some(i) = columns.index(&Column::Id) {
+        let id = cells.get(i).expect("cell must exist");
+        annotationbuilder = annotationbuilder.with_id(id.to_string());
+    } else if let Some(i) = columns.index(&Column::Annotation) {
+        //same as above
+        let id = cells.get(i).expect("cell must exist");
+        annotationbuilder = annotationbuilder.with_id(id.to_string());
+    } else if let (Some(ikey), Some(ivalue)) = (
+        columns.index(&Column::DataKey),
+        columns.index(&Column::DataValue),
+    ) {
+        let mut databuilder = AnnotationDataBuilder::new();
+        if let Some(i) = columns.index(&Column::AnnotationData) {
+            let id = cells.get(i).expect("cell must exist");
+            databuilder = databuilder.with_id(Item::IdRef(id));
+        } else if let Some(default_set) = default_set {
+            databuilder = databuilder.with_id(Item::IdRef(default_set));
+        }
+        if let Some(i) = columns.index(&Column::AnnotationDataSet) {
+            let set = cells.get(i).expect("cell must exist");
+            databuilder = databuilder.with_annotationset(Item::Id(set.to_string()));
+        }
+        let key = cells.get(ikey).expect("cell must exist");
+        let value = cells.get(ivalue).expect("cell must exist");
+        if !value.is_empty() && *value != nullvalue {
+            if value.find(subdelimiter).is_some() {
+                for value in value.split(subdelimiter) {
+                    let mut multidatabuilder = AnnotationDataBuilder::new();
+                    if let Some(i) = columns.index(&Column::AnnotationDataSet) {
+                        let set = cells.get(i).expect("cell must exist");
+                        multidatabuilder =
+                            multidatabuilder.with_annotationset(Item::Id(set.to_string()));
+                    }
+                    multidatabuilder = multidatabuilder.with_key(Item::from(*key));
+                    if escape {
+                        multidatabuilder =
+                            multidatabuilder.with_value(DataValue::from(unescape(value)));
+                    } else {
+                        multidatabuilder = multidatabuilder.with_value(DataValue::from(value));
+                    }
+                    annotationbuilder = annotationbuilder.with_data_builder(multidatabuilder);
+                }
+            } else {
+                databuilder = databuilder.with_key(Item::from(*key));
+                if escape {
+                    databuilder = databuilder.with_value(DataValue::from(unescape(value)));
+                } else {
+                    databuilder = databuilder.with_value(DataValue::from(*value));
+                }
+                annotationbuilder = annotationbuilder.with_data_builder(databuilder);
+            }
+        }
+    }
+    //process custom columns
+    for (column, cell) in columns.iter().zip(cells.iter()) {
+        if let Column::Custom { set, key } = column {
+            if cell.find(subdelimiter).is_some() {
+                for value in cell.split(subdelimiter) {
+                    let value: DataValue = if escape {
+                        unescape(value).into()
+                    } else {
+                        value.into()
+                    };
+                    let databuilder = AnnotationDataBuilder::new()
+                        .with_annotationset(Item::Id(set.clone()))
+                        .with_key(Item::Id(key.clone()))
+                        .with_value(value);
+                    annotationbuilder = annotationbuilder.with_data_builder(databuilder);
+                }
+            } else {
+                let value: DataValue = if escape {
+                    unescape(cell).into()
+                } else {
+                    (*cell).into()
+                };
+                let databuilder = AnnotationDataBuilder::new()
+                    .with_annotationset(Item::Id(set.clone()))
+                    .with_key(Item::Id(key.clone()))
+                    .with_value(value);
+                annotationbuilder = annotationbuilder.with_data_builder(databuilder);
+            }
+        }
+    }
+    Ok(annotationbuilder)
+}
+
+/// Extra data to add to annotations, by annotation ID, loaded from secondary TSV files (`--join`)
+#[derive(Debug, Default)]
+pub struct JoinData {
+    /// Maps annotation IDs to (set, key, value) triples
+    data: HashMap<String, Vec<(String, String, String)>>,
+}
+
+impl JoinData {
+    /// Loads a secondary TSV file. `spec` takes the form `filename:column`, where the column (by default `Id`) is the
+    /// name of the header column holding the annotation IDs, see [`parse_join_spec()`]. All other columns must be custom
+    /// columns (set/key), or keys in the default set. The file is read with the annotation set, delimiters, escaping, null
+    /// value, comments and dialect of the import job.
+    pub fn load(&mut self, spec: &str, job: &ImportJob) -> Result<(), String> {
+        let ImportJob {
+            default_set,
+            setdelimiter,
+            subdelimiter,
+            comments,
+            escape,
+            null: nullvalue,
+            ref dialect,
+            ..
+        } = *job;
+        let (filename, idcolumn) = parse_join_spec(spec);
+        let filename = filename.as_str();
+        let mut lines = dialect.read_lines(filename)?;
+        let header = lines
+            .next()
+            .ok_or_else(|| format!("Join file {} is empty", filename))??;
+        let headercells: Vec<&str> = header.split('\t').collect();
+        let idindex = headercells
+            .iter()
+            .position(|cell| *cell == idcolumn)
+            .ok_or_else(|| format!("Join file {} has no column {}", filename, idcolumn))?;
+        let mut columns: Vec<Option<(String, String)>> = Vec::new();
+        for (i, cell) in headercells.iter().enumerate() {
+            if i == idindex {
+                columns.push(None);
+                continue;
+            }
+            match parse_column(cell, default_set, setdelimiter) {
+                Ok(Column::Custom { set, key }) => columns.push(Some((set, key))),
+                Ok(Column::Ignore) => columns.push(None),
+                _ => {
+                    return Err(format!(
+                        "Join file {}: column {} must be a custom column (set{}key), or set --annotationset",
+                        filename, cell, setdelimiter
+                    ))
+                }
+            }
+        }
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() || (comments && line.starts_with('#')) {
+                continue;
+            }
+            let cells: Vec<&str> = line.split('\t').collect();
+            if cells.len() != columns.len() {
+                return Err(format!(
+                    "Join file {} line {}: number of cells is not equal to number of columns in header ({} vs {})",
+                    filename,
+                    i + 2,
+                    cells.len(),
+                    columns.len()
+                ));
+            }
+            let entry = self.data.entry(cells[idindex].to_string()).or_default();
+            for (column, cell) in columns.iter().zip(cells.iter()) {
+                if let Some((set, key)) = column {
+                    if cell.is_empty() || *cell == nullvalue {
+                        continue;
+                    }
+                    for value in cell.split(subdelimiter) {
+                        let value = if escape {
+                            unescape(value)
+                        } else {
+                            value.to_string()
+                        };
+                        entry.push((set.clone(), key.clone(), value));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the IDs of all annotations that have not been joined yet
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.data.keys().map(|x| x.as_str())
+    }
+
+    /// Removes and returns the data for the given annotation ID
+    pub fn take_raw(&mut self, id: &str) -> Vec<(String, String, String)> {
+        self.data.remove(id).unwrap_or_default()
+    }
+
+    /// Removes and returns the data for the given annotation ID, as data builders
+    pub fn take<'a>(&mut self, id: &str) -> Vec<AnnotationDataBuilder<'a>> {
+        self.take_raw(id)
+            .into_iter()
+            .map(|(set, key, value)| {
+                AnnotationDataBuilder::new()
+                    .with_annotationset(Item::Id(set))
+                    .with_key(Item::Id(key))
+                    .with_value(value.into())
+            })
+            .collect()
+    }
+}
+
+/// Splits the specification of a join file (`filename:column`) at the first colon that is not escaped by a backslash.
+/// The column may itself contain colons (as in URIs), a colon in the filename must be written as `\:`. If the column is
+/// omitted, it is `Id`. Returns the (unescaped) filename and the column.
+pub fn parse_join_spec(spec: &str) -> (String, &str) {
+    let mut filename = String::with_capacity(spec.len());
+    let mut chars = spec.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if chars.peek().map(|(_, c)| *c) == Some(':') => {
+                filename.push(':');
+                chars.next();
+            }
+            ':' if i + 1 < spec.len() => return (filename, &spec[i + 1..]),
+            ':' => break,
+            c => filename.push(c),
+        }
+    }
+    (filename, "Id")
+}
+
+/// What the data of join files was added to, see [`join_existing()`] and [`crate::pipeline::ImportJob::run()`]
+#[derive(Debug, Default)]
+pub struct JoinReport {
+    /// Number of annotations already in the store that received joined data
+    pub annotations: usize,
+    /// IDs in the joined data that did not match any annotation
+    pub unmatched: Vec<String>,
+    /// Changed sets that keep their original standoff file, as (ID, filename); these files must be rewritten
+    pub setfiles: Vec<(String, String)>,
+}
+
+/// Adds joined data to annotations that already exist in the store. The store has to be rebuilt for this.
+/// Returns the new store (`None` if no annotations were affected) and a report, the IDs that remain unmatched are
+/// not part of it as they may still match annotations that are imported later.
+pub fn join_existing(
+    store: &AnnotationStore,
+    joins: &mut JoinData,
+) -> Result<(Option<AnnotationStore>, JoinReport), String> {
+    let mut report = JoinReport::default();
+    if !store
+        .annotations()
+        .any(|annotation| annotation.id().map(|id| joins.data.contains_key(id)) == Some(true))
+    {
+        return Ok((None, report));
+    }
+    let mut storejson = StoreJson::from_store(store)?;
+    let mut changedsets = BTreeSet::new();
+    for annotation in storejson.annotations.iter_mut() {
+        let id = annotation
+            .get("@id")
+            .and_then(|x| x.as_str())
+            .unwrap_or("")
+            .to_string();
+        let newdata = joins.take_raw(&id);
+        if !newdata.is_empty() {
+            report.annotations += 1;
+            if let Some(serde_json::Value::Array(data)) = annotation.get_mut("data") {
+                for (set, key, value) in newdata {
+                    data.push(serde_json::json!({
+                        "@type": "AnnotationData",
+                        "set": set,
+                        "key": key,
+                        "value": { "@type": "String", "value": value }
+                    }));
+                    changedsets.insert(set);
+                }
+            }
+        }
+    }
+    //changed sets keep their original standoff file, which must be rewritten
+    for set_id in changedsets {
+        if let Some(filename) = storejson
+            .annotationset_filename(&set_id)
+            .map(|x| x.to_string())
+        {
+            storejson.set_annotationset_filename(&set_id, &filename);
+            report.setfiles.push((set_id, filename));
+        }
+    }
+    Ok((Some(storejson.into_store(store)?), report))
+}
+
+pub fn parse_resource_file<'a>(
+    cells: &[&'a str],
+    columns: &Columns,
+    existing_resource: Option<&'a str>,
+    new_resource: Option<&'a str>,
+) -> Result<&'a str, String> {
+    if let Some(i) = columns.index(&Column::TextResource) {
+        Ok(cells.get(i).expect("cell must exist"))
+    } else if let Some(i) = columns.index(&Column::TextSelection) {
+        let textselection = cells.get(i).expect("cell must exist");
+        if let Some(bytepos) = textselection.find('#') {
+            Ok(&textselection[..bytepos])
+        } else {
+            Err("Text selection must have format: resource#beginoffset-endoffset".to_string())
+        }
+    } else if let Some(existing_resource) = existing_resource {
+        Ok(existing_resource)
+    } else if let Some(new_resource) = new_resource {
+        Ok(new_resource)
+    } else {
+        Err(
+            "Can't find resource (data doesn't make an explicit reference to it). You may want to specify a default (existing) resource using --resource"
+                .to_string(),
+        )
+    }
+}
+
+pub fn get_resource_handle(
+    store: &mut AnnotationStore,
+    filename: &str,
+) -> Result<TextResourceHandle, String> {
+    if let Some(resource) = store.resource(&Item::from(filename)) {
+        if let Some(handle) = resource.handle() {
+            return Ok(handle);
+        }
+    }
+    store
+        .add_resource_from_file(filename)
+        .map_err(|e| format!("Specified resource not found: {}: {}", filename, e))
+}
+
+pub fn build_selector(
+    cells: &[&str],
+    columns: &Columns,
+    resource_handle: TextResourceHandle,
+) -> Result<Selector, String> {
+    //TODO: for now this only returns a TextSelector, should be adapted to handle multiple offsets (with subdelimiter) and return a CompositeSelector then
+    let offset = parse_offset(cells, columns)?;
+    Ok(Selector::TextSelector(resource_handle, offset))
+}
+
+pub fn parse_offset(cells: &[&str], columns: &Columns) -> Result<Offset, String> {
+    if let Some(ioffset) = columns.index(&Column::Offset) {
+        let cell = cells.get(ioffset).expect("cell must exist");
+        if let Some(delimiterpos) = &cell[1..].find('-') {
+            let delimiterpos = *delimiterpos + 1; //we do 1 rather than 0 to not consider an immediate hyphen after the # , that would indicate a negative begin index
+            let begin_str = &cell[0..delimiterpos];
+            let end_str = &cell[(delimiterpos + 1)..];
+            let begin: Cursor = begin_str.try_into().map_err(|e| format!("{}", e))?;
+            let end: Cursor = end_str.try_into().map_err(|e| format!("{}", e))?;
+            return Ok(Offset::new(begin, end));
+        }
+        Err("Offset must have format: beginoffset-endoffset".to_string())
+    } else if let (Some(b), Some(e)) = (
+        columns.index(&Column::BeginOffset),
+        columns.index(&Column::EndOffset),
+    ) {
+        let begin_str = cells.get(b).expect("cell must exist");
+        let end_str = cells.get(e).expect("cell must exist");
+        let begin: Cursor = (*begin_str).try_into().map_err(|e| format!("{}", e))?;
+        let end: Cursor = (*end_str).try_into().map_err(|e| format!("{}", e))?;
+        Ok(Offset::new(begin, end))
+    } else if let Some(i) = columns.index(&Column::TextSelection) {
+        let textselection = cells.get(i).expect("cell must exist");
+        if let Some(bytepos) = textselection.find('#') {
+            if let Some(delimiterpos) = &textselection[(bytepos + 2)..].find('-') {
+                let delimiterpos = *delimiterpos + bytepos + 2; //we do 2 rather than 1 to not consider an immediate hyphen after the # , that would indicate a negative begin index
+                let begin_str = &textselection[(bytepos + 1)..delimiterpos];
+                let end_str = &textselection[(delimiterpos + 1)..];
+                let begin: Cursor = begin_str.try_into().map_err(|e| format!("{}", e))?;
+                let end: Cursor = end_str.try_into().map_err(|e| format!("{}", e))?;
+                return Ok(Offset::new(begin, end));
+            }
+        }
+        Err("Text selection must have format: resource#beginoffset-endoffset".to_string())
+    } else {
+        Err("No offset information found".to_string())
+    }
+}
+
+/// Converts an offset in UTF-8 bytes to an offset in unicode points, the offsets must fall on character boundaries
+pub fn offset_from_utf8(
+    resource: &WrappedItem<TextResource>,
+    offset: &Offset,
+) -> Result<Offset, String> {
+    let convert = |cursor: &Cursor| -> Result<Cursor, String> {
+        let error = |e: StamError| format!("Invalid UTF-8 offset {}: {}", cursor, e);
+        match *cursor {
+            Cursor::BeginAligned(bytepos) => Ok(Cursor::BeginAligned(
+                resource.utf8byte_to_charpos(bytepos).map_err(error)?,
+            )),
+            Cursor::EndAligned(bytepos) => {
+                let textlen = resource.text().len();
+                let abs = textlen
+                    .checked_sub(bytepos.unsigned_abs())
+                    .ok_or_else(|| format!("Invalid UTF-8 offset {}: out of bounds", cursor))?;
+                let charpos = resource.utf8byte_to_charpos(abs).map_err(error)?;
+                Ok(Cursor::EndAligned(
+                    -((resource.textlen() - charpos) as isize),
+                ))
+            }
+        }
+    };
+    Ok(Offset::new(convert(&offset.begin)?, convert(&offset.end)?))
+}
+
+pub fn parse_column(
+    column: &str,
+    default_set: Option<&str>,
+    setdelimiter: &str,
+) -> Result<Column, String> {
+    let result = Column::parse(column, setdelimiter)
+        .map_err(|err| format!("Unable to parse provided columns: {}", err));
+    match (result.is_err(), default_set) {
+        (true, Some(default_set)) => Ok(Column::Custom {
+            set: default_set.to_string(),
+            key: column.to_string(),
+        }),
+        _ => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stam::{Config, SelectorBuilder, TextResource};
+
+    fn tempfile(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("stamtools-tsv-{}", name));
+        std::fs::write(&path, content).expect("writing temporary file");
+        path.to_str().expect("valid path").to_string()
+    }
+
+    fn read_lines(dialect: Dialect, filename: &str) -> Vec<String> {
+        dialect
+            .read_lines(filename)
+            .unwrap()
+            .collect::<Result<Vec<_>, String>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn dialect_delimiter() {
+        assert_eq!(Dialect::new("tab", false).unwrap(), Dialect::default());
+        assert_eq!(
+            Dialect::new("\\t", true).unwrap(),
+            Dialect {
+                delimiter: b'\t',
+                quoted: true
+            }
+        );
+        //any other delimiter implies quoting
+        assert_eq!(
+            Dialect::new(";", false).unwrap(),
+            Dialect {
+                delimiter: b';',
+                quoted: true
+            }
+        );
+        assert!(Dialect::new(";;", false).is_err());
+        assert!(Dialect::new("§", false).is_err());
+    }
+
+    #[test]
+    fn dialect_format_row() {
+        let csv = Dialect::new(",", false).unwrap();
+        assert_eq!(
+            csv.format_row(&["a", "b,c", "say \"hi\"", "two\nlines"]),
+            "a,\"b,c\",\"say \"\"hi\"\"\",\"two\nlines\""
+        );
+        //unquoted TSV writes cells as they are
+        assert_eq!(Dialect::default().format_row(&["a", "b,c"]), "a\tb,c");
+    }
+
+    #[test]
+    fn dialect_read_quoted() {
+        let filename = tempfile(
+            "quoted.csv",
+            "\u{feff}Text,Note\n\"b,c\",\"two\nlines\"\n\"tab\there\",\"say \"\"hi\"\"\"\n",
+        );
+        assert_eq!(
+            read_lines(Dialect::new(",", false).unwrap(), &filename),
+            vec!["Text\tNote", "b,c\ttwo\\nlines", "tab\\there\tsay \"hi\""]
+        );
+    }
+
+    #[test]
+    fn dialect_read_blank_lines() {
+        //blank lines are kept in both modes, also with CRLF line endings
+        let content = "a,b\n\nc,d\n\n\ne,f\n";
+        let expected = vec!["a\tb", "", "c\td", "", "", "e\tf"];
+        for (name, content) in [
+            ("blank.csv", content.to_string()),
+            ("blankcrlf.csv", content.replace('\n', "\r\n")),
+        ] {
+            let filename = tempfile(name, &content);
+            assert_eq!(
+                read_lines(Dialect::new(",", false).unwrap(), &filename),
+                expected
+            );
+        }
+        let filename = tempfile("blank.tsv", "a\tb\n\nc\td\n");
+        assert_eq!(
+            read_lines(Dialect::default(), &filename),
+            vec!["a\tb", "", "c\td"]
+        );
+    }
+
+    #[test]
+    fn count_blank_lines() {
+        assert_eq!(blank_lines(b"a\n\n\nb\n", 2), 2);
+        assert_eq!(blank_lines(b"a\nb\n", 2), 0);
+        //the second half of a CRLF terminator is not a blank line
+        assert_eq!(blank_lines(b"a\r\n\r\nb\r\n", 2), 1);
+        assert_eq!(blank_lines(b"", 0), 0);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(unescape("a\\tb\\nc"), "a\tb\nc");
+        assert_eq!(unescape("back\\\\slash\\x\\"), "back\\\\slash\\x\\");
+    }
+
+    #[test]
+    fn columns() {
+        let columns =
+            parse_columns(&["Type", "begin", "EndOffset", "pos/tag", "Text"], "/").unwrap();
+        assert_eq!(
+            columns.0,
+            vec![
+                Column::Type,
+                Column::BeginOffset,
+                Column::EndOffset,
+                Column::Custom {
+                    set: "pos".to_string(),
+                    key: "tag".to_string()
+                },
+                Column::Text
+            ]
+        );
+        assert!(parse_columns(&["Bogus"], "/").is_err());
+        //the set delimiter is configurable, the last occurrence separates the key
+        let columns = parse_columns(&["a::b::c"], "::").unwrap();
+        assert_eq!(
+            columns.0,
+            vec![Column::Custom {
+                set: "a::b".to_string(),
+                key: "c".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn columns_utf8() {
+        let columns = parse_columns(&["Text", "BeginOffset", "EndOffset"], "/").unwrap();
+        let columns = columns.with_utf8_offsets(true);
+        assert_eq!(
+            columns.0,
+            vec![Column::Text, Column::BeginUtf8Offset, Column::EndUtf8Offset]
+        );
+        //the header of an export can be imported again
+        let header: Vec<String> = columns.0.iter().map(|column| column.to_string()).collect();
+        let header: Vec<&str> = header.iter().map(|column| column.as_str()).collect();
+        let columns = parse_columns(&header, "/").unwrap();
+        assert_eq!(
+            columns.without_utf8_offsets(true).unwrap().0,
+            vec![Column::Text, Column::BeginOffset, Column::EndOffset]
+        );
+        let columns = parse_columns(&header, "/").unwrap();
+        let err = columns.without_utf8_offsets(false).unwrap_err();
+        assert!(err.contains("BeginUtf8Offset"), "{}", err);
+    }
+
+    #[test]
+    fn join_spec() {
+        assert_eq!(
+            parse_join_spec("lemmas.tsv"),
+            ("lemmas.tsv".to_string(), "Id")
+        );
+        assert_eq!(
+            parse_join_spec("lemmas.tsv:Token"),
+            ("lemmas.tsv".to_string(), "Token")
+        );
+        //the column may be a URI
+        assert_eq!(
+            parse_join_spec("lemmas.tsv:http://example.org/id"),
+            ("lemmas.tsv".to_string(), "http://example.org/id")
+        );
+        assert_eq!(
+            parse_join_spec("C\\:/lemmas.tsv:Id"),
+            ("C:/lemmas.tsv".to_string(), "Id")
+        );
+        assert_eq!(
+            parse_join_spec("lemmas.tsv:"),
+            ("lemmas.tsv".to_string(), "Id")
+        );
+    }
+
+    #[test]
+    fn join_comments() {
+        let filename = tempfile(
+            "join.tsv",
+            "Id\tx/lemma\n# a comment\tx\nA1\tfox\n#A2\tjump\n",
+        )
+        .replace(':', "\\:");
+        let mut joins = JoinData::default();
+        joins.load(&filename, &ImportJob::new()).unwrap();
+        assert_eq!(joins.take_raw("A1").len(), 1);
+        assert!(joins.is_empty());
+        //without comments, the lines starting with # are IDs too
+        let mut joins = JoinData::default();
+        joins
+            .load(&filename, &ImportJob::new().with_comments(false))
+            .unwrap();
+        assert_eq!(joins.take_raw("#A2")[0].2, "jump");
+        assert_eq!(joins.take_raw("# a comment")[0].2, "x");
+    }
+
+    #[test]
+    fn join_existing_report() {
+        let mut store = AnnotationStore::new();
+        store
+            .insert(TextResource::from_string(
+                "doc".to_string(),
+                "Hello world".to_string(),
+                Config::default(),
+            ))
+            .unwrap();
+        store
+            .annotate(
+                AnnotationBuilder::new()
+                    .with_id("A1".to_string())
+                    .with_target(SelectorBuilder::TextSelector(
+                        Item::IdRef("doc"),
+                        Offset::simple(0, 5),
+                    ))
+                    .with_data_builder(
+                        AnnotationDataBuilder::new()
+                            .with_annotationset(Item::IdRef("x"))
+                            .with_key(Item::IdRef("pos"))
+                            .with_value("interjection".into()),
+                    ),
+            )
+            .unwrap();
+        let filename =
+            tempfile("joinexisting.tsv", "Id\tx/lemma\nA1\thello\nA2\tworld\n").replace(':', "\\:");
+        let mut joins = JoinData::default();
+        joins.load(&filename, &ImportJob::new()).unwrap();
+        let (newstore, report) = join_existing(&store, &mut joins).unwrap();
+        assert_eq!(report.annotations, 1);
+        assert!(report.setfiles.is_empty());
+        let newstore = newstore.unwrap();
+        let annotation = newstore.annotation(&Item::IdRef("A1")).unwrap();
+        assert!(annotation.test_data(
+            Some(Item::IdRef("x")),
+            Some(Item::IdRef("lemma")),
+            DataOperator::Equals("hello")
+        ));
+        //A2 may still be imported later
+        assert_eq!(joins.ids().collect::<Vec<_>>(), vec!["A2"]);
+    }
+}
@@ -0,0 +1,707 @@
+use clap::{Arg, ArgAction};
+use sha2::{Digest, Sha256};
+use stam::{
+    Annotation, AnnotationHandle, AnnotationStore, AssociatedFile, Configurable, DataValue, Item,
+    Storable, ToJson, WrappedItem,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use crate::filter::DataFilter;
+use crate::rewrite::StoreJson;
+
+pub fn validate_arguments<'a>() -> Vec<clap::Arg<'a>> {
+    vec![
+        Arg::with_name("schema")
+            .long("schema")
+            .help("TSV file describing constraints on the annotation data, each annotation is checked against it.")
+            .long_help(
+                "TSV file describing constraints on the annotation data, each annotation is checked against it
+and every violation is reported. Each line describes a key and has the following columns:
+
+1. The ID of the annotation data set
+2. The ID of the data key
+3. The type of the value: String, Int, Float, Bool, List or Null. Use * (or leave empty) to allow any type.
+4. (optional) The allowed values, separated by |. Leave empty (or use -) to allow any value.
+5. (optional) Other keys in the same set that are required whenever this key is used on an annotation,
+   separated by |.
+
+A set that is mentioned in the schema may only use the keys listed for it, sets not mentioned are not checked.
+Lines starting with # are ignored.",
+            )
+            .takes_value(true),
+        Arg::with_name("disallow-overlap")
+            .long("disallow-overlap")
+            .help("Report annotations with this data key (set/key, same syntax as --filter) whose text overlaps or nests with that of another such annotation. This option may be specified multiple times.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("allow-nesting")
+            .long("allow-nesting")
+            .help("Report annotations with this data key (set/key, same syntax as --filter) whose text partially overlaps that of another such annotation; one being contained in the other is allowed. This option may be specified multiple times.")
+            .takes_value(true)
+            .action(ArgAction::Append),
+        Arg::with_name("setdelimiter")
+            .long("setdelimiter")
+            .env("STAM_SETDELIMITER")
+            .help("The delimiter between the annotation set and the key in --disallow-overlap and --allow-nesting")
+            .takes_value(true)
+            .default_value("/"),
+        Arg::with_name("delete-overlap")
+            .long("delete-overlap")
+            .help("Delete the lower-priority annotations reported by --disallow-overlap or --allow-nesting (along with any annotations pointing to them), and save the annotation store")
+            .required(false),
+        Arg::with_name("overlap-priority")
+            .long("overlap-priority")
+            .help("Which of two conflicting annotations to keep with --delete-overlap: first (the one added to the store first) or longest (the one covering the most text)")
+            .takes_value(true)
+            .possible_values(["first", "longest"])
+            .default_value("first"),
+        Arg::with_name("make-manifest")
+            .long("make-manifest")
+            .help("Write a manifest with the SHA-256 checksums of the annotation store and all files it includes (standoff annotation sets and text resources) to this file (- for standard output). Paths are relative to the directory of the annotation store.")
+            .takes_value(true)
+            .conflicts_with("verify-manifest"),
+        Arg::with_name("verify-manifest")
+            .long("verify-manifest")
+            .help("Check that all files listed in this manifest (as written by --make-manifest) are present and unmodified, relative to the directory of the annotation store. The store itself is not loaded or validated otherwise, so this can not be combined with --schema, --disallow-overlap or --allow-nesting.")
+            .takes_value(true)
+            .conflicts_with_all(&["schema", "disallow-overlap", "allow-nesting", "delete-overlap"]),
+    ]
+}
+
+pub fn validate(store: &AnnotationStore, verbose: bool) {
+    let result = store.to_json_string(&store.config().clone().with_use_include(false));
+    match result {
+        Ok(result) => {
+            if verbose {
+                println!("{}", result)
+            }
+        }
+        Err(err) => {
+            eprintln!("Error during serialization: {}", err);
+            exit(1);
+        }
+    }
+}
+
+/// Constraints on the data of annotations, per set and key
+pub struct Schema {
+    keys: BTreeMap<(String, String), KeyConstraint>,
+    sets: BTreeSet<String>,
+}
+
+struct KeyConstraint {
+    /// Expected type of the value, or None for any type
+    valuetype: Option<String>,
+    /// Closed vocabulary, or None if any value is allowed
+    values: Option<BTreeSet<String>>,
+    /// Other keys (in the same set) that must co-occur on the annotation
+    requires: Vec<String>,
+}
+
+const VALUETYPES: [&str; 6] = ["String", "Int", "Float", "Bool", "List", "Null"];
+
+impl Schema {
+    /// Loads a schema from a TSV file
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let f = File::open(filename)
+            .map_err(|e| format!("Error opening schema {}: {}", filename, e))?;
+        let mut schema = Self {
+            keys: BTreeMap::new(),
+            sets: BTreeSet::new(),
+        };
+        for (i, line) in BufReader::new(f).lines().enumerate() {
+            let line = line.map_err(|e| format!("Error reading schema {}: {}", filename, e))?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 3 || fields.len() > 5 {
+                return Err(format!(
+                    "Error parsing schema {} line {}: Expected 3 to 5 columns, got {}",
+                    filename,
+                    i + 1,
+                    fields.len()
+                ));
+            }
+            let valuetype = match fields[2] {
+                "" | "*" => None,
+                valuetype if VALUETYPES.contains(&valuetype) => Some(valuetype.to_string()),
+                valuetype => {
+                    return Err(format!(
+                        "Error in schema {} line {}: Unknown type '{}', expected one of {}",
+                        filename,
+                        i + 1,
+                        valuetype,
+                        VALUETYPES.join(", ")
+                    ))
+                }
+            };
+            let values = match fields.get(3) {
+                Some(&"") | Some(&"-") | None => None,
+                Some(values) => Some(values.split('|').map(|s| s.to_string()).collect()),
+            };
+            let requires = match fields.get(4) {
+                Some(&"") | Some(&"-") | None => Vec::new(),
+                Some(keys) => keys.split('|').map(|s| s.to_string()).collect(),
+            };
+            schema.sets.insert(fields[0].to_string());
+            schema.keys.insert(
+                (fields[0].to_string(), fields[1].to_string()),
+                KeyConstraint {
+                    valuetype,
+                    values,
+                    requires,
+                },
+            );
+        }
+        Ok(schema)
+    }
+
+    /// Checks the data of an annotation against the schema, returns a description of each violation
+    pub fn check(&self, annotation: &WrappedItem<Annotation>) -> Vec<String> {
+        let mut violations = Vec::new();
+        let mut present: BTreeSet<(String, String)> = BTreeSet::new();
+        for data in annotation.data() {
+            present.insert((
+                data.set().id().unwrap_or("(none)").to_string(),
+                data.key().id().unwrap_or("(none)").to_string(),
+            ));
+        }
+        for data in annotation.data() {
+            let set_id = data.set().id().unwrap_or("(none)").to_string();
+            let key_id = data.key().id().unwrap_or("(none)").to_string();
+            if !self.sets.contains(&set_id) {
+                continue;
+            }
+            let constraint = match self.keys.get(&(set_id.clone(), key_id.clone())) {
+                Some(constraint) => constraint,
+                None => {
+                    violations.push(format!(
+                        "key '{}' is not allowed in set '{}'",
+                        key_id, set_id
+                    ));
+                    continue;
+                }
+            };
+            if let Some(valuetype) = constraint.valuetype.as_ref() {
+                let actual = match data.value() {
+                    DataValue::String(_) => "String",
+                    DataValue::Int(_) => "Int",
+                    DataValue::Float(_) => "Float",
+                    DataValue::Bool(_) => "Bool",
+                    DataValue::List(_) => "List",
+                    DataValue::Null => "Null",
+                };
+                if actual != valuetype {
+                    violations.push(format!(
+                        "{}/{} has a value of type {}, expected {}",
+                        set_id, key_id, actual, valuetype
+                    ));
+                }
+            }
+            if let Some(values) = constraint.values.as_ref() {
+                let value = data.value().to_string();
+                if !values.contains(&value) {
+                    violations.push(format!(
+                        "{}/{} has value '{}', which is not in the allowed values",
+                        set_id, key_id, value
+                    ));
+                }
+            }
+            for required in constraint.requires.iter() {
+                if !present.contains(&(set_id.clone(), required.clone())) {
+                    violations.push(format!(
+                        "{}/{} requires {}/{}, which is missing",
+                        set_id, key_id, set_id, required
+                    ));
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// Checks all annotations against a schema and reports the violations per annotation on standard error.
+/// Returns the number of annotations with violations.
+pub fn validate_schema(store: &AnnotationStore, schema: &Schema) -> usize {
+    let mut invalid = 0;
+    for annotation in store.annotations() {
+        let violations = schema.check(&annotation);
+        if !violations.is_empty() {
+            invalid += 1;
+            for violation in violations {
+                eprintln!(
+                    "Annotation {}: {}",
+                    annotation.id().unwrap_or("(no id)"),
+                    violation
+                );
+            }
+        }
+    }
+    invalid
+}
+
+/// How annotations with the same data key may overlap
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverlapPolicy {
+    /// Annotations may not share any text
+    DisallowOverlap,
+    /// Annotations may be contained in one another, but may not partially overlap
+    AllowNesting,
+    /// Anything goes
+    AllowAll,
+}
+
+impl TryFrom<&str> for OverlapPolicy {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "disallow-overlap" => Ok(Self::DisallowOverlap),
+            "allow-nesting" => Ok(Self::AllowNesting),
+            "allow-all" => Ok(Self::AllowAll),
+            _ => Err(format!("Invalid overlap policy: {}", value)),
+        }
+    }
+}
+
+/// Which of two conflicting annotations takes priority
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverlapPriority {
+    First,
+    Longest,
+}
+
+impl TryFrom<&str> for OverlapPriority {
+    type Error = String;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "first" => Ok(Self::First),
+            "longest" => Ok(Self::Longest),
+            _ => Err(format!("Invalid overlap priority: {}", value)),
+        }
+    }
+}
+
+/// Two annotations with the same data key whose text overlaps in a way the policy does not allow.
+/// The first annotation has priority over the second.
+pub struct OverlapConflict {
+    pub filter: DataFilter,
+    pub keep: AnnotationHandle,
+    pub discard: AnnotationHandle,
+}
+
+/// Finds conflicting annotations for each of the filters according to its policy. Only annotations that match the same filter
+/// are compared with each other. The conflicts are sorted so those of the highest priority annotations come first.
+pub fn find_overlap(
+    store: &AnnotationStore,
+    policies: &[(DataFilter, OverlapPolicy)],
+    priority: OverlapPriority,
+) -> Vec<OverlapConflict> {
+    let mut conflicts = Vec::new();
+    for (filter, policy) in policies {
+        if *policy == OverlapPolicy::AllowAll {
+            continue;
+        }
+        //spans per resource: begin, end, annotation handle; and the total length and rank of each annotation
+        let mut spans: HashMap<_, Vec<(usize, usize, AnnotationHandle)>> = HashMap::new();
+        let mut ranks: HashMap<AnnotationHandle, (usize, usize)> = HashMap::new();
+        for (rank, annotation) in store
+            .annotations()
+            .filter(|annotation| filter.test(annotation))
+            .enumerate()
+        {
+            let handle = annotation.handle().expect("annotation must have handle");
+            let mut length = 0;
+            for textselection in annotation.textselections() {
+                length += textselection.end() - textselection.begin();
+                spans
+                    .entry(textselection.resource().handle())
+                    .or_default()
+                    .push((textselection.begin(), textselection.end(), handle));
+            }
+            ranks.insert(handle, (rank, length));
+        }
+        let mut pairs: BTreeSet<(AnnotationHandle, AnnotationHandle)> = BTreeSet::new();
+        for spans in spans.values_mut() {
+            spans.sort_unstable();
+            for (i, (begin, end, a)) in spans.iter().enumerate() {
+                for (otherbegin, otherend, b) in spans[i + 1..].iter() {
+                    if otherbegin >= end {
+                        break;
+                    }
+                    if a == b {
+                        continue;
+                    }
+                    //spans are sorted by begin, so the other span is either nested or crosses the end
+                    let nested = otherend <= end || (otherbegin == begin && otherend >= end);
+                    if *policy == OverlapPolicy::DisallowOverlap || !nested {
+                        pairs.insert(if a < b { (*a, *b) } else { (*b, *a) });
+                    }
+                }
+            }
+        }
+        //lower sorts first, i.e. has higher priority
+        let priority_of = |handle: &AnnotationHandle| {
+            let (rank, length) = ranks[handle];
+            match priority {
+                OverlapPriority::First => (0, rank),
+                OverlapPriority::Longest => (usize::MAX - length, rank),
+            }
+        };
+        let mut filterconflicts: Vec<OverlapConflict> = pairs
+            .into_iter()
+            .map(|(a, b)| {
+                let (keep, discard) = if priority_of(&a) < priority_of(&b) {
+                    (a, b)
+                } else {
+                    (b, a)
+                };
+                OverlapConflict {
+                    filter: filter.clone(),
+                    keep,
+                    discard,
+                }
+            })
+            .collect();
+        filterconflicts.sort_by_key(|conflict| priority_of(&conflict.keep));
+        conflicts.extend(filterconflicts);
+    }
+    conflicts
+}
+
+/// Reports the conflicts on standard error
+pub fn report_overlap(store: &AnnotationStore, conflicts: &[OverlapConflict]) {
+    let describe = |handle: AnnotationHandle| {
+        let annotation = store
+            .annotation(&Item::Handle(handle))
+            .expect("annotation must exist");
+        let offsets: Vec<String> = annotation
+            .textselections()
+            .map(|textselection| format!("{}-{}", textselection.begin(), textselection.end()))
+            .collect();
+        let text: Vec<&str> = annotation.text().collect();
+        format!(
+            "{} ({}: \"{}\")",
+            annotation.id().unwrap_or("(no id)"),
+            offsets.join(","),
+            text.join(" ")
+        )
+    };
+    for conflict in conflicts {
+        eprintln!(
+            "Overlap in {}/{}: {} conflicts with {}",
+            conflict.filter.set,
+            conflict.filter.key,
+            describe(conflict.discard),
+            describe(conflict.keep)
+        );
+    }
+}
+
+/// Deletes the lower-priority annotation of each conflict, unless its counterpart was already deleted itself.
+/// The conflicts are processed in order, as returned by [`find_overlap()`] (highest priority first).
+/// Annotations pointing to deleted annotations are deleted as well. Returns the new store and the IDs of
+/// the deleted annotations.
+pub fn delete_overlap(
+    store: &AnnotationStore,
+    conflicts: &[OverlapConflict],
+) -> Result<(AnnotationStore, Vec<String>), String> {
+    let mut deleted: HashSet<AnnotationHandle> = HashSet::new();
+    let mut ids = Vec::new();
+    for conflict in conflicts {
+        if deleted.contains(&conflict.keep) || deleted.contains(&conflict.discard) {
+            continue;
+        }
+        deleted.insert(conflict.discard);
+        let annotation = store
+            .annotation(&Item::Handle(conflict.discard))
+            .expect("annotation must exist");
+        match annotation.id() {
+            Some(id) => ids.push(id.to_string()),
+            None => eprintln!("Warning: can not delete an annotation without an ID"),
+        }
+    }
+    let mut storejson = StoreJson::from_store(store)?;
+    let removed = storejson.remove_annotations(&ids);
+    Ok((storejson.into_store(store)?, removed))
+}
+
+/// A file listed in a manifest that did not pass verification
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ManifestProblem {
+    Missing,
+    Modified,
+}
+
+impl ManifestProblem {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Missing => "missing",
+            Self::Modified => "modified",
+        }
+    }
+}
+
+/// Computes the SHA-256 checksum of a file, as a hexadecimal string
+fn checksum(path: &Path) -> Result<String, String> {
+    let mut file =
+        File::open(path).map_err(|e| format!("Error opening {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// The directory that paths in a manifest are relative to: the directory of the annotation store
+pub fn manifest_basedir(storefile: &str) -> PathBuf {
+    let mut basedir: PathBuf = storefile.into();
+    basedir.pop();
+    basedir
+}
+
+/// Returns the files that make up the project: the annotation store files and all existing
+/// files for resources and sets that were loaded via `@include`. Included files are looked up
+/// the way the STAM library does, first relative to `basedir` and then to the current directory.
+pub fn project_files(
+    store: &AnnotationStore,
+    storefiles: &[&str],
+    basedir: &Path,
+) -> Result<Vec<PathBuf>, String> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for storefile in storefiles {
+        if *storefile == "-" {
+            return Err(
+                "Unable to make a manifest for a store read from standard input".to_string(),
+            );
+        }
+        files.push(storefile.into());
+    }
+    let included = store
+        .resources()
+        .filter_map(|resource| resource.filename().map(|x| x.to_string()))
+        .chain(
+            store
+                .annotationsets()
+                .filter_map(|annotationset| annotationset.filename().map(|x| x.to_string())),
+        );
+    for filename in included {
+        let path = match basedir.join(&filename) {
+            path if path.is_file() => path,
+            _ => PathBuf::from(&filename),
+        };
+        if path.is_file() && !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Writes a manifest with one line per project file (see [`project_files()`]): the SHA-256 checksum,
+/// two spaces, and the path relative to `basedir` (the format of `sha256sum`). Returns the number of files.
+pub fn make_manifest(
+    writer: &mut impl Write,
+    store: &AnnotationStore,
+    storefiles: &[&str],
+    basedir: &Path,
+) -> Result<usize, String> {
+    let files = project_files(store, storefiles, basedir)?;
+    for path in files.iter() {
+        let relpath = path.strip_prefix(basedir).unwrap_or(path);
+        writeln!(writer, "{}  {}", checksum(path)?, relpath.display())
+            .map_err(|e| format!("Error writing manifest: {}", e))?;
+    }
+    Ok(files.len())
+}
+
+/// Checks all files listed in a manifest (as written by [`make_manifest()`]) against the files on disk,
+/// relative paths are resolved against `basedir`. Returns the number of files checked and the files
+/// that are missing or modified.
+pub fn verify_manifest(
+    manifest: &str,
+    basedir: &Path,
+) -> Result<(usize, Vec<(String, ManifestProblem)>), String> {
+    let f =
+        File::open(manifest).map_err(|e| format!("Error opening manifest {}: {}", manifest, e))?;
+    let mut count = 0;
+    let mut problems = Vec::new();
+    for (i, line) in BufReader::new(f).lines().enumerate() {
+        let line = line.map_err(|e| format!("Error reading manifest {}: {}", manifest, e))?;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (expected, filename) = line.split_once("  ").ok_or_else(|| {
+            format!(
+                "Error parsing manifest {} line {}: Expected a checksum and a path",
+                manifest,
+                i + 1
+            )
+        })?;
+        count += 1;
+        let path = basedir.join(filename);
+        if !path.is_file() {
+            problems.push((filename.to_string(), ManifestProblem::Missing));
+        } else if checksum(&path)? != expected {
+            problems.push((filename.to_string(), ManifestProblem::Modified));
+        }
+    }
+    Ok((count, problems))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stam::{
+        AnnotationBuilder, AnnotationDataBuilder, Config, Offset, SelectorBuilder, StoreFor,
+        TextResource,
+    };
+
+    /// Builds a store with a single resource and an annotation with key `test/entity` for each span
+    fn store_with_spans(spans: &[(&str, usize, usize)]) -> AnnotationStore {
+        let mut store = AnnotationStore::new();
+        store
+            .insert(TextResource::from_string(
+                "doc".to_string(),
+                "The University of Amsterdam is in Amsterdam".to_string(),
+                Config::default(),
+            ))
+            .unwrap();
+        for (id, begin, end) in spans {
+            store
+                .annotate(
+                    AnnotationBuilder::new()
+                        .with_id(id.to_string())
+                        .with_target(SelectorBuilder::TextSelector(
+                            Item::IdRef("doc"),
+                            Offset::simple(*begin, *end),
+                        ))
+                        .with_data_builder(
+                            AnnotationDataBuilder::new()
+                                .with_annotationset(Item::IdRef("test"))
+                                .with_key(Item::IdRef("entity"))
+                                .with_value("yes".into()),
+                        ),
+                )
+                .unwrap();
+        }
+        store
+    }
+
+    /// Returns the IDs of the kept and discarded annotation of each conflict
+    fn conflicts(
+        store: &AnnotationStore,
+        policy: OverlapPolicy,
+        priority: OverlapPriority,
+    ) -> Vec<(String, String)> {
+        let filter = DataFilter::parse("test/entity", "/").unwrap();
+        let id = |handle: AnnotationHandle| {
+            store
+                .annotation(&Item::Handle(handle))
+                .and_then(|annotation| annotation.id().map(|id| id.to_string()))
+                .unwrap()
+        };
+        find_overlap(store, &[(filter, policy)], priority)
+            .into_iter()
+            .map(|conflict| (id(conflict.keep), id(conflict.discard)))
+            .collect()
+    }
+
+    fn pair(keep: &str, discard: &str) -> (String, String) {
+        (keep.to_string(), discard.to_string())
+    }
+
+    #[test]
+    fn overlap_disallowed() {
+        //University of Amsterdam, Amsterdam (nested), Amsterdam (separate)
+        let store = store_with_spans(&[("A1", 4, 27), ("A2", 18, 27), ("A3", 34, 43)]);
+        assert_eq!(
+            conflicts(
+                &store,
+                OverlapPolicy::DisallowOverlap,
+                OverlapPriority::First
+            ),
+            vec![pair("A1", "A2")]
+        );
+        assert!(conflicts(&store, OverlapPolicy::AllowNesting, OverlapPriority::First).is_empty());
+        assert!(conflicts(&store, OverlapPolicy::AllowAll, OverlapPriority::First).is_empty());
+    }
+
+    #[test]
+    fn overlap_crossing() {
+        //"University of" and "of Amsterdam" cross each other, "University of Amsterdam" contains both
+        let store = store_with_spans(&[("A1", 4, 17), ("A2", 15, 27), ("A3", 4, 27)]);
+        assert_eq!(
+            conflicts(&store, OverlapPolicy::AllowNesting, OverlapPriority::First),
+            vec![pair("A1", "A2")]
+        );
+    }
+
+    #[test]
+    fn overlap_priority() {
+        let store = store_with_spans(&[("A1", 18, 27), ("A2", 4, 27)]);
+        assert_eq!(
+            conflicts(
+                &store,
+                OverlapPolicy::DisallowOverlap,
+                OverlapPriority::First
+            ),
+            vec![pair("A1", "A2")]
+        );
+        assert_eq!(
+            conflicts(
+                &store,
+                OverlapPolicy::DisallowOverlap,
+                OverlapPriority::Longest
+            ),
+            vec![pair("A2", "A1")]
+        );
+    }
+
+    #[test]
+    fn overlap_adjacent() {
+        //spans that merely touch do not overlap
+        let store = store_with_spans(&[("A1", 0, 3), ("A2", 3, 14)]);
+        assert!(conflicts(
+            &store,
+            OverlapPolicy::DisallowOverlap,
+            OverlapPriority::First
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn overlap_delete() {
+        let store = store_with_spans(&[("A1", 4, 27), ("A2", 18, 27), ("A3", 34, 43)]);
+        let filter = DataFilter::parse("test/entity", "/").unwrap();
+        let found = find_overlap(
+            &store,
+            &[(filter, OverlapPolicy::DisallowOverlap)],
+            OverlapPriority::First,
+        );
+        let (store, deleted) = delete_overlap(&store, &found).unwrap();
+        assert_eq!(deleted, vec!["A2".to_string()]);
+        assert!(store.annotation(&Item::IdRef("A2")).is_none());
+        assert!(store.annotation(&Item::IdRef("A1")).is_some());
+        assert!(store.annotation(&Item::IdRef("A3")).is_some());
+    }
+
+    #[test]
+    fn overlap_options() {
+        assert_eq!(
+            OverlapPolicy::try_from("allow-nesting"),
+            Ok(OverlapPolicy::AllowNesting)
+        );
+        assert!(OverlapPolicy::try_from("nesting").is_err());
+        assert_eq!(
+            OverlapPriority::try_from("longest"),
+            Ok(OverlapPriority::Longest)
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+use stam::AnnotationStore;
+
+use crate::pipeline::ExportJob;
+use crate::tsv::{to_table, Row, Type};
+
+/// Characters that are not allowed in worksheet names
+const INVALID_SHEET_CHARS: &[char] = &['[', ']', ':', '*', '?', '/', '\\'];
+
+/// Maximum number of characters a spreadsheet cell can hold
+const MAX_CELL_CHARS: usize = 32767;
+
+/// Export to a spreadsheet (XLSX). Like [`crate::tsv::to_tsv()`], but writes to `filename`. Rows are grouped in one
+/// sheet per type, or per resource if `sheet_per_resource` is set.
+pub fn to_xlsx(
+    store: &AnnotationStore,
+    job: &ExportJob,
+    filename: &str,
+    sheet_per_resource: bool,
+) -> Result<(), String> {
+    let (header, rows) = to_table(store, job)?;
+
+    let sheets = sheets_by_type_or_resource(rows.iter(), job.tp, sheet_per_resource);
+    write_xlsx(&header, &sheets, filename)
+}
+
+/// Groups rows into sheets, one per type or one per resource, in order of first appearance.
+/// There is always at least one (possibly empty) sheet.
+pub fn sheets_by_type_or_resource<'a>(
+    rows: impl Iterator<Item = &'a Row>,
+    tp: Type,
+    sheet_per_resource: bool,
+) -> Vec<(String, Vec<&'a Row>)> {
+    let mut sheets: Vec<(String, Vec<&Row>)> = Vec::new();
+    for row in rows {
+        let sheetname = if sheet_per_resource {
+            row.resource.clone().unwrap_or("No resource".to_string())
+        } else {
+            row.tp.to_string()
+        };
+        if let Some((_, sheetrows)) = sheets.iter_mut().find(|(name, _)| *name == sheetname) {
+            sheetrows.push(row);
+        } else {
+            sheets.push((sheetname, vec![row]));
+        }
+    }
+    if sheets.is_empty() {
+        sheets.push((tp.to_string(), Vec::new()));
+    }
+    sheets
+}
+
+/// Writes a spreadsheet (XLSX) with the given sheets (name and rows), each sheet gets the same header
+pub fn write_xlsx(
+    header: &[String],
+    sheets: &[(String, Vec<&Row>)],
+    filename: &str,
+) -> Result<(), String> {
+    let mut workbook = Workbook::new();
+    let headerformat = Format::new().set_bold();
+    let mut sheetnames: Vec<String> = Vec::new();
+    for (sheetname, sheetrows) in sheets {
+        let mut worksheet = Worksheet::new();
+        worksheet
+            .set_name(sheet_name(sheetname, &sheetnames))
+            .map_err(|err| format!("Invalid sheet name {}: {}", sheetname, err))?;
+        sheetnames.push(worksheet.name());
+        for (colnr, column) in header.iter().enumerate() {
+            worksheet
+                .write_string_with_format(0, colnr as u16, column, &headerformat)
+                .map_err(|err| format!("{}", err))?;
+        }
+        worksheet
+            .set_freeze_panes(1, 0)
+            .map_err(|err| format!("{}", err))?;
+        for (rownr, row) in sheetrows.iter().enumerate() {
+            for (colnr, cell) in row.cells.iter().enumerate() {
+                let cell = truncate_cell(cell);
+                if cell.len() < row.cells[colnr].len() {
+                    eprintln!(
+                        "Warning: cell in row {} column {} of sheet {} exceeds {} characters and was truncated",
+                        rownr + 2,
+                        colnr + 1,
+                        worksheet.name(),
+                        MAX_CELL_CHARS
+                    );
+                }
+                worksheet
+                    .write_string(rownr as u32 + 1, colnr as u16, cell)
+                    .map_err(|err| format!("{}", err))?;
+            }
+        }
+        worksheet.autofit();
+        workbook.push_worksheet(worksheet);
+    }
+    workbook
+        .save(filename)
+        .map_err(|err| format!("Failed to write {}: {}", filename, err))
+}
+
+/// Truncates a cell value to the maximum number of characters a spreadsheet cell can hold
+fn truncate_cell(cell: &str) -> &str {
+    match cell.char_indices().nth(MAX_CELL_CHARS) {
+        Some((bytepos, _)) => &cell[..bytepos],
+        None => cell,
+    }
+}
+
+/// Derive a valid and unique worksheet name (max 31 characters, no special characters)
+fn sheet_name(name: &str, existing: &[String]) -> String {
+    let base: String = name
+        .chars()
+        .map(|c| {
+            if INVALID_SHEET_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(31)
+        .collect();
+    let mut candidate = base.clone();
+    let mut i = 1;
+    while existing
+        .iter()
+        .any(|x| x.to_lowercase() == candidate.to_lowercase())
+    {
+        i += 1;
+        let suffix = format!(" ({})", i);
+        candidate = format!(
+            "{}{}",
+            base.chars()
+                .take(31 - suffix.chars().count())
+                .collect::<String>(),
+            suffix
+        );
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate() {
+        assert_eq!(truncate_cell("short"), "short");
+        let long = "é".repeat(MAX_CELL_CHARS + 10);
+        let truncated = truncate_cell(&long);
+        assert_eq!(truncated.chars().count(), MAX_CELL_CHARS);
+        assert!(long.starts_with(truncated));
+    }
+
+    #[test]
+    fn sheet_names() {
+        assert_eq!(sheet_name("Annotation", &[]), "Annotation");
+        assert_eq!(sheet_name("a/b:c?", &[]), "a_b_c_");
+        let long = "x".repeat(40);
+        assert_eq!(sheet_name(&long, &[]).chars().count(), 31);
+        //names must be unique, case insensitively
+        let existing = vec!["hello.txt".to_string()];
+        assert_eq!(sheet_name("Hello.txt", &existing), "Hello.txt (2)");
+        let existing = vec!["x".repeat(31)];
+        assert_eq!(
+            sheet_name(&long, &existing),
+            format!("{} (2)", "x".repeat(27))
+        );
+    }
+}